@@ -0,0 +1,360 @@
+//! Stand-in for the Google OAuth token endpoint and the FCM HTTP v1 API, to exercise
+//! the real FCM client in the integration tests without reaching Google.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Form, Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::signature::{RsaKeyPair, UnparsedPublicKey, RSA_PKCS1_2048_8192_SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::task::AbortHandle;
+
+/// Private key of the service account keys returned by `FakeFcmServer::service_account_key`.
+///
+/// Only valid for tests: the fake OAuth token endpoint only accepts JWTs signed with it.
+pub const TEST_PRIVATE_KEY: &str = include_str!("test_private_key.pem");
+
+/// Well formed private key whose JWTs are rejected by the fake OAuth token endpoint
+pub const UNTRUSTED_PRIVATE_KEY: &str = include_str!("untrusted_private_key.pem");
+
+/// Error codes returned by FCM, as documented in
+/// https://firebase.google.com/docs/reference/fcm/rest/v1/ErrorCode
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FcmErrorCode {
+    Unregistered,
+    InvalidArgument,
+    SenderIdMismatch,
+    QuotaExceeded,
+    Unavailable,
+    Internal,
+    ThirdPartyAuthError,
+}
+
+impl FcmErrorCode {
+    fn status(&self) -> (StatusCode, &'static str) {
+        match self {
+            FcmErrorCode::Unregistered => (StatusCode::NOT_FOUND, "NOT_FOUND"),
+            FcmErrorCode::InvalidArgument => (StatusCode::BAD_REQUEST, "INVALID_ARGUMENT"),
+            FcmErrorCode::SenderIdMismatch => (StatusCode::FORBIDDEN, "PERMISSION_DENIED"),
+            FcmErrorCode::QuotaExceeded => (StatusCode::TOO_MANY_REQUESTS, "RESOURCE_EXHAUSTED"),
+            FcmErrorCode::Unavailable => (StatusCode::SERVICE_UNAVAILABLE, "UNAVAILABLE"),
+            FcmErrorCode::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL"),
+            FcmErrorCode::ThirdPartyAuthError => (StatusCode::UNAUTHORIZED, "UNAUTHENTICATED"),
+        }
+    }
+
+    fn as_str(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value.as_str().map(String::from))
+            .unwrap_or_default()
+    }
+}
+
+/// A request received at `/v1/projects/{project}/messages:send`
+#[derive(Debug, Clone)]
+pub struct ReceivedMessage {
+    pub fcm_project_id: String,
+    pub validate_only: bool,
+    /// The `message` field of the request
+    pub message: Value,
+    /// The value of the `Authorization` header
+    pub authorization: Option<String>,
+}
+
+impl ReceivedMessage {
+    pub fn token(&self) -> Option<&str> {
+        self.message.get("token").and_then(|token| token.as_str())
+    }
+}
+
+#[derive(Default)]
+struct ServerState {
+    /// Audience that the JWTs must be issued for
+    token_uri: String,
+    rejected_assertions: usize,
+    received_messages: Vec<ReceivedMessage>,
+    issued_tokens: usize,
+    error: Option<FcmErrorCode>,
+    token_errors: HashMap<String, FcmErrorCode>,
+}
+
+/// Fake FCM server listening on localhost, stopped when dropped.
+pub struct FakeFcmServer {
+    address: SocketAddr,
+    state: Arc<Mutex<ServerState>>,
+    abort_handle: AbortHandle,
+}
+
+impl Drop for FakeFcmServer {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
+
+impl FakeFcmServer {
+    pub async fn start() -> Result<Self> {
+        let listener = tokio::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+        let address = listener.local_addr()?;
+
+        let state = Arc::new(Mutex::new(ServerState {
+            token_uri: format!("http://{address}/token"),
+            ..Default::default()
+        }));
+
+        let app = Router::new()
+            .route("/token", post(token))
+            .route("/v1/projects/:project/:method", post(send_message))
+            .with_state(state.clone());
+
+        let abort_handle = tokio::spawn(async move {
+            if let Err(err) = axum::serve(listener, app).await {
+                eprintln!("Fake FCM server stopped: {err:?}");
+            }
+        })
+        .abort_handle();
+
+        Ok(Self {
+            address,
+            state,
+            abort_handle,
+        })
+    }
+
+    /// Base URL of the fake FCM HTTP v1 API
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// URL of the fake OAuth token endpoint
+    pub fn token_uri(&self) -> String {
+        format!("http://{}/token", self.address)
+    }
+
+    /// Service account key, in the JSON format downloaded from the Firebase console,
+    /// whose `token_uri` points to this server
+    pub fn service_account_key(&self, fcm_project_id: &str) -> Value {
+        json!({
+            "type": "service_account",
+            "project_id": fcm_project_id,
+            "private_key_id": "fake-private-key-id",
+            "private_key": TEST_PRIVATE_KEY,
+            "client_email": format!("firebase-adminsdk@{fcm_project_id}.iam.gserviceaccount.com"),
+            "client_id": "1234567890",
+            "auth_uri": "https://accounts.google.com/o/oauth2/auth",
+            "token_uri": self.token_uri(),
+            "auth_provider_x509_cert_url": "https://www.googleapis.com/oauth2/v1/certs",
+            "client_x509_cert_url": "https://www.googleapis.com/robot/v1/metadata/x509/firebase-adminsdk",
+        })
+    }
+
+    /// Number of JWTs rejected by the OAuth token endpoint, e.g. because of an invalid signature
+    pub fn rejected_assertions(&self) -> usize {
+        self.state
+            .lock()
+            .map(|state| state.rejected_assertions)
+            .unwrap_or_default()
+    }
+
+    pub fn received_messages(&self) -> Vec<ReceivedMessage> {
+        self.state
+            .lock()
+            .map(|state| state.received_messages.clone())
+            .unwrap_or_default()
+    }
+
+    /// Number of access tokens issued by the OAuth token endpoint
+    pub fn issued_tokens(&self) -> usize {
+        self.state
+            .lock()
+            .map(|state| state.issued_tokens)
+            .unwrap_or_default()
+    }
+
+    /// Makes every following message fail with the given error, until `clear_errors` is called
+    pub fn fail_with(&self, error: FcmErrorCode) {
+        if let Ok(mut state) = self.state.lock() {
+            state.error = Some(error);
+        }
+    }
+
+    /// Makes the messages sent to the given token fail with the given error
+    pub fn fail_token_with(&self, token: impl Into<String>, error: FcmErrorCode) {
+        if let Ok(mut state) = self.state.lock() {
+            state.token_errors.insert(token.into(), error);
+        }
+    }
+
+    pub fn clear_errors(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.error = None;
+            state.token_errors.clear();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    assertion: String,
+}
+
+async fn token(
+    State(state): State<Arc<Mutex<ServerState>>>,
+    Form(request): Form<TokenRequest>,
+) -> (StatusCode, Json<Value>) {
+    let Ok(mut state) = state.lock() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({})));
+    };
+
+    let verified = if request.grant_type == "urn:ietf:params:oauth:grant-type:jwt-bearer" {
+        verify_assertion(&request.assertion, &state.token_uri)
+    } else {
+        Err(anyhow!("Unsupported grant type {}", request.grant_type))
+    };
+    if let Err(err) = verified {
+        state.rejected_assertions += 1;
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "invalid_grant",
+                "error_description": format!("{err:#}"),
+            })),
+        );
+    }
+
+    state.issued_tokens += 1;
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "access_token": format!("fake-access-token-{}", state.issued_tokens),
+            "token_type": "Bearer",
+            "expires_in": 3600,
+        })),
+    )
+}
+
+/// Checks that the JWT is signed with `TEST_PRIVATE_KEY`, issued for this token endpoint and not expired
+fn verify_assertion(assertion: &str, token_uri: &str) -> Result<()> {
+    let parts: Vec<&str> = assertion.split('.').collect();
+    let [header, claims, signature] = parts.as_slice() else {
+        return Err(anyhow!("Malformed JWT"));
+    };
+
+    let key_pair = RsaKeyPair::from_pkcs8(&pem_to_der(TEST_PRIVATE_KEY)?)
+        .map_err(|err| anyhow!("Invalid test private key: {err}"))?;
+    UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, key_pair.public().as_ref())
+        .verify(
+            format!("{header}.{claims}").as_bytes(),
+            &URL_SAFE_NO_PAD
+                .decode(signature)
+                .context("Malformed JWT signature")?,
+        )
+        .map_err(|_| anyhow!("Invalid JWT signature"))?;
+
+    let header: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header)?)?;
+    if header["alg"] != "RS256" {
+        return Err(anyhow!("Unsupported JWT algorithm {}", header["alg"]));
+    }
+    let claims: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims)?)?;
+    if claims["aud"] != token_uri {
+        return Err(anyhow!(
+            "JWT issued for another audience: {}",
+            claims["aud"]
+        ));
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    if claims["exp"].as_u64().unwrap_or_default() <= now {
+        return Err(anyhow!("Expired JWT"));
+    }
+
+    Ok(())
+}
+
+fn pem_to_der(pem: &str) -> Result<Vec<u8>> {
+    let base64_body: String = pem
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect();
+    Ok(base64::engine::general_purpose::STANDARD.decode(base64_body)?)
+}
+
+#[derive(Deserialize)]
+struct SendRequest {
+    message: Value,
+    #[serde(default)]
+    validate_only: bool,
+}
+
+async fn send_message(
+    State(state): State<Arc<Mutex<ServerState>>>,
+    Path((project, method)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(request): Json<SendRequest>,
+) -> (StatusCode, Json<Value>) {
+    if method != "messages:send" {
+        return (StatusCode::NOT_FOUND, Json(json!({})));
+    }
+
+    let received = ReceivedMessage {
+        fcm_project_id: project.clone(),
+        validate_only: request.validate_only,
+        message: request.message,
+        authorization: headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from),
+    };
+
+    let Ok(mut state) = state.lock() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({})));
+    };
+
+    let error = received
+        .token()
+        .and_then(|token| state.token_errors.get(token).cloned())
+        .or(state.error);
+    state.received_messages.push(received);
+
+    if let Some(error) = error {
+        let (status_code, status) = error.status();
+        return (
+            status_code,
+            Json(json!({
+                "error": {
+                    "code": status_code.as_u16(),
+                    "message": format!("Fake FCM error {}", error.as_str()),
+                    "status": status,
+                    "details": [{
+                        "@type": "type.googleapis.com/google.firebase.fcm.v1.FcmError",
+                        "errorCode": error.as_str(),
+                    }],
+                }
+            })),
+        );
+    }
+
+    let message_id = state.received_messages.len();
+    (
+        StatusCode::OK,
+        Json(json!({
+            "name": format!("projects/{project}/messages/{message_id}"),
+        })),
+    )
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, including the fields of the current span
+    Json,
+}
+
+/// Log levels for the noisy modules of holochain and its networking
+pub fn default_filters() -> BTreeMap<String, String> {
+    let mut filters = BTreeMap::new();
+    filters.insert(String::from("holochain_sqlite"), String::from("off"));
+    filters.insert(String::from("tracing::span"), String::from("off"));
+    filters.insert(String::from("iroh"), String::from("warn"));
+    filters.insert(String::from("kitsune2"), String::from("warn"));
+    filters
+}
+
+/// Installs the global tracing subscriber, which also collects the records from the `log` crate
+///
+/// `level` is the default log level, overridden by the `RUST_LOG` environment variable,
+/// and `filters` the log level for specific modules
+pub fn init_logging(
+    level: &str,
+    format: LogFormat,
+    filters: &BTreeMap<String, String>,
+) -> Result<()> {
+    let level = std::env::var("RUST_LOG").unwrap_or(level.to_string());
+    let mut filter = EnvFilter::try_new(&level)
+        .map_err(|err| anyhow!("Invalid log level \"{level}\": {err}"))?;
+    for (module, level) in filters {
+        filter = filter.add_directive(format!("{module}={level}").parse()?);
+    }
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stdout);
+    match format {
+        LogFormat::Text => builder.with_target(false).try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).try_init(),
+    }
+    .map_err(|err| anyhow!("Failed to initialize logging: {err}"))
+}
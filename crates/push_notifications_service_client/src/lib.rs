@@ -5,16 +5,70 @@ use fcm_v1::auth::ServiceAccountKey;
 use holochain_client::ZomeCallTarget;
 use holochain_runtime::*;
 use holochain_types::prelude::*;
+use push_notifications_types::{
+    GetNotificationTemplateInput, NotificationStyle, NotificationTemplate,
+    RequestTestPushNotificationInput, RotateServiceAccountKeyInput, ServiceAccountKeyHistoryItem,
+    TestPushNotificationResult, TestPushNotificationTarget,
+};
 use roles_types::Properties;
+use serde::{Deserialize, Serialize};
 use setup::setup;
 use std::{fs, path::PathBuf, time::Duration};
 use utils::with_retries;
 
+pub mod service_account_key_source;
 mod setup;
 mod utils;
 
 pub const SERVICES_ROLE_NAME: &'static str = "services";
 
+/// Maximum time to wait for a provider to report the result of a test push notification
+const TEST_NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Metadata of the service account key of an FCM project, without its private key
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FcmProjectInfo {
+    pub fcm_project_id: String,
+    pub client_email: String,
+    pub client_id: Option<String>,
+    pub private_key_id: Option<String>,
+    pub token_uri: String,
+}
+
+impl FcmProjectInfo {
+    fn new(fcm_project_id: String, key: push_notifications_types::ServiceAccountKey) -> Self {
+        Self {
+            fcm_project_id,
+            client_email: key.client_email,
+            client_id: key.client_id,
+            private_key_id: key.private_key_id,
+            token_uri: key.token_uri,
+        }
+    }
+}
+
+/// Clone request for the service providers DNA, with its network seed only if it was asked for:
+/// anyone who knows the seed can join the network of the providers
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CloneRequestInfo {
+    pub clone_request_hash: EntryHashB64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_seed: Option<String>,
+}
+
+impl CloneRequestInfo {
+    pub fn new(
+        clone_request_hash: EntryHash,
+        clone_request: CloneRequest,
+        show_seed: bool,
+    ) -> Self {
+        Self {
+            clone_request_hash: clone_request_hash.into(),
+            network_seed: show_seed.then_some(clone_request.dna_modifiers.network_seed),
+        }
+    }
+}
+
 pub struct PushNotificationsServiceClient {
     pub runtime: HolochainRuntime,
     app_id: String,
@@ -24,6 +78,29 @@ pub struct PushNotificationsServiceClient {
 impl PushNotificationsServiceClient {
     pub async fn create(
         data_dir: PathBuf,
+        network_config: NetworkConfig,
+        app_id: String,
+        push_notifications_service_provider_happ_path: PathBuf,
+        progenitors: Vec<AgentPubKey>,
+        mdns_discovery: bool,
+    ) -> Result<Self> {
+        Self::create_with_passphrase(
+            data_dir,
+            vec![],
+            network_config,
+            app_id,
+            push_notifications_service_provider_happ_path,
+            progenitors,
+            mdns_discovery,
+        )
+        .await
+    }
+
+    /// Launches the conductor with the given lair keystore passphrase, which must be
+    /// the same every time the same data directory is reused
+    pub async fn create_with_passphrase(
+        data_dir: PathBuf,
+        passphrase: Vec<u8>,
         mut network_config: NetworkConfig,
         app_id: String,
         push_notifications_service_provider_happ_path: PathBuf,
@@ -34,7 +111,7 @@ impl PushNotificationsServiceClient {
         let mut config = HolochainRuntimeConfig::new(data_dir.clone(), network_config);
         config.mdns_discovery = mdns_discovery;
 
-        let runtime = HolochainRuntime::launch(vec_to_locked(vec![]), config).await?;
+        let runtime = HolochainRuntime::launch(vec_to_locked(passphrase), config).await?;
         setup(
             &runtime,
             &app_id,
@@ -49,9 +126,25 @@ impl PushNotificationsServiceClient {
         })
     }
 
+    /// The agent key of this client, which is stable as long as the same data directory is reused
+    pub async fn agent_pub_key(&self) -> Result<AgentPubKey> {
+        let app_ws = self
+            .runtime
+            .app_websocket(self.app_id.clone(), holochain_client::AllowedOrigins::Any)
+            .await?;
+        let Some(app_info) = app_ws.app_info().await? else {
+            return Err(anyhow!("App {} is not installed.", self.app_id));
+        };
+        Ok(app_info.agent_pub_key)
+    }
+
+    /// Publishes the service account key for its FCM project
+    ///
+    /// The notification style is applied to the notifications of the project that don't set their own
     pub async fn publish_service_account_key(
         &self,
         service_account_key: ServiceAccountKey,
+        notification_style: Option<NotificationStyle>,
     ) -> anyhow::Result<()> {
         self.wait_for_clone_providers().await?;
 
@@ -72,7 +165,10 @@ impl PushNotificationsServiceClient {
                 ZomeCallTarget::RoleName("push_notifications_service".into()),
                 ZomeName::from("push_notifications_service"),
                 "publish_service_account_key".into(),
-                ExternIO::encode(from(service_account_key.clone()))?,
+                ExternIO::encode(push_notifications_types::ServiceAccountKey {
+                    notification_style,
+                    ..from(service_account_key.clone())
+                })?,
             )
             .await?;
 
@@ -109,7 +205,339 @@ impl PushNotificationsServiceClient {
         Ok(())
     }
 
-    pub async fn wait_for_clone_providers(&self) -> anyhow::Result<()> {
+    pub async fn publish_notification_template(
+        &self,
+        notification_template: NotificationTemplate,
+    ) -> anyhow::Result<()> {
+        self.wait_for_clone_providers().await?;
+
+        log::info!("Successfully joined peers: executing request...");
+
+        let app_ws = self
+            .runtime
+            .app_websocket(self.app_id.clone(), holochain_client::AllowedOrigins::Any)
+            .await?;
+
+        log::info!("Publishing notification template...");
+
+        app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName("push_notifications_service".into()),
+                ZomeName::from("push_notifications_service"),
+                "publish_notification_template".into(),
+                ExternIO::encode(notification_template.clone())?,
+            )
+            .await?;
+
+        let maybe_template: Option<NotificationTemplate> = app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName("push_notifications_service".into()),
+                ZomeName::from("push_notifications_service"),
+                "get_notification_template".into(),
+                ExternIO::encode(GetNotificationTemplateInput {
+                    fcm_project_id: notification_template.fcm_project_id.clone(),
+                    template_id: notification_template.template_id.clone(),
+                })?,
+            )
+            .await?
+            .decode()?;
+
+        if maybe_template.ne(&Some(notification_template)) {
+            return Err(anyhow!("Failed to publish notification template."));
+        }
+
+        std::thread::sleep(Duration::from_secs(4));
+
+        println!("");
+
+        println!(
+            "{}",
+            "Successfully published notification template."
+                .bold()
+                .green()
+        );
+
+        println!("");
+
+        Ok(())
+    }
+
+    /// Publishes a new service account key for its FCM project, which only replaces the current key
+    /// once all the providers have acknowledged it or the grace period has passed
+    ///
+    /// Without a notification style, the new key keeps the one of the current key
+    pub async fn rotate_service_account_key(
+        &self,
+        service_account_key: ServiceAccountKey,
+        notification_style: Option<NotificationStyle>,
+        grace_period_secs: Option<u64>,
+    ) -> anyhow::Result<()> {
+        self.wait_for_clone_providers().await?;
+
+        log::info!("Successfully joined peers: executing request...");
+
+        if service_account_key.project_id.is_none() {
+            return Err(anyhow!("Invalid ServiceAccountKey: project_id is null."));
+        }
+        let app_ws = self
+            .runtime
+            .app_websocket(self.app_id.clone(), holochain_client::AllowedOrigins::Any)
+            .await?;
+
+        log::info!("Rotating service account key...");
+
+        app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName("push_notifications_service".into()),
+                ZomeName::from("push_notifications_service"),
+                "rotate_service_account_key".into(),
+                ExternIO::encode(RotateServiceAccountKeyInput {
+                    service_account_key: push_notifications_types::ServiceAccountKey {
+                        notification_style,
+                        ..from(service_account_key)
+                    },
+                    grace_period_secs,
+                })?,
+            )
+            .await?;
+
+        std::thread::sleep(Duration::from_secs(4));
+
+        Ok(())
+    }
+
+    pub async fn get_service_account_key_history(
+        &self,
+        fcm_project_id: String,
+    ) -> anyhow::Result<Vec<ServiceAccountKeyHistoryItem>> {
+        self.wait_for_clone_providers().await?;
+
+        let app_ws = self
+            .runtime
+            .app_websocket(self.app_id.clone(), holochain_client::AllowedOrigins::Any)
+            .await?;
+
+        let history: Vec<ServiceAccountKeyHistoryItem> = app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName("push_notifications_service".into()),
+                ZomeName::from("push_notifications_service"),
+                "get_service_account_key_history".into(),
+                ExternIO::encode(fcm_project_id)?,
+            )
+            .await?
+            .decode()?;
+
+        Ok(history)
+    }
+
+    pub async fn list_fcm_projects(&self) -> anyhow::Result<Vec<String>> {
+        self.wait_for_clone_providers().await?;
+
+        let app_ws = self
+            .runtime
+            .app_websocket(self.app_id.clone(), holochain_client::AllowedOrigins::Any)
+            .await?;
+
+        let fcm_projects: Vec<String> = app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName("push_notifications_service".into()),
+                ZomeName::from("push_notifications_service"),
+                "get_all_fcm_projects".into(),
+                ExternIO::encode(())?,
+            )
+            .await?
+            .decode()?;
+
+        Ok(fcm_projects)
+    }
+
+    /// Gets the metadata of the current service account key for the FCM project,
+    /// never exposing its private key
+    pub async fn get_fcm_project(
+        &self,
+        fcm_project_id: String,
+    ) -> anyhow::Result<Option<FcmProjectInfo>> {
+        self.wait_for_clone_providers().await?;
+
+        let app_ws = self
+            .runtime
+            .app_websocket(self.app_id.clone(), holochain_client::AllowedOrigins::Any)
+            .await?;
+
+        let maybe_key: Option<push_notifications_types::ServiceAccountKey> = app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName("push_notifications_service".into()),
+                ZomeName::from("push_notifications_service"),
+                "get_current_service_account_key".into(),
+                ExternIO::encode(fcm_project_id.clone())?,
+            )
+            .await?
+            .decode()?;
+
+        Ok(maybe_key.map(|key| FcmProjectInfo::new(fcm_project_id, key)))
+    }
+
+    pub async fn delete_fcm_project(&self, fcm_project_id: String) -> anyhow::Result<()> {
+        self.wait_for_clone_providers().await?;
+
+        log::info!("Successfully joined peers: executing request...");
+
+        let app_ws = self
+            .runtime
+            .app_websocket(self.app_id.clone(), holochain_client::AllowedOrigins::Any)
+            .await?;
+
+        log::info!("Deleting FCM project...");
+
+        app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName("push_notifications_service".into()),
+                ZomeName::from("push_notifications_service"),
+                "delete_fcm_project".into(),
+                ExternIO::encode(fcm_project_id.clone())?,
+            )
+            .await?;
+
+        std::thread::sleep(Duration::from_secs(4));
+
+        Ok(())
+    }
+
+    /// Lists the clone requests, hiding their network seeds unless `show_seeds` is set
+    pub async fn list_clone_requests(
+        &self,
+        show_seeds: bool,
+    ) -> anyhow::Result<Vec<CloneRequestInfo>> {
+        self.wait_for_clone_providers().await?;
+
+        let app_ws = self
+            .runtime
+            .app_websocket(self.app_id.clone(), holochain_client::AllowedOrigins::Any)
+            .await?;
+
+        let clone_request_hashes: Vec<EntryHash> = app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName("push_notifications_service".into()),
+                ZomeName::from("clone_manager"),
+                "get_all_clone_requests".into(),
+                ExternIO::encode(())?,
+            )
+            .await?
+            .decode()?;
+
+        let mut clone_requests = Vec::new();
+        for clone_request_hash in clone_request_hashes {
+            let maybe_clone_request: Option<CloneRequest> = app_ws
+                .call_zome(
+                    ZomeCallTarget::RoleName("push_notifications_service".into()),
+                    ZomeName::from("clone_manager"),
+                    "get_clone_request".into(),
+                    ExternIO::encode(clone_request_hash.clone())?,
+                )
+                .await?
+                .decode()?;
+            if let Some(clone_request) = maybe_clone_request {
+                clone_requests.push(CloneRequestInfo::new(
+                    clone_request_hash,
+                    clone_request,
+                    show_seeds,
+                ));
+            }
+        }
+
+        Ok(clone_requests)
+    }
+
+    /// Asks the clone providers, one at a time, to deliver a test push notification,
+    /// returning the result from FCM reported by the first one that handles the request
+    pub async fn send_test_notification(
+        &self,
+        fcm_project_id: String,
+        target: TestPushNotificationTarget,
+        dry_run: bool,
+    ) -> anyhow::Result<TestPushNotificationResult> {
+        let providers = self.wait_for_clone_providers().await?;
+
+        log::info!("Successfully joined peers: executing request...");
+
+        let app_ws = self
+            .runtime
+            .app_websocket(self.app_id.clone(), holochain_client::AllowedOrigins::Any)
+            .await?;
+
+        // The providers send the results with a remote signal, which needs the capability grant
+        app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName("push_notifications_service".into()),
+                ZomeName::from("push_notifications_service"),
+                "ensure_test_push_notification_capability".into(),
+                ExternIO::encode(())?,
+            )
+            .await?;
+
+        let (results_tx, mut results_rx) = tokio::sync::mpsc::unbounded_channel();
+        app_ws
+            .on_signal(move |signal| {
+                let Signal::App { signal, .. } = signal else {
+                    return ();
+                };
+                if let Ok(result) = signal.into_inner().decode::<TestPushNotificationResult>() {
+                    let _ = results_tx.send(result);
+                }
+            })
+            .await;
+
+        // Random so that no other agent can guess it to forge a result
+        let request_id: String = rand::random::<[u8; 16]>()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        for provider in providers {
+            log::info!("Requesting test push notification from provider {provider}...");
+
+            if let Err(err) = app_ws
+                .call_zome(
+                    ZomeCallTarget::RoleName("push_notifications_service".into()),
+                    ZomeName::from("push_notifications_service"),
+                    "request_test_push_notification".into(),
+                    ExternIO::encode(RequestTestPushNotificationInput {
+                        provider: provider.clone(),
+                        request_id: request_id.clone(),
+                        fcm_project_id: fcm_project_id.clone(),
+                        target: target.clone(),
+                        dry_run,
+                    })?,
+                )
+                .await
+            {
+                log::warn!("Provider {provider} failed to handle the request: {err:?}");
+                continue;
+            }
+
+            let result = tokio::time::timeout(TEST_NOTIFICATION_TIMEOUT, async {
+                while let Some(result) = results_rx.recv().await {
+                    // Late results from the providers that already timed out are ignored
+                    if result.request_id.eq(&request_id) && result.provider.eq(&provider) {
+                        return Some(result);
+                    }
+                }
+                None
+            })
+            .await;
+
+            match result {
+                Ok(Some(result)) => return Ok(result),
+                _ => log::warn!(
+                    "Provider {provider} didn't report the result of the test push notification."
+                ),
+            }
+        }
+
+        Err(anyhow!("No provider delivered the test push notification."))
+    }
+
+    pub async fn wait_for_clone_providers(&self) -> anyhow::Result<Vec<AgentPubKey>> {
         log::info!("Waiting for clone providers...");
         let app_ws = self
             .runtime
@@ -130,7 +558,7 @@ impl PushNotificationsServiceClient {
                 if clone_providers.is_empty() {
                     return Err(anyhow!("No clone providers found."));
                 }
-                Ok(())
+                Ok(clone_providers)
             },
             30,
         )
@@ -240,5 +668,6 @@ pub fn from(key: fcm_v1::auth::ServiceAccountKey) -> push_notifications_types::S
         token_uri: key.token_uri,
         auth_provider_x509_cert_url: key.auth_provider_x509_cert_url,
         client_x509_cert_url: key.client_x509_cert_url,
+        notification_style: None,
     }
 }
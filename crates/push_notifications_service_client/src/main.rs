@@ -1,16 +1,19 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
-use env_logger::Builder;
-use fcm_v1::auth::ServiceAccountKey;
-use holochain::core::AgentPubKeyB64;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use colored::Colorize;
+use holochain::core::{ActionHashB64, AgentPubKeyB64};
 use holochain::prelude::NetworkSeed;
 use holochain_runtime::NetworkConfig;
 use holochain_util::ffs::read_to_string;
-use log::Level;
-use push_notifications_service_client::PushNotificationsServiceClient;
+use push_notifications_logging::{default_filters, init_logging, LogFormat};
+use push_notifications_service_client::{
+    service_account_key_source::ServiceAccountKeySource, PushNotificationsServiceClient,
+};
+use push_notifications_types::{
+    NotificationStyle, NotificationTemplate, ServiceAccountKeyStatus, TestPushNotificationTarget,
+};
 use std::io::Write;
 use std::path::PathBuf;
-use std::str::FromStr;
 use tempdir::TempDir;
 
 #[derive(Parser, Debug)]
@@ -30,6 +33,22 @@ struct Args {
     #[arg(long)]
     mdns_discovery: bool,
 
+    /// Directory in which to keep the conductor and the agent key of the client across invocations,
+    /// a temporary directory with a new agent is used if not set
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+
+    /// File containing the passphrase of the lair keystore in --data-dir, prompted if not set
+    #[arg(long, requires = "data_dir")]
+    passphrase_file: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Format of the output of the commands that inspect the service
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -38,14 +57,191 @@ struct Args {
 enum Commands {
     /// Publishes a service account key
     PublishServiceAccountKey {
+        #[command(flatten)]
+        service_account_key: ServiceAccountKeyArgs,
+
+        /// Style of the notifications of the project that don't set their own
+        #[arg(long, value_enum)]
+        notification_style: Option<NotificationStyleArg>,
+    },
+    /// Publishes a notification template, read from a JSON file
+    PublishNotificationTemplate {
         #[arg(long)]
-        service_account_key_path: PathBuf,
+        notification_template_path: PathBuf,
     },
     /// Create a clone request for the service providers DNA
     CreateCloneRequest {
         #[arg(long)]
         network_seed: NetworkSeed,
     },
+    /// Publishes a new service account key for an FCM project, keeping the current one active
+    /// until all the providers have acknowledged the new key or the grace period has passed
+    RotateKey {
+        #[command(flatten)]
+        service_account_key: ServiceAccountKeyArgs,
+
+        /// Style of the notifications of the project that don't set their own,
+        /// by default the one of the current key
+        #[arg(long, value_enum)]
+        notification_style: Option<NotificationStyleArg>,
+
+        /// Seconds after which the new key is activated even if not all providers have acknowledged it
+        #[arg(long)]
+        grace_period_secs: Option<u64>,
+    },
+    /// Lists the active, pending and retired service account keys of an FCM project
+    KeyHistory {
+        #[arg(long)]
+        fcm_project_id: String,
+    },
+    /// Asks a provider to send a test push notification, and reports the result from FCM
+    SendTestNotification {
+        #[arg(long)]
+        fcm_project_id: String,
+
+        /// FCM token of the device that will receive the test push notification
+        #[arg(long, conflicts_with = "agent", required_unless_present = "agent")]
+        token: Option<String>,
+
+        /// Agent that has registered their FCM token, as an alternative to --token
+        #[arg(long)]
+        agent: Option<AgentPubKeyB64>,
+
+        /// Only validate the push notification with FCM, without delivering it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Prints the agent key of the client, to be listed in --progenitors
+    PrintAgentKey,
+    /// Lists the FCM projects that have a service account key published
+    ListProjects,
+    /// Shows the metadata of the current service account key of an FCM project
+    ShowProject {
+        #[arg(long)]
+        fcm_project_id: String,
+    },
+    /// Deletes all the service account keys of an FCM project
+    DeleteProject {
+        #[arg(long)]
+        fcm_project_id: String,
+
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Lists the clone requests for the service providers DNA
+    ListCloneRequests {
+        /// Also print the network seeds, which give access to the network of the providers
+        #[arg(long)]
+        show_seeds: bool,
+    },
+}
+
+/// The service account key can be plain JSON, base64 encoded JSON, or YAML (e.g. decrypted by SOPS)
+#[derive(clap::Args, Debug)]
+#[group(required = true, multiple = false)]
+struct ServiceAccountKeyArgs {
+    /// Path to the file of the service account key, or "-" to read it from stdin
+    #[arg(long)]
+    service_account_key_path: Option<PathBuf>,
+
+    /// Environment variable that contains the service account key
+    #[arg(long)]
+    service_account_key_env: Option<String>,
+}
+
+impl ServiceAccountKeyArgs {
+    fn source(&self) -> ServiceAccountKeySource {
+        match (
+            &self.service_account_key_path,
+            &self.service_account_key_env,
+        ) {
+            (Some(path), _) if path.as_os_str() == "-" => ServiceAccountKeySource::Stdin,
+            (Some(path), _) => ServiceAccountKeySource::File(path.clone()),
+            (None, Some(var)) => ServiceAccountKeySource::Env(var.clone()),
+            (None, None) => unreachable!("clap requires one of the service account key sources"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum NotificationStyleArg {
+    /// The devices display the notification
+    Display,
+    /// The notification is only delivered as data to the app
+    DataOnly,
+    /// The devices display the notification, which is also delivered as data to the app
+    Both,
+}
+
+impl From<NotificationStyleArg> for NotificationStyle {
+    fn from(style: NotificationStyleArg) -> Self {
+        match style {
+            NotificationStyleArg::Display => NotificationStyle::Display,
+            NotificationStyleArg::DataOnly => NotificationStyle::DataOnly,
+            NotificationStyleArg::Both => NotificationStyle::Both,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+}
+
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.len());
+        }
+    }
+
+    let format_row = |cells: Vec<String>| {
+        cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| format!("{cell:<width$}", width = widths[i]))
+            .collect::<Vec<String>>()
+            .join("  ")
+    };
+
+    println!(
+        "{}",
+        format_row(headers.iter().map(|h| h.to_string()).collect())
+    );
+    for row in rows {
+        println!("{}", format_row(row));
+    }
+}
+
+fn confirm(question: String) -> Result<bool> {
+    print!("{question} [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Environment variable from which to read the passphrase of the lair keystore
+const PASSPHRASE_ENV: &str = "PUSH_NOTIFICATIONS_SERVICE_CLIENT_PASSPHRASE";
+
+const CLIENT_APP_ID: &str = "push-notifications-service-client";
+
+/// Reads the lair keystore passphrase from the given file, the environment, or the terminal, in that order
+fn read_passphrase(passphrase_file: Option<&PathBuf>) -> Result<Vec<u8>> {
+    let passphrase = match passphrase_file {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read passphrase file {path:?}: {err}"))?
+            .trim_end_matches(['\n', '\r'])
+            .to_string(),
+        None => match std::env::var(PASSPHRASE_ENV) {
+            Ok(passphrase) => passphrase,
+            Err(_) => rpassword::prompt_password("Lair keystore passphrase: ")?,
+        },
+    };
+    Ok(passphrase.into_bytes())
 }
 
 fn network_config(bootstrap_url: Option<String>, signal_url: Option<String>) -> NetworkConfig {
@@ -62,13 +258,6 @@ fn network_config(bootstrap_url: Option<String>, signal_url: Option<String>) ->
     network_config
 }
 
-fn log_level() -> Level {
-    match std::env::var("RUST_LOG") {
-        Ok(s) => Level::from_str(s.as_str()).expect("Invalid RUST_LOG level"),
-        _ => Level::Info,
-    }
-}
-
 fn set_wasm_level() {
     match std::env::var("WASM_LOG") {
         Ok(_s) => {}
@@ -82,23 +271,60 @@ fn set_wasm_level() {
 async fn main() -> Result<()> {
     let args = Args::parse();
 
-    Builder::new()
-        .format(|buf, record| writeln!(buf, "[{}] {}", record.level(), record.args()))
-        .target(env_logger::Target::Stdout)
-        .filter(None, log_level().to_level_filter())
-        .filter_module("holochain_sqlite", log::LevelFilter::Off)
-        .filter_module("tracing::span", log::LevelFilter::Off)
-        .filter_module("iroh", log::LevelFilter::Warn)
-        .init();
+    init_logging("info", args.log_format, &default_filters())?;
     set_wasm_level();
 
-    let tempdir = TempDir::new("push-notifications-service-client")?;
-    let data_dir = tempdir.path().to_path_buf();
+    if let Commands::DeleteProject {
+        fcm_project_id,
+        yes: false,
+    } = &args.command
+    {
+        if !confirm(format!(
+            "Delete all the service account keys of FCM project {fcm_project_id}?"
+        ))? {
+            return Ok(());
+        }
+    }
+
+    // Validated before launching the conductor so that a malformed key fails fast
+    let service_account_key = match &args.command {
+        Commands::PublishServiceAccountKey {
+            service_account_key,
+            ..
+        }
+        | Commands::RotateKey {
+            service_account_key,
+            ..
+        } => Some(service_account_key.source().load()?),
+        _ => None,
+    };
+
+    if matches!(args.command, Commands::PrintAgentKey) && args.data_dir.is_none() {
+        return Err(anyhow!(
+            "print-agent-key requires --data-dir: without it, a new agent is created on every invocation."
+        ));
+    }
 
-    let client = PushNotificationsServiceClient::create(
-        data_dir.clone(),
+    // Kept alive until the end of main so that the temporary directory is not removed earlier
+    let mut tempdir: Option<TempDir> = None;
+    let (data_dir, passphrase) = match &args.data_dir {
+        Some(data_dir) => (
+            data_dir.clone(),
+            read_passphrase(args.passphrase_file.as_ref())?,
+        ),
+        None => {
+            let dir = TempDir::new("push-notifications-service-client")?;
+            let data_dir = dir.path().to_path_buf();
+            tempdir = Some(dir);
+            (data_dir, vec![])
+        }
+    };
+
+    let client = PushNotificationsServiceClient::create_with_passphrase(
+        data_dir,
+        passphrase,
         network_config(args.bootstrap_url, args.signal_url),
-        String::from("temporary-client-app"),
+        String::from(CLIENT_APP_ID),
         args.push_notifications_service_provider_happ,
         args.progenitors.into_iter().map(|p| p.into()).collect(),
         args.mdns_discovery,
@@ -107,22 +333,206 @@ async fn main() -> Result<()> {
 
     match args.command {
         Commands::PublishServiceAccountKey {
-            service_account_key_path,
+            notification_style, ..
         } => {
-            let service_account_str = read_to_string(service_account_key_path).await?;
-            let service_account_key: ServiceAccountKey =
-                serde_json::from_str(&service_account_str)?;
+            let Some(service_account_key) = service_account_key else {
+                unreachable!("The service account key is loaded before launching the client");
+            };
 
             client
-                .publish_service_account_key(service_account_key)
+                .publish_service_account_key(
+                    service_account_key,
+                    notification_style.map(Into::into),
+                )
+                .await?;
+        }
+        Commands::PublishNotificationTemplate {
+            notification_template_path,
+        } => {
+            let notification_template_str = read_to_string(notification_template_path).await?;
+            let notification_template: NotificationTemplate =
+                serde_json::from_str(&notification_template_str)?;
+
+            client
+                .publish_notification_template(notification_template)
                 .await?;
         }
         Commands::CreateCloneRequest { network_seed } => {
             client.create_clone_request(network_seed).await?;
         }
+        Commands::RotateKey {
+            notification_style,
+            grace_period_secs,
+            ..
+        } => {
+            let Some(service_account_key) = service_account_key else {
+                unreachable!("The service account key is loaded before launching the client");
+            };
+
+            client
+                .rotate_service_account_key(
+                    service_account_key,
+                    notification_style.map(Into::into),
+                    grace_period_secs,
+                )
+                .await?;
+            println!(
+                "{}",
+                "Published the new service account key: it will be activated once all the providers have acknowledged it."
+                    .bold()
+                    .green()
+            );
+        }
+        Commands::KeyHistory { fcm_project_id } => {
+            let history = client
+                .get_service_account_key_history(fcm_project_id)
+                .await?;
+            match args.output {
+                OutputFormat::Table => print_table(
+                    &[
+                        "KEY",
+                        "PRIVATE KEY ID",
+                        "CLIENT EMAIL",
+                        "PUBLISHED AT",
+                        "STATUS",
+                    ],
+                    history
+                        .into_iter()
+                        .map(|item| {
+                            let status = match item.status {
+                                ServiceAccountKeyStatus::Active => String::from("active"),
+                                ServiceAccountKeyStatus::Pending { acknowledgements } => {
+                                    format!("pending ({acknowledgements} acknowledgements)")
+                                }
+                                ServiceAccountKeyStatus::Retired { retired_at } => {
+                                    format!("retired at {retired_at}")
+                                }
+                            };
+                            vec![
+                                ActionHashB64::from(item.key_hash).to_string(),
+                                item.private_key_id.unwrap_or_default(),
+                                item.client_email,
+                                item.published_at.to_string(),
+                                status,
+                            ]
+                        })
+                        .collect(),
+                ),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&history)?),
+            }
+        }
+        Commands::SendTestNotification {
+            fcm_project_id,
+            token,
+            agent,
+            dry_run,
+        } => {
+            let target = match (token, agent) {
+                (Some(token), _) => TestPushNotificationTarget::Token(token),
+                (None, Some(agent)) => TestPushNotificationTarget::Agent(agent.into()),
+                (None, None) => return Err(anyhow!("Either --token or --agent is required.")),
+            };
+            let result = client
+                .send_test_notification(fcm_project_id, target, dry_run)
+                .await?;
+            match args.output {
+                OutputFormat::Table => print_table(
+                    &["FIELD", "VALUE"],
+                    vec![
+                        vec![String::from("request_id"), result.request_id.clone()],
+                        vec![
+                            String::from("provider"),
+                            AgentPubKeyB64::from(result.provider.clone()).to_string(),
+                        ],
+                        vec![String::from("dry_run"), result.dry_run.to_string()],
+                        vec![
+                            String::from("result"),
+                            result.error.clone().unwrap_or(String::from("success")),
+                        ],
+                    ],
+                ),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&result)?),
+            }
+            if let Some(error) = result.error {
+                client.runtime.shutdown().await?;
+                return Err(anyhow!("Test push notification failed: {error}"));
+            }
+        }
+        Commands::PrintAgentKey => {
+            let agent_pub_key = client.agent_pub_key().await?;
+            println!("{}", AgentPubKeyB64::from(agent_pub_key));
+        }
+        Commands::ListProjects => {
+            let fcm_projects = client.list_fcm_projects().await?;
+            match args.output {
+                OutputFormat::Table => print_table(
+                    &["FCM PROJECT"],
+                    fcm_projects.into_iter().map(|p| vec![p]).collect(),
+                ),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&fcm_projects)?),
+            }
+        }
+        Commands::ShowProject { fcm_project_id } => {
+            let Some(project) = client.get_fcm_project(fcm_project_id.clone()).await? else {
+                return Err(anyhow!(
+                    "FCM project {fcm_project_id} has no service account key."
+                ));
+            };
+            match args.output {
+                OutputFormat::Table => print_table(
+                    &["FIELD", "VALUE"],
+                    vec![
+                        vec![String::from("fcm_project_id"), project.fcm_project_id],
+                        vec![String::from("client_email"), project.client_email],
+                        vec![
+                            String::from("client_id"),
+                            project.client_id.unwrap_or_default(),
+                        ],
+                        vec![
+                            String::from("private_key_id"),
+                            project.private_key_id.unwrap_or_default(),
+                        ],
+                        vec![String::from("token_uri"), project.token_uri],
+                    ],
+                ),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&project)?),
+            }
+        }
+        Commands::DeleteProject { fcm_project_id, .. } => {
+            client.delete_fcm_project(fcm_project_id.clone()).await?;
+            println!(
+                "{}",
+                format!("Deleted FCM project {fcm_project_id}.")
+                    .bold()
+                    .green()
+            );
+        }
+        Commands::ListCloneRequests { show_seeds } => {
+            let clone_requests = client.list_clone_requests(show_seeds).await?;
+            match args.output {
+                OutputFormat::Table => print_table(
+                    &["CLONE REQUEST", "NETWORK SEED"],
+                    clone_requests
+                        .into_iter()
+                        .map(|clone_request| {
+                            vec![
+                                clone_request.clone_request_hash.to_string(),
+                                clone_request
+                                    .network_seed
+                                    .unwrap_or(String::from("<hidden, use --show-seeds>")),
+                            ]
+                        })
+                        .collect(),
+                ),
+                OutputFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&clone_requests)?)
+                }
+            }
+        }
     }
 
     client.runtime.shutdown().await?;
+    drop(tempdir);
 
     Ok(())
 }
@@ -28,7 +28,9 @@
                 clone_limit: 100000
         '';
 
-        dnas = { services = inputs'.service-providers.packages.services_dna; };
+        dnas = {
+          services = self'.packages.services_dna_with_push_notifications_receiver;
+        };
       }).meta.debug;
 
       craneLib = inputs.crane.mkLib pkgs;
@@ -65,6 +67,10 @@
         # For the integration test
         inherit END_USER_HAPP CLIENT_HAPP SERVICE_PROVIDER_HAPP;
       });
+      clippy = craneLib.cargoClippy (commonArgs // {
+        inherit cargoArtifacts;
+        cargoClippyExtraArgs = "--workspace --all-targets -- -D warnings";
+      });
 
       binaryWithDebugHapp =
         pkgs.runCommandLocal "push-notifications-service-provider" {
@@ -126,5 +132,6 @@
         };
 
       checks.send-push-notification-test = check;
+      checks.clippy = clippy;
     };
 }
@@ -0,0 +1,381 @@
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use holochain_runtime::NetworkConfig;
+pub use push_notifications_logging::LogFormat;
+use push_notifications_logging::{default_filters, init_logging};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::fcm_client::{FcmClientConfig, DEFAULT_FCM_BASE_URL};
+
+/// Prefix of the environment variables that override the configuration file.
+///
+/// Nested fields are separated by `__`, e.g. `PUSH_NOTIFICATIONS_PROVIDER__FCM__REQUEST_TIMEOUT_SECS=5`.
+pub const ENV_PREFIX: &str = "PUSH_NOTIFICATIONS_PROVIDER";
+
+/// Configuration of the push notifications service provider.
+///
+/// Every field has a default, so the configuration file only needs to contain the values to change.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderConfig {
+    pub network: NetworkSettings,
+    pub fcm: FcmSettings,
+    pub reconcile: ReconcileSettings,
+    pub metrics: MetricsSettings,
+    pub health: HealthSettings,
+    pub shutdown: ShutdownSettings,
+    pub logging: LoggingSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSettings {
+    pub bootstrap_url: Option<String>,
+    pub signal_url: Option<String>,
+    pub mdns_discovery: bool,
+    pub admin_port: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FcmSettings {
+    /// Timeout for each request to FCM
+    pub request_timeout_secs: u64,
+    /// Maximum number of push notifications being sent to FCM at the same time
+    pub max_concurrent_sends: usize,
+    /// Maximum number of push notifications sent to FCM per second, unlimited if not set
+    pub max_sends_per_second: Option<u32>,
+    /// Base URL of the FCM HTTP v1 API, e.g. to go through a regional endpoint or a local emulator
+    pub base_url: String,
+    /// OAuth token endpoint, overriding the `token_uri` of the service account keys
+    pub token_url: Option<String>,
+    /// HTTP(S) proxy for the requests to FCM and to the OAuth token endpoint
+    pub proxy: Option<String>,
+    /// PEM files with additional root certificates to trust, e.g. for a TLS intercepting egress proxy
+    pub tls_root_certificates: Vec<PathBuf>,
+    /// Whether to trust the root certificates bundled with the TLS implementation
+    pub tls_built_in_roots: bool,
+}
+
+impl Default for FcmSettings {
+    fn default() -> Self {
+        Self {
+            request_timeout_secs: 2,
+            max_concurrent_sends: 16,
+            max_sends_per_second: None,
+            base_url: String::from(DEFAULT_FCM_BASE_URL),
+            token_url: None,
+            proxy: None,
+            tls_root_certificates: vec![],
+            tls_built_in_roots: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReconcileSettings {
+    /// Interval between reconciliations of the cloned service cells
+    pub interval_secs: u64,
+    /// Number of times to retry fetching a new clone request before giving up
+    pub clone_request_retries: usize,
+}
+
+impl Default for ReconcileSettings {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60,
+            clone_request_retries: 20,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsSettings {
+    /// Port in which to serve the Prometheus metrics at `/metrics`, disabled if not set
+    pub port: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthSettings {
+    /// Port in which to serve the `/healthz` and `/readyz` probes, disabled if not set
+    pub port: Option<u16>,
+    /// The provider is not ready if the cloned cells haven't been reconciled successfully for this long
+    pub reconcile_max_age_secs: u64,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self {
+            port: None,
+            reconcile_max_age_secs: 5 * 60,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownSettings {
+    /// Maximum time to wait for the in-flight push notifications to be sent when shutting down
+    pub drain_timeout_secs: u64,
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        Self {
+            drain_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingSettings {
+    /// Default log level, overridden by the `RUST_LOG` environment variable
+    pub level: String,
+    pub format: LogFormat,
+    /// Log level for specific modules
+    pub filters: BTreeMap<String, String>,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        Self {
+            level: String::from("info"),
+            format: LogFormat::default(),
+            filters: default_filters(),
+        }
+    }
+}
+
+impl LoggingSettings {
+    /// Installs the global tracing subscriber, which also collects the records from the `log` crate
+    pub fn init(&self) -> Result<()> {
+        init_logging(&self.level, self.format, &self.filters)
+    }
+}
+
+impl ProviderConfig {
+    /// Loads the configuration from the given TOML or YAML file, if any, applying the
+    /// overrides from the environment variables on top of it
+    pub fn load(path: Option<&PathBuf>) -> Result<Self> {
+        Self::load_with_env(path, std::env::vars())
+    }
+
+    /// Like `load`, but taking the overrides from the given variables instead of the environment
+    pub fn load_with_env(
+        path: Option<&PathBuf>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        let mut value = serde_json::to_value(ProviderConfig::default())?;
+
+        if let Some(path) = path {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the config file {path:?}"))?;
+            let file_value: Value = match path.extension().and_then(|e| e.to_str()) {
+                Some("toml") => toml::from_str(&contents)
+                    .with_context(|| format!("Failed to parse the config file {path:?}"))?,
+                Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)
+                    .with_context(|| format!("Failed to parse the config file {path:?}"))?,
+                _ => {
+                    return Err(anyhow!(
+                        "Unsupported config file {path:?}: it must have a .toml, .yaml or .yml extension"
+                    ))
+                }
+            };
+            merge(&mut value, file_value);
+        }
+
+        // Overrides of unset optional fields whose type can't be told from their current value
+        let mut untyped_overrides = vec![];
+        for (path, raw_value) in env_overrides(vars.into_iter()) {
+            let override_value = match get_value(&value, &path) {
+                // String fields take the raw value as is, e.g. "123" or "true"
+                Some(Value::String(_)) => Value::String(raw_value),
+                Some(Value::Null) | None => {
+                    let decoded = decode_env_value(&raw_value);
+                    if !decoded.is_string() {
+                        untyped_overrides.push((path.clone(), raw_value));
+                    }
+                    decoded
+                }
+                Some(_) => decode_env_value(&raw_value),
+            };
+            set_value(&mut value, &path, override_value);
+        }
+
+        let config: ProviderConfig = match serde_json::from_value(value.clone()) {
+            Ok(config) => config,
+            // The unset optional fields may be strings that look like JSON
+            Err(_) if !untyped_overrides.is_empty() => {
+                for (path, raw_value) in untyped_overrides {
+                    set_value(&mut value, &path, Value::String(raw_value));
+                }
+                serde_json::from_value(value).context("Invalid configuration")?
+            }
+            Err(err) => return Err(anyhow::Error::new(err).context("Invalid configuration")),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the values that can't be expressed by their types
+    pub fn validate(&self) -> Result<()> {
+        let fcm_base_url = Some(self.fcm.base_url.clone());
+        for (name, url) in [
+            ("network.bootstrap_url", &self.network.bootstrap_url),
+            ("network.signal_url", &self.network.signal_url),
+            ("fcm.base_url", &fcm_base_url),
+            ("fcm.token_url", &self.fcm.token_url),
+            ("fcm.proxy", &self.fcm.proxy),
+        ] {
+            if let Some(url) = url {
+                url2::Url2::try_parse(url)
+                    .map_err(|err| anyhow!("Invalid {name} \"{url}\": {err:?}"))?;
+            }
+        }
+        if self.fcm.request_timeout_secs == 0 {
+            return Err(anyhow!("fcm.request_timeout_secs must be greater than 0"));
+        }
+        if self.fcm.max_concurrent_sends == 0 {
+            return Err(anyhow!("fcm.max_concurrent_sends must be greater than 0"));
+        }
+        if self.fcm.max_sends_per_second == Some(0) {
+            return Err(anyhow!(
+                "fcm.max_sends_per_second must be greater than 0, or not be set to send without limits"
+            ));
+        }
+        if !self.fcm.tls_built_in_roots && self.fcm.tls_root_certificates.is_empty() {
+            return Err(anyhow!(
+                "fcm.tls_root_certificates must not be empty when fcm.tls_built_in_roots is false"
+            ));
+        }
+        self.fcm_client_config()
+            .http_client()
+            .context("Invalid fcm settings")?;
+        if self.reconcile.interval_secs == 0 {
+            return Err(anyhow!("reconcile.interval_secs must be greater than 0"));
+        }
+        if self.reconcile.clone_request_retries == 0 {
+            return Err(anyhow!(
+                "reconcile.clone_request_retries must be greater than 0"
+            ));
+        }
+        if self.health.reconcile_max_age_secs < self.reconcile.interval_secs {
+            return Err(anyhow!(
+                "health.reconcile_max_age_secs ({}) must not be lower than reconcile.interval_secs ({}), or the provider would never be ready",
+                self.health.reconcile_max_age_secs,
+                self.reconcile.interval_secs
+            ));
+        }
+        log::Level::from_str(&self.logging.level)
+            .map_err(|_| anyhow!("Invalid logging.level \"{}\"", self.logging.level))?;
+        for (module, level) in &self.logging.filters {
+            log::LevelFilter::from_str(level).map_err(|_| {
+                anyhow!("Invalid log level \"{level}\" for logging.filters.\"{module}\"")
+            })?;
+        }
+        let ports = [
+            ("network.admin_port", self.network.admin_port),
+            ("metrics.port", self.metrics.port),
+            ("health.port", self.health.port),
+        ];
+        for (i, (name, port)) in ports.iter().enumerate() {
+            for (other_name, other_port) in &ports[i + 1..] {
+                if port.is_some() && port == other_port {
+                    return Err(anyhow!("{name} and {other_name} can't be the same port"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn network_config(&self) -> NetworkConfig {
+        let mut network_config = NetworkConfig::default();
+
+        if let Some(bootstrap_url) = &self.network.bootstrap_url {
+            network_config.bootstrap_url = url2::Url2::parse(bootstrap_url);
+        }
+        if let Some(signal_url) = &self.network.signal_url {
+            network_config.signal_url = url2::Url2::parse(signal_url);
+        }
+
+        network_config
+    }
+
+    pub fn fcm_client_config(&self) -> FcmClientConfig {
+        FcmClientConfig {
+            request_timeout: Duration::from_secs(self.fcm.request_timeout_secs),
+            fcm_base_url: self.fcm.base_url.clone(),
+            token_url: self.fcm.token_url.clone(),
+            proxy: self.fcm.proxy.clone(),
+            tls_root_certificates: self.fcm.tls_root_certificates.clone(),
+            tls_built_in_roots: self.fcm.tls_built_in_roots,
+        }
+    }
+
+    /// The default configuration, as a TOML document
+    pub fn default_toml() -> Result<String> {
+        Ok(toml::to_string_pretty(&ProviderConfig::default())?)
+    }
+}
+
+/// Collects the overrides from the environment variables that start with `ENV_PREFIX`,
+/// as the path to the overridden field and its raw value
+fn env_overrides(vars: impl Iterator<Item = (String, String)>) -> Vec<(Vec<String>, String)> {
+    vars.filter_map(|(key, raw_value)| {
+        let path = key.strip_prefix(&format!("{ENV_PREFIX}__"))?;
+        let segments = path.split("__").map(|s| s.to_lowercase()).collect();
+        Some((segments, raw_value))
+    })
+    .collect()
+}
+
+/// Decodes the value of a field that is not a string
+fn decode_env_value(raw_value: &str) -> Value {
+    // Values that are not valid JSON (e.g. URLs) are taken as strings
+    serde_json::from_str(raw_value).unwrap_or(Value::String(raw_value.to_string()))
+}
+
+fn get_value<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(value, |value, segment| value.get(segment))
+}
+
+/// Sets the field at the given path, creating the objects that lead to it
+fn set_value(value: &mut Value, path: &[String], new_value: Value) {
+    let mut current = value;
+    for (i, segment) in path.iter().enumerate() {
+        let Value::Object(map) = current else {
+            return;
+        };
+        if i == path.len() - 1 {
+            map.insert(segment.clone(), new_value);
+            return;
+        }
+        current = map
+            .entry(segment.clone())
+            .or_insert_with(|| Value::Object(Default::default()));
+    }
+}
+
+/// Deep merges `overrides` into `base`
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
@@ -1,24 +1,123 @@
+use anyhow::{anyhow, Context};
 use fcm_v1::auth::ServiceAccountKey;
-use push_notifications_types::PushNotification;
-use serde_json::{Map, Value};
-use std::{collections::HashMap, time::Duration};
-
-use fcm_v1::{
-    android::AndroidConfig, apns::ApnsConfig, auth::Authenticator, message::Message, Client,
+use push_notifications_types::{
+    AndroidNotificationOptions, AndroidNotificationPriority, IosNotificationOptions,
+    NotificationStyle, PushNotification,
 };
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use fcm_v1::{android::AndroidConfig, apns::ApnsConfig, message::Message};
 
 use mockall::predicate::*;
 use mockall::*;
 
+use crate::{oauth, send_limiter::SendLimiter};
+
+/// Base URL of the FCM HTTP v1 API
+pub const DEFAULT_FCM_BASE_URL: &str = "https://fcm.googleapis.com";
+
+/// Settings for the requests to FCM
+#[derive(Clone, Debug)]
+pub struct FcmClientConfig {
+    pub request_timeout: Duration,
+    /// Base URL of the FCM HTTP v1 API, the messages are sent to `{fcm_base_url}/v1/projects/{project}/messages:send`
+    pub fcm_base_url: String,
+    /// OAuth token endpoint to use instead of the `token_uri` of the service account key
+    pub token_url: Option<String>,
+    /// Proxy for all the requests to FCM and to the OAuth token endpoint
+    pub proxy: Option<String>,
+    /// PEM files with additional root certificates to trust
+    pub tls_root_certificates: Vec<PathBuf>,
+    /// Whether to trust the root certificates bundled with the TLS implementation
+    pub tls_built_in_roots: bool,
+}
+
+impl Default for FcmClientConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(2),
+            fcm_base_url: String::from(DEFAULT_FCM_BASE_URL),
+            token_url: None,
+            proxy: None,
+            tls_root_certificates: vec![],
+            tls_built_in_roots: true,
+        }
+    }
+}
+
+impl FcmClientConfig {
+    /// Builds the HTTP client for the requests to FCM and to the OAuth token endpoint
+    pub fn http_client(&self) -> anyhow::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(self.request_timeout)
+            .tls_built_in_root_certs(self.tls_built_in_roots);
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(
+                reqwest::Proxy::all(proxy).with_context(|| format!("Invalid proxy {proxy}"))?,
+            );
+        }
+        for path in &self.tls_root_certificates {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read root certificate {path:?}"))?;
+            let certificate = reqwest::Certificate::from_pem(&pem)
+                .with_context(|| format!("Invalid root certificate {path:?}"))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// HTTP client for FCM and the OAuth token endpoint, built once from its config
+/// so that the connections are reused across sends
+#[derive(Clone, Debug)]
+pub struct FcmHttpClient {
+    pub config: FcmClientConfig,
+    pub client: reqwest::Client,
+}
+
+impl FcmHttpClient {
+    pub fn new(config: FcmClientConfig) -> anyhow::Result<Self> {
+        let client = config.http_client()?;
+        Ok(Self { config, client })
+    }
+}
+
 // We extract the actual calls to FCM to make our code testable
 #[automock]
 pub trait FcmClient {
     fn validate_fcm_project(
+        fcm_client: FcmHttpClient,
         fcm_project_id: String,
         service_account_key: ServiceAccountKey,
     ) -> impl std::future::Future<Output = anyhow::Result<()>> + Send;
 
     fn send_push_notification(
+        fcm_client: FcmHttpClient,
+        fcm_project_id: String,
+        service_account_key: ServiceAccountKey,
+        token: String,
+        push_notification: PushNotification,
+    ) -> impl std::future::Future<Output = anyhow::Result<()>> + Send;
+
+    /// Sends the push notifications of the same FCM project concurrently, with a single access token
+    /// and over the same connection, acquiring a permit from the send limiter for each of them
+    ///
+    /// Fails only if none of them could be sent, e.g. when the access token can't be fetched;
+    /// otherwise returns the result for each token, in the same order as the notifications
+    fn send_push_notification_batch(
+        fcm_client: FcmHttpClient,
+        fcm_project_id: String,
+        service_account_key: ServiceAccountKey,
+        notifications: Vec<(String, PushNotification)>,
+        send_limiter: SendLimiter,
+    ) -> impl std::future::Future<Output = anyhow::Result<Vec<anyhow::Result<()>>>> + Send;
+
+    /// Validates the push notification with FCM without delivering it
+    fn validate_push_notification(
+        fcm_client: FcmHttpClient,
         fcm_project_id: String,
         service_account_key: ServiceAccountKey,
         token: String,
@@ -30,110 +129,448 @@ pub struct RealFcmClient;
 
 impl FcmClient for RealFcmClient {
     async fn validate_fcm_project(
+        fcm_client: FcmHttpClient,
         fcm_project_id: String,
         service_account_key: ServiceAccountKey,
     ) -> anyhow::Result<()> {
-        let auth = Authenticator::service_account::<String>(service_account_key).await?;
+        let mut message = build_message(PushNotification {
+            title: String::from("This is a test notification"),
+            body: String::from("This is a test notification"),
+            ..Default::default()
+        });
+        message["topic"] = json!("test");
+
+        send_message(
+            &fcm_client,
+            &fcm_project_id,
+            &service_account_key,
+            &message,
+            true,
+        )
+        .await
+    }
 
-        let client = Client::new(auth, fcm_project_id, true, Duration::from_secs(2));
+    async fn send_push_notification(
+        fcm_client: FcmHttpClient,
+        fcm_project_id: String,
+        service_account_key: fcm_v1::auth::ServiceAccountKey,
+        token: String,
+        push_notification: PushNotification,
+    ) -> anyhow::Result<()> {
+        let mut message = build_message(push_notification);
+        message["token"] = json!(token);
 
-        let mut message = Message::default();
+        log::info!("Sending push notification.");
 
-        let mut map = HashMap::new();
-        map.insert(
-            "title".to_string(),
-            Value::String(String::from("This is a test notification")),
-        );
-        map.insert(
-            "body".to_string(),
-            Value::String(String::from("This is a test notification")),
-        );
-        message.data = Some(map.clone());
-        let mut apns_config = ApnsConfig::default();
+        send_message(
+            &fcm_client,
+            &fcm_project_id,
+            &service_account_key,
+            &message,
+            false,
+        )
+        .await?;
 
-        let mut alert_data = Map::new();
-        alert_data.insert(
-            "title".to_string(),
-            Value::String(String::from("This is a test notification")),
-        );
-        alert_data.insert(
-            "body".to_string(),
-            Value::String(String::from("This is a test notification")),
-        );
+        log::info!("Push notification sent.");
 
-        let mut aps_data = Map::new();
-        aps_data.insert("alert".to_string(), Value::Object(alert_data.clone()));
-        aps_data.insert("mutable-content".to_string(), Value::Number(1.into()));
-        let mut apns_data = HashMap::new();
-        apns_data.insert("aps".to_string(), Value::Object(aps_data));
-        apns_config.payload = Some(apns_data);
+        Ok(())
+    }
 
-        message.apns = Some(apns_config);
+    async fn send_push_notification_batch(
+        fcm_client: FcmHttpClient,
+        fcm_project_id: String,
+        service_account_key: fcm_v1::auth::ServiceAccountKey,
+        notifications: Vec<(String, PushNotification)>,
+        send_limiter: SendLimiter,
+    ) -> anyhow::Result<Vec<anyhow::Result<()>>> {
+        let access_token = access_token(&fcm_client, &service_account_key).await?;
 
-        let mut android_config = AndroidConfig::default();
-        android_config.data = Some(map);
+        log::info!("Sending {} push notifications.", notifications.len());
 
-        message.android = Some(android_config);
-        message.topic = Some(String::from("test"));
+        let sends = notifications.into_iter().map(|(token, push_notification)| {
+            let mut message = build_message(push_notification);
+            message["token"] = json!(token);
+            let fcm_client = &fcm_client;
+            let fcm_project_id = &fcm_project_id;
+            let access_token = &access_token;
+            let send_limiter = &send_limiter;
+            async move {
+                let _permit = send_limiter.acquire().await?;
+                post_message(fcm_client, access_token, fcm_project_id, &message, false).await
+            }
+        });
+        let results = futures::future::join_all(sends).await;
 
-        client.send(&message).await?;
+        log::info!(
+            "{} of {} push notifications sent.",
+            results.iter().filter(|result| result.is_ok()).count(),
+            results.len()
+        );
 
-        Ok(())
+        Ok(results)
     }
 
-    async fn send_push_notification(
+    async fn validate_push_notification(
+        fcm_client: FcmHttpClient,
         fcm_project_id: String,
         service_account_key: fcm_v1::auth::ServiceAccountKey,
         token: String,
         push_notification: PushNotification,
     ) -> anyhow::Result<()> {
-        let auth = Authenticator::service_account::<String>(service_account_key).await?;
+        let mut message = build_message(push_notification);
+        message["token"] = json!(token);
 
-        let client = Client::new(auth, fcm_project_id, false, Duration::from_secs(2));
+        send_message(
+            &fcm_client,
+            &fcm_project_id,
+            &service_account_key,
+            &message,
+            true,
+        )
+        .await
+    }
+}
 
-        let mut message = Message::default();
+async fn send_message(
+    fcm_client: &FcmHttpClient,
+    fcm_project_id: &str,
+    service_account_key: &ServiceAccountKey,
+    message: &Value,
+    validate_only: bool,
+) -> anyhow::Result<()> {
+    let access_token = access_token(fcm_client, service_account_key).await?;
+    post_message(
+        fcm_client,
+        &access_token,
+        fcm_project_id,
+        message,
+        validate_only,
+    )
+    .await
+}
 
-        let mut map = HashMap::new();
-        map.insert(
-            "title".to_string(),
-            Value::String(push_notification.title.clone()),
-        );
-        map.insert(
-            "body".to_string(),
-            Value::String(push_notification.body.clone()),
-        );
-        message.data = Some(map.clone());
-        let mut apns_config = ApnsConfig::default();
+async fn access_token(
+    fcm_client: &FcmHttpClient,
+    service_account_key: &ServiceAccountKey,
+) -> anyhow::Result<String> {
+    let token_url = fcm_client
+        .config
+        .token_url
+        .clone()
+        .unwrap_or(service_account_key.token_uri.clone());
+    oauth::fetch_access_token(&fcm_client.client, service_account_key, &token_url).await
+}
 
-        let mut alert_data = Map::new();
-        alert_data.insert(
-            "title".to_string(),
-            Value::String(push_notification.title.clone()),
-        );
-        alert_data.insert(
-            "body".to_string(),
-            Value::String(push_notification.body.clone()),
-        );
+async fn post_message(
+    fcm_client: &FcmHttpClient,
+    access_token: &str,
+    fcm_project_id: &str,
+    message: &Value,
+    validate_only: bool,
+) -> anyhow::Result<()> {
+    let url = format!(
+        "{}/v1/projects/{fcm_project_id}/messages:send",
+        fcm_client.config.fcm_base_url.trim_end_matches('/')
+    );
+    let response = fcm_client
+        .client
+        .post(&url)
+        .bearer_auth(access_token)
+        .json(&json!({
+            "message": message,
+            "validate_only": validate_only,
+        }))
+        .send()
+        .await
+        .context("Failed to send the message to FCM")?;
 
-        let mut aps_data = Map::new();
-        aps_data.insert("alert".to_string(), Value::Object(alert_data.clone()));
-        aps_data.insert("mutable-content".to_string(), Value::Number(1.into()));
-        let mut apns_data = HashMap::new();
-        apns_data.insert("aps".to_string(), Value::Object(aps_data));
-        apns_config.payload = Some(apns_data);
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body: Value = response.json().await.unwrap_or_default();
+    Err(fcm_error(status, &body))
+}
 
-        message.apns = Some(apns_config);
+/// Error codes of the FCM HTTP v1 API, see https://firebase.google.com/docs/reference/fcm/rest/v1/ErrorCode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FcmErrorCode {
+    Unregistered,
+    InvalidArgument,
+    SenderIdMismatch,
+    QuotaExceeded,
+    Unavailable,
+    Internal,
+    ThirdPartyAuthError,
+}
 
-        let mut android_config = AndroidConfig::default();
-        android_config.data = Some(map);
+impl FcmErrorCode {
+    fn parse(code: &str) -> Option<Self> {
+        match code {
+            "UNREGISTERED" => Some(Self::Unregistered),
+            "INVALID_ARGUMENT" => Some(Self::InvalidArgument),
+            "SENDER_ID_MISMATCH" => Some(Self::SenderIdMismatch),
+            "QUOTA_EXCEEDED" => Some(Self::QuotaExceeded),
+            "UNAVAILABLE" => Some(Self::Unavailable),
+            "INTERNAL" => Some(Self::Internal),
+            "THIRD_PARTY_AUTH_ERROR" => Some(Self::ThirdPartyAuthError),
+            _ => None,
+        }
+    }
+}
 
-        message.android = Some(android_config);
-        message.token = Some(token);
+/// Error response from FCM
+#[derive(Debug, Clone)]
+pub struct FcmError {
+    pub status: reqwest::StatusCode,
+    /// The `errorCode` of the FCM error details, if FCM gave one
+    pub code: Option<FcmErrorCode>,
+    /// The raw `errorCode`, or the `status` of the error (e.g. `PERMISSION_DENIED`) if there is none
+    pub code_name: String,
+    pub message: String,
+}
 
-        log::info!("Sending push notification.");
+impl std::fmt::Display for FcmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "FCM error {} ({}): {}",
+            self.code_name, self.status, self.message
+        )
+    }
+}
 
-        client.send(&message).await?;
+impl std::error::Error for FcmError {}
 
-        Ok(())
+fn fcm_error(status: reqwest::StatusCode, body: &Value) -> anyhow::Error {
+    let error = &body["error"];
+    let error_code = error["details"].as_array().and_then(|details| {
+        details
+            .iter()
+            .find_map(|detail| detail["errorCode"].as_str())
+    });
+    let status_name = error["status"].as_str().unwrap_or_default();
+
+    anyhow::Error::new(FcmError {
+        status,
+        code: error_code.and_then(FcmErrorCode::parse),
+        code_name: error_code.unwrap_or(status_name).to_string(),
+        message: error["message"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+/// Builds the FCM `Message` resource, without its target
+fn build_message(push_notification: PushNotification) -> Value {
+    let mut message = Message::default();
+
+    let mut map = HashMap::new();
+    map.insert(
+        "title".to_string(),
+        Value::String(push_notification.title.clone()),
+    );
+    map.insert(
+        "body".to_string(),
+        Value::String(push_notification.body.clone()),
+    );
+    message.data = Some(map.clone());
+    let mut apns_config = ApnsConfig::default();
+
+    let mut alert_data = Map::new();
+    alert_data.insert(
+        "title".to_string(),
+        Value::String(push_notification.title.clone()),
+    );
+    alert_data.insert(
+        "body".to_string(),
+        Value::String(push_notification.body.clone()),
+    );
+
+    let mut aps_data = Map::new();
+    aps_data.insert("alert".to_string(), Value::Object(alert_data.clone()));
+    aps_data.insert("mutable-content".to_string(), Value::Number(1.into()));
+    if let Some(ios) = &push_notification.ios {
+        insert_ios_options(&mut aps_data, ios);
+    }
+    let mut apns_data = HashMap::new();
+    apns_data.insert("aps".to_string(), Value::Object(aps_data));
+    apns_config.payload = Some(apns_data);
+
+    message.apns = Some(apns_config);
+
+    let mut android_config = AndroidConfig::default();
+    android_config.data = Some(map);
+
+    message.android = Some(android_config);
+
+    let mut message = json!(message);
+    if let Some(android) = &push_notification.android {
+        insert_android_options(&mut message["android"], &push_notification, android);
+    }
+    if let Some(style) = &push_notification.style {
+        apply_notification_style(&mut message, &push_notification, style);
+    }
+    if let Some(badge) = push_notification.badge {
+        let background = matches!(push_notification.style, Some(NotificationStyle::DataOnly));
+        insert_badge(&mut message, badge, background);
     }
+    message
+}
+
+/// Shows the badge on the app icon on iOS and on Android, and delivers it as data to the app
+///
+/// Background notifications on iOS only deliver it as data, as they can't update the app icon
+fn insert_badge(message: &mut Value, badge: u32, background: bool) {
+    if !background {
+        message["apns"]["payload"]["aps"]["badge"] = json!(badge);
+    }
+
+    if let Some(android_notification) = message["android"]
+        .get_mut("notification")
+        .and_then(Value::as_object_mut)
+    {
+        android_notification
+            .entry("notification_count")
+            .or_insert(json!(badge));
+    }
+
+    // The values of the FCM data must be strings
+    if message["data"].is_object() {
+        message["data"]["badge"] = json!(badge.to_string());
+    }
+    if message["android"]["data"].is_object() {
+        message["android"]["data"]["badge"] = json!(badge.to_string());
+    }
+}
+
+/// Adjusts the message so that every platform displays the notification, only delivers its data
+/// to the app, or both
+fn apply_notification_style(
+    message: &mut Value,
+    push_notification: &PushNotification,
+    style: &NotificationStyle,
+) {
+    let data = json!({
+        "title": push_notification.title,
+        "body": push_notification.body,
+    });
+    let display = matches!(style, NotificationStyle::Display | NotificationStyle::Both);
+    let data_only = matches!(style, NotificationStyle::DataOnly);
+
+    if display {
+        let android_notification = &mut message["android"]["notification"];
+        if !android_notification.is_object() {
+            *android_notification = json!({});
+        }
+        android_notification["title"] = data["title"].clone();
+        android_notification["body"] = data["body"].clone();
+        message["webpush"]["notification"] = data.clone();
+    }
+    if matches!(style, NotificationStyle::Display) {
+        remove_field(message, "data");
+        remove_field(&mut message["android"], "data");
+    } else {
+        message["webpush"]["data"] = data;
+    }
+    if data_only {
+        remove_field(&mut message["android"], "notification");
+        // A background notification wakes the app up without showing anything, so it can't carry
+        // the fields that alert the user, keeping the iOS options like the category or the thread id
+        let aps = &mut message["apns"]["payload"]["aps"];
+        for field in [
+            "alert",
+            "mutable-content",
+            "sound",
+            "interruption-level",
+            "relevance-score",
+        ] {
+            remove_field(aps, field);
+        }
+        aps["content-available"] = json!(1);
+        message["apns"]["headers"] = json!({
+            "apns-push-type": "background",
+            "apns-priority": "5",
+        });
+    }
+}
+
+fn remove_field(value: &mut Value, field: &str) {
+    if let Some(object) = value.as_object_mut() {
+        object.remove(field);
+    }
+}
+
+fn insert_ios_options(aps_data: &mut Map<String, Value>, ios: &IosNotificationOptions) {
+    if let Some(category) = &ios.category {
+        aps_data.insert("category".to_string(), Value::String(category.clone()));
+    }
+    if let Some(thread_id) = &ios.thread_id {
+        aps_data.insert("thread-id".to_string(), Value::String(thread_id.clone()));
+    }
+    if let Some(interruption_level) = &ios.interruption_level {
+        aps_data.insert("interruption-level".to_string(), json!(interruption_level));
+    }
+    if let Some(relevance_score) = ios.relevance_score {
+        aps_data.insert(
+            "relevance-score".to_string(),
+            json!(relevance_score.clamp(0.0, 1.0)),
+        );
+    }
+    if let Some(target_content_id) = &ios.target_content_id {
+        aps_data.insert(
+            "target-content-id".to_string(),
+            Value::String(target_content_id.clone()),
+        );
+    }
+}
+
+/// Only adds an `AndroidNotification`, which makes the system display the notification,
+/// if any of its fields are set: otherwise the message stays data-only
+fn insert_android_options(
+    android_config: &mut Value,
+    push_notification: &PushNotification,
+    android: &AndroidNotificationOptions,
+) {
+    if android.direct_boot_ok {
+        android_config["direct_boot_ok"] = json!(true);
+    }
+
+    let mut notification = Map::new();
+    if let Some(channel_id) = &android.channel_id {
+        notification.insert("channel_id".to_string(), json!(channel_id));
+    }
+    if let Some(tag) = &android.tag {
+        notification.insert("tag".to_string(), json!(tag));
+    }
+    if let Some(priority) = &android.notification_priority {
+        let priority = match priority {
+            AndroidNotificationPriority::Min => "PRIORITY_MIN",
+            AndroidNotificationPriority::Low => "PRIORITY_LOW",
+            AndroidNotificationPriority::Default => "PRIORITY_DEFAULT",
+            AndroidNotificationPriority::High => "PRIORITY_HIGH",
+            AndroidNotificationPriority::Max => "PRIORITY_MAX",
+        };
+        notification.insert("notification_priority".to_string(), json!(priority));
+    }
+    if let Some(visibility) = &android.visibility {
+        notification.insert("visibility".to_string(), json!(visibility));
+    }
+    if let Some(icon) = &android.icon {
+        notification.insert("icon".to_string(), json!(icon));
+    }
+    if let Some(color) = &android.color {
+        notification.insert("color".to_string(), json!(color));
+    }
+    if android.sticky {
+        notification.insert("sticky".to_string(), json!(true));
+    }
+    if let Some(notification_count) = android.notification_count {
+        notification.insert("notification_count".to_string(), json!(notification_count));
+    }
+    if notification.is_empty() {
+        return;
+    }
+
+    notification.insert("title".to_string(), json!(push_notification.title));
+    notification.insert("body".to_string(), json!(push_notification.body));
+    android_config["notification"] = Value::Object(notification);
 }
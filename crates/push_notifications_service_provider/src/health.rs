@@ -0,0 +1,216 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use holochain_client::{AdminWebsocket, AppWebsocket, ExternIO, ZomeCallTarget};
+use holochain_runtime::HolochainRuntime;
+use holochain_types::prelude::{AgentPubKey, CellInfo};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::{metrics, SERVICES_ROLE_NAME};
+
+/// Maximum time to wait for the conductor to answer the liveness checks
+const LIVENESS_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Status {
+    app_installed: bool,
+    /// Connection of the provider to its app, reused by the liveness probe
+    app_ws: Option<AppWebsocket>,
+    announced_as_provider: bool,
+    peers: usize,
+    last_reconcile: Option<Instant>,
+}
+
+/// Status of the provider as observed by its background tasks, shared with the health server.
+#[derive(Clone, Default)]
+pub struct ProviderStatus(Arc<RwLock<Status>>);
+
+impl ProviderStatus {
+    pub fn set_app_installed(&self, app_ws: AppWebsocket) {
+        if let Ok(mut status) = self.0.write() {
+            status.app_installed = true;
+            status.app_ws = Some(app_ws);
+        }
+    }
+
+    pub fn record_reconcile_success(&self) {
+        if let Ok(mut status) = self.0.write() {
+            status.last_reconcile = Some(Instant::now());
+        }
+    }
+
+    /// Queries the conductor for the state of the network and the cloned cells,
+    /// updating the status and the related metrics
+    pub async fn refresh(&self, admin_ws: &AdminWebsocket, app_ws: &AppWebsocket) -> Result<()> {
+        let Some(app_info) = app_ws.app_info().await? else {
+            return Err(anyhow!("App is not installed"));
+        };
+
+        let cloned_cells = app_info
+            .cell_info
+            .get(SERVICES_ROLE_NAME)
+            .map(|cells| {
+                cells
+                    .iter()
+                    .filter(|cell| matches!(cell, CellInfo::Cloned(_)))
+                    .count()
+            })
+            .unwrap_or_default();
+        metrics::CLONED_SERVICE_CELLS.set(cloned_cells as i64);
+
+        // The conductor also knows about the agent infos of our own cells
+        let own_cells: usize = app_info.cell_info.values().map(|cells| cells.len()).sum();
+        let known_agents = admin_ws.agent_info(None).await?.len();
+        let peers = known_agents.saturating_sub(own_cells);
+        metrics::CONNECTED_PEERS.set(peers as i64);
+
+        let providers: Vec<AgentPubKey> = app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName(SERVICES_ROLE_NAME.into()),
+                "service_providers".into(),
+                "get_providers_for_service".into(),
+                ExternIO::encode(
+                    push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+                )?,
+            )
+            .await?
+            .decode()?;
+        let announced_as_provider = providers.contains(&app_info.agent_pub_key);
+
+        if let Ok(mut status) = self.0.write() {
+            status.peers = peers;
+            status.announced_as_provider = announced_as_provider;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct HealthState {
+    runtime: HolochainRuntime,
+    /// Opened by the first liveness probe and reused by the next ones, until it fails
+    admin_ws: Arc<Mutex<Option<AdminWebsocket>>>,
+    status: ProviderStatus,
+    reconcile_max_age: Duration,
+}
+
+/// Serves the liveness probe at `/healthz` and the readiness probe at `/readyz`
+///
+/// The provider is not ready if the cloned cells haven't been reconciled successfully
+/// within `reconcile_max_age`
+pub async fn serve_health(
+    port: u16,
+    runtime: HolochainRuntime,
+    status: ProviderStatus,
+    reconcile_max_age: Duration,
+) -> Result<()> {
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(HealthState {
+            runtime,
+            admin_ws: Arc::new(Mutex::new(None)),
+            status,
+            reconcile_max_age,
+        });
+    let listener = tokio::net::TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port))).await?;
+    log::info!("Serving health checks at port {port}.");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn healthz(State(state): State<HealthState>) -> (StatusCode, Json<Value>) {
+    let conductor_alive = {
+        let mut admin_ws = state.admin_ws.lock().await;
+        let conductor = tokio::time::timeout(LIVENESS_TIMEOUT, async {
+            if admin_ws.is_none() {
+                *admin_ws = Some(state.runtime.admin_websocket().await?);
+            }
+            if let Some(admin_ws) = admin_ws.as_ref() {
+                admin_ws.list_apps(None).await?;
+            }
+            anyhow::Ok(())
+        })
+        .await;
+        let conductor_alive = matches!(conductor, Ok(Ok(())));
+        if !conductor_alive {
+            // Reconnects on the next probe, in case the connection itself is broken
+            *admin_ws = None;
+        }
+        conductor_alive
+    };
+
+    let app_ws = state
+        .status
+        .0
+        .read()
+        .ok()
+        .and_then(|status| status.app_ws.clone());
+
+    // The app websocket can only be checked once the app has been installed by setup
+    let app_websocket_alive = match app_ws {
+        Some(app_ws) => {
+            let app_websocket = tokio::time::timeout(LIVENESS_TIMEOUT, app_ws.app_info()).await;
+            Some(matches!(app_websocket, Ok(Ok(_))))
+        }
+        None => None,
+    };
+
+    let healthy = conductor_alive && app_websocket_alive.unwrap_or(true);
+    let status_code = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status_code,
+        Json(json!({
+            "healthy": healthy,
+            "conductor": conductor_alive,
+            "app_websocket": app_websocket_alive,
+        })),
+    )
+}
+
+async fn readyz(State(state): State<HealthState>) -> (StatusCode, Json<Value>) {
+    let Ok(status) = state.status.0.read() else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "ready": false })),
+        );
+    };
+
+    let last_reconcile_age = status.last_reconcile.map(|instant| instant.elapsed());
+    let reconciled_recently = last_reconcile_age
+        .map(|age| age < state.reconcile_max_age)
+        .unwrap_or(false);
+
+    let ready = status.app_installed
+        && status.announced_as_provider
+        && status.peers > 0
+        && reconciled_recently;
+    let status_code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status_code,
+        Json(json!({
+            "ready": ready,
+            "app_installed": status.app_installed,
+            "announced_as_provider": status.announced_as_provider,
+            "peers": status.peers,
+            "last_reconcile_seconds_ago": last_reconcile_age.map(|age| age.as_secs()),
+        })),
+    )
+}
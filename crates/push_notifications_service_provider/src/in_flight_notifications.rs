@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use push_notifications_types::SendPushNotificationSignal;
+use tokio::{sync::Notify, time::Instant};
+
+/// Push notifications currently being sent to FCM, tracked so that the provider can wait
+/// for them to finish before shutting down.
+#[derive(Clone)]
+pub struct InFlightNotifications {
+    accepting: Arc<AtomicBool>,
+    next_id: Arc<AtomicU64>,
+    notifications: Arc<Mutex<HashMap<u64, SendPushNotificationSignal>>>,
+    finished: Arc<Notify>,
+}
+
+impl Default for InFlightNotifications {
+    fn default() -> Self {
+        Self {
+            accepting: Arc::new(AtomicBool::new(true)),
+            next_id: Arc::new(AtomicU64::new(0)),
+            notifications: Arc::new(Mutex::new(HashMap::new())),
+            finished: Arc::new(Notify::new()),
+        }
+    }
+}
+
+/// Marks the notification as finished when dropped.
+pub struct InFlightGuard {
+    id: u64,
+    in_flight: InFlightNotifications,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if let Ok(mut notifications) = self.in_flight.notifications.lock() {
+            notifications.remove(&self.id);
+        }
+        self.in_flight.finished.notify_waiters();
+    }
+}
+
+impl InFlightNotifications {
+    /// Whether new notifications should be sent right away, false once the shutdown has started.
+    pub fn is_accepting(&self) -> bool {
+        self.accepting.load(Ordering::SeqCst)
+    }
+
+    pub fn stop_accepting(&self) {
+        self.accepting.store(false, Ordering::SeqCst);
+    }
+
+    /// Tracks the notification until the returned guard is dropped.
+    pub fn start(&self, signal: &SendPushNotificationSignal) -> InFlightGuard {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        if let Ok(mut notifications) = self.notifications.lock() {
+            notifications.insert(id, signal.clone());
+        }
+        InFlightGuard {
+            id,
+            in_flight: self.clone(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.notifications
+            .lock()
+            .map(|notifications| notifications.len())
+            .unwrap_or_default()
+    }
+
+    /// Waits for all the in-flight notifications to finish, up to the given timeout.
+    ///
+    /// Returns the notifications that were still in flight when the timeout expired.
+    pub async fn drain(&self, timeout: Duration) -> Vec<SendPushNotificationSignal> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Created before checking the count so that no notification can be missed
+            let finished = self.finished.notified();
+            if self.count() == 0 {
+                return vec![];
+            }
+            if tokio::time::timeout_at(deadline, finished).await.is_err() {
+                return self
+                    .notifications
+                    .lock()
+                    .map(|notifications| notifications.values().cloned().collect())
+                    .unwrap_or_default();
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::Result;
+use holochain_client::{AppWebsocket, ExternIO, ZomeCallTarget};
+use push_notifications_types::PendingServiceAccountKey;
+
+use crate::fcm_client::{FcmClient, FcmHttpClient};
+
+/// Acknowledges the pending service account keys that this provider can use to send
+/// push notifications, and activates the rotations that are ready to be finalized
+pub async fn acknowledge_pending_service_account_keys<T: FcmClient>(
+    app_ws: &AppWebsocket,
+    fcm_client: &FcmHttpClient,
+) -> Result<()> {
+    let pending_keys: Vec<PendingServiceAccountKey> = app_ws
+        .call_zome(
+            ZomeCallTarget::RoleName("push_notifications_service".into()),
+            "push_notifications_service".into(),
+            "get_pending_service_account_keys".into(),
+            ExternIO::encode(())?,
+        )
+        .await?
+        .decode()?;
+
+    for pending_key in pending_keys {
+        if let Err(err) = T::validate_fcm_project(
+            fcm_client.clone(),
+            pending_key.fcm_project_id.clone(),
+            crate::into(pending_key.service_account_key),
+        )
+        .await
+        {
+            log::warn!(
+                "Pending service account key for project {} is not valid yet: {err:?}",
+                pending_key.fcm_project_id
+            );
+            continue;
+        }
+
+        app_ws
+            .call_zome(
+                ZomeCallTarget::RoleName("push_notifications_service".into()),
+                "push_notifications_service".into(),
+                "acknowledge_service_account_key".into(),
+                ExternIO::encode(pending_key.key_hash)?,
+            )
+            .await?;
+        log::info!(
+            "Acknowledged pending service account key for project {}.",
+            pending_key.fcm_project_id
+        );
+    }
+
+    app_ws
+        .call_zome(
+            ZomeCallTarget::RoleName("push_notifications_service".into()),
+            "push_notifications_service".into(),
+            "finalize_service_account_key_rotations".into(),
+            ExternIO::encode(())?,
+        )
+        .await?;
+
+    Ok(())
+}
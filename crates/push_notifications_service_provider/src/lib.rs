@@ -1,35 +1,119 @@
 use anyhow::{anyhow, Result};
 use clone_manager_types::{CloneRequest, NewCloneRequest};
 use clone_manager_utils::reconcile_cloned_cells;
+use config::ProviderConfig;
 use holochain_client::{AdminWebsocket, AppWebsocket};
 use holochain_runtime::*;
 use holochain_types::prelude::*;
-use push_notifications_types::SendPushNotificationSignal;
+use push_notifications_types::{
+    CancelScheduledPushNotificationSignal, PrepareScheduledPushNotificationInput, PushNotification,
+    RecordSentPushNotificationInput, ReportTestPushNotificationResultInput,
+    SendPushNotificationBatchSignal, SendPushNotificationSignal, SendTestPushNotificationSignal,
+    ServiceAccountKey, TestPushNotificationResult,
+};
+use scheduled_notifications::{
+    ScheduledNotification, ScheduledNotifications, MAX_SCHEDULED_SEND_ATTEMPTS,
+};
+use send_limiter::SendLimiter;
+use sent_notifications::SentNotifications;
 use setup::setup;
-use std::{fs, path::PathBuf, time::Duration};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tracing::Instrument;
 use utils::with_retries;
 
+pub mod config;
 pub mod fcm_client;
+pub mod health;
+pub mod in_flight_notifications;
+pub mod key_rotation;
+pub mod metrics;
+mod oauth;
 mod utils;
-use fcm_client::FcmClient;
+use fcm_client::{FcmClient, FcmError, FcmHttpClient};
+use health::ProviderStatus;
+use in_flight_notifications::InFlightNotifications;
+pub mod scheduled_notifications;
+pub mod send_limiter;
+pub mod sent_notifications;
 mod setup;
 
 pub const SERVICES_ROLE_NAME: &'static str = "services";
 
-pub async fn run<T: FcmClient>(
+/// How long the idempotency keys of sent notifications are remembered
+pub const SENT_NOTIFICATIONS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Local state of the provider. The notification queues are persisted in its data dir.
+#[derive(Clone)]
+pub struct ProviderState {
+    pub config: ProviderConfig,
+    /// Shared by all the requests to FCM, to reuse its connections
+    pub fcm_client: FcmHttpClient,
+    pub scheduled_notifications: ScheduledNotifications,
+    pub sent_notifications: SentNotifications,
+    pub send_limiter: SendLimiter,
+    pub in_flight_notifications: InFlightNotifications,
+}
+
+impl ProviderState {
+    pub fn load(data_dir: &PathBuf, config: ProviderConfig) -> Result<Self> {
+        Ok(Self {
+            fcm_client: FcmHttpClient::new(config.fcm_client_config())?,
+            send_limiter: SendLimiter::new(
+                config.fcm.max_concurrent_sends,
+                config.fcm.max_sends_per_second,
+            ),
+            config,
+            in_flight_notifications: InFlightNotifications::default(),
+            scheduled_notifications: ScheduledNotifications::load(
+                data_dir.join("scheduled_notifications.json"),
+            )?,
+            sent_notifications: SentNotifications::load(
+                data_dir.join("sent_notifications.json"),
+                SENT_NOTIFICATIONS_TTL,
+            )?,
+        })
+    }
+}
+
+pub async fn run<T: FcmClient + 'static>(
     data_dir: PathBuf,
-    network_config: NetworkConfig,
     app_id: String,
     push_notifications_service_provider_happ_path: PathBuf,
     progenitors: Vec<AgentPubKey>,
-    mdns_discovery: bool,
-    admin_port: Option<u16>
+    config: ProviderConfig,
 ) -> anyhow::Result<()> {
-    let mut config = HolochainRuntimeConfig::new(data_dir.clone(), network_config);
-    config.mdns_discovery = mdns_discovery;
-    config.admin_port = admin_port;
+    let metrics_abort_handle = config.metrics.port.map(|port| {
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve_metrics(port).await {
+                log::error!("Failed to serve metrics: {err:?}");
+            }
+        })
+        .abort_handle()
+    });
+
+    let mut runtime_config = HolochainRuntimeConfig::new(data_dir.clone(), config.network_config());
+    runtime_config.mdns_discovery = config.network.mdns_discovery;
+    runtime_config.admin_port = config.network.admin_port;
+
+    let runtime = HolochainRuntime::launch(vec_to_locked(vec![]), runtime_config).await?;
+
+    let status = ProviderStatus::default();
+    let reconcile_max_age = Duration::from_secs(config.health.reconcile_max_age_secs);
+    let health_abort_handle = config.health.port.map(|port| {
+        let runtime = runtime.clone();
+        let status = status.clone();
+        tokio::spawn(async move {
+            if let Err(err) = health::serve_health(port, runtime, status, reconcile_max_age).await {
+                log::error!("Failed to serve health checks: {err:?}");
+            }
+        })
+        .abort_handle()
+    });
 
-    let runtime = HolochainRuntime::launch(vec_to_locked(vec![]), config).await?;
     setup(
         &runtime,
         &app_id,
@@ -41,20 +125,43 @@ pub async fn run<T: FcmClient>(
     let app_ws = runtime
         .app_websocket(app_id.clone(), holochain_client::AllowedOrigins::Any)
         .await?;
+    status.set_app_installed(app_ws.clone());
+    // Cells installed by older versions of the provider don't grant it in their init
+    app_ws
+        .call_zome(
+            holochain_client::ZomeCallTarget::RoleName(String::from("push_notifications_service")),
+            "push_notifications_service".into(),
+            "ensure_test_push_notification_capability".into(),
+            ExternIO::encode(())?,
+        )
+        .await?;
     let app_clone = app_ws.clone();
+    let scheduler_app_ws = app_ws.clone();
     let admin_ws = runtime.admin_websocket().await?;
 
+    let reconcile_interval = Duration::from_secs(config.reconcile.interval_secs);
+    let drain_timeout = Duration::from_secs(config.shutdown.drain_timeout_secs);
+    let state = ProviderState::load(&data_dir, config)?;
+    let reconcile_fcm_client = state.fcm_client.clone();
+    let s = state.clone();
+    let shutdown_state = state.clone();
+
     app_ws
         .on_signal(move |signal| {
-            let Signal::App { signal, .. } = signal else {
+            let Signal::App {
+                cell_id, signal, ..
+            } = signal
+            else {
                 return ();
             };
 
             let app_ws = &app_clone;
             let admin_ws = &admin_ws;
+            let state = &s;
 
             holochain_util::tokio_helper::run_on(async move {
-                if let Err(err) = handle_signal::<T>(admin_ws, app_ws, signal).await {
+                if let Err(err) = handle_signal::<T>(admin_ws, app_ws, state, cell_id, signal).await
+                {
                     log::error!("Failed to handle signal: {err:?}");
                 }
             });
@@ -63,6 +170,44 @@ pub async fn run<T: FcmClient>(
 
     log::info!("Starting push notifications service provider.");
 
+    let scheduler = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+
+            // The scheduled notifications stay persisted while shutting down
+            if !state.in_flight_notifications.is_accepting() {
+                break;
+            }
+
+            let due = match state.scheduled_notifications.take_due(Timestamp::now()) {
+                Ok(due) => due,
+                Err(err) => {
+                    log::error!("Failed to get the scheduled notifications: {err}");
+                    continue;
+                }
+            };
+            if let Ok(count) = state.scheduled_notifications.count() {
+                metrics::SCHEDULED_QUEUE_DEPTH.set(count as i64);
+            }
+            let mut due = due.into_iter();
+            while let Some(notification) = due.next() {
+                // Put back the rest of the batch if the shutdown started while sending it
+                if !state.in_flight_notifications.is_accepting() {
+                    for notification in std::iter::once(notification).chain(due.by_ref()) {
+                        if let Err(err) = state.scheduled_notifications.schedule(notification) {
+                            log::error!("Failed to persist scheduled push notification: {err:?}");
+                        }
+                    }
+                    break;
+                }
+                let span = scheduled_notification_span(&notification);
+                send_scheduled_push_notification::<T>(&scheduler_app_ws, &state, notification)
+                    .instrument(span)
+                    .await;
+            }
+        }
+    });
+
     let r = runtime.clone();
     let abort_handle = tokio::spawn(async move {
         loop {
@@ -86,42 +231,115 @@ pub async fn run<T: FcmClient>(
             .await
             {
                 log::error!("Failed to reconcile cloned services: {err}");
+            } else {
+                status.record_reconcile_success();
+            }
+            if let Err(err) = status.refresh(&admin_ws, &app_ws).await {
+                log::error!("Failed to refresh the provider status: {err}");
+            }
+            if let Err(err) = key_rotation::acknowledge_pending_service_account_keys::<T>(
+                &app_ws,
+                &reconcile_fcm_client,
+            )
+            .await
+            {
+                log::error!("Failed to acknowledge the pending service account keys: {err}");
             }
 
-            std::thread::sleep(Duration::from_secs(60));
+            std::thread::sleep(reconcile_interval);
         }
     })
     .abort_handle();
 
-    // wait for a unix signal or ctrl-c instruction to
-    // shutdown holochain
-    ctrlc::set_handler(move || {
-        abort_handle.abort();
-        let r = r.clone();
-        holochain_util::tokio_helper::block_on(
-            async move {
-                log::info!("Gracefully shutting down conductor...");
-                if let Err(err) = r.shutdown().await {
-                    log::error!("Failed to shutdown conductor: {err:?}.");
-                }
-            },
-            Duration::from_secs(10),
-        )
-        .expect("Timed out shutting down holochain.");
-        std::process::exit(0);
-    })?;
+    wait_for_termination_signal().await;
 
-    // wait for a unix signal or ctrl-c instruction to
-    tokio::signal::ctrl_c()
+    log::info!(
+        "Received termination signal: waiting for the in-flight push notifications to finish..."
+    );
+    shutdown_state.in_flight_notifications.stop_accepting();
+    // A single deadline for the whole drain, shared by the scheduler and the in-flight notifications
+    let drain_deadline = tokio::time::Instant::now() + drain_timeout;
+    // Not aborted, so that the due notifications it has already taken are either sent or put back
+    if tokio::time::timeout_at(drain_deadline, scheduler)
         .await
-        .unwrap_or_else(|e| log::error!("Could not handle termination signal: {:?}", e));
+        .is_err()
+    {
+        log::warn!("The scheduler didn't stop after {drain_timeout:?}.");
+    }
+    let leftover = shutdown_state
+        .in_flight_notifications
+        .drain(drain_deadline.saturating_duration_since(tokio::time::Instant::now()))
+        .await;
+    if !leftover.is_empty() {
+        // They may still reach FCM before the process exits, in which case they will be sent twice
+        log::warn!(
+            "{} push notifications were still being sent after {drain_timeout:?}: persisting them to be sent on the next start.",
+            leftover.len()
+        );
+        for signal in leftover {
+            if let Err(err) = shutdown_state.scheduled_notifications.schedule(signal) {
+                log::error!("Failed to persist in-flight push notification: {err:?}");
+            }
+        }
+    }
+    match shutdown_state.scheduled_notifications.count() {
+        Ok(0) => {}
+        Ok(count) => log::info!(
+            "{count} scheduled push notifications are persisted and will be sent after the next start."
+        ),
+        Err(err) => log::error!("Failed to count the scheduled push notifications: {err:?}"),
+    }
+
+    abort_handle.abort();
+    if let Some(metrics_abort_handle) = &metrics_abort_handle {
+        metrics_abort_handle.abort();
+    }
+    if let Some(health_abort_handle) = &health_abort_handle {
+        health_abort_handle.abort();
+    }
+
+    log::info!("Gracefully shutting down conductor...");
+    match tokio::time::timeout(Duration::from_secs(10), r.shutdown()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => log::error!("Failed to shutdown conductor: {err:?}."),
+        Err(_) => log::error!("Timed out shutting down holochain."),
+    }
 
     Ok(())
 }
 
+/// Waits for SIGTERM or ctrl-c
+async fn wait_for_termination_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                log::error!("Could not handle SIGTERM: {err:?}");
+                std::future::pending::<()>().await
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            if let Err(err) = result {
+                log::error!("Could not handle termination signal: {err:?}");
+            }
+        }
+        _ = terminate => {}
+    }
+}
+
 pub async fn handle_signal<T: FcmClient>(
     admin_ws: &AdminWebsocket,
     app_ws: &AppWebsocket,
+    state: &ProviderState,
+    cell_id: CellId,
     signal: AppSignal,
 ) -> anyhow::Result<()> {
     if let Ok(send_push_notification_signal) = signal
@@ -129,24 +347,562 @@ pub async fn handle_signal<T: FcmClient>(
         .into_inner()
         .decode::<SendPushNotificationSignal>()
     {
-        T::send_push_notification(
-            send_push_notification_signal.fcm_project_id,
-            crate::into(send_push_notification_signal.service_account_key),
-            send_push_notification_signal.token,
-            send_push_notification_signal.notification,
+        metrics::SIGNALS_RECEIVED
+            .with_label_values(&["send_push_notification"])
+            .inc();
+        let span = notification_span(&send_push_notification_signal);
+        span.record("cell_id", tracing::field::debug(&cell_id));
+        handle_send_push_notification_signal::<T>(app_ws, state, send_push_notification_signal)
+            .instrument(span)
+            .await?;
+    }
+    if let Ok(batch_signal) = signal
+        .clone()
+        .into_inner()
+        .decode::<SendPushNotificationBatchSignal>()
+    {
+        metrics::SIGNALS_RECEIVED
+            .with_label_values(&["send_push_notification_batch"])
+            .inc();
+        handle_send_push_notification_batch_signal::<T>(app_ws, state, batch_signal).await?;
+    }
+    if let Ok(cancel_signal) = signal
+        .clone()
+        .into_inner()
+        .decode::<CancelScheduledPushNotificationSignal>()
+    {
+        metrics::SIGNALS_RECEIVED
+            .with_label_values(&["cancel_scheduled_push_notification"])
+            .inc();
+        if !state.scheduled_notifications.cancel(
+            &cancel_signal.provenance,
+            &cancel_signal.recipient,
+            &cancel_signal.id,
+        )? {
+            log::warn!(
+                "Scheduled push notification {} was not found.",
+                cancel_signal.id
+            );
+        }
+    }
+    if let Ok(test_signal) = signal
+        .clone()
+        .into_inner()
+        .decode::<SendTestPushNotificationSignal>()
+    {
+        metrics::SIGNALS_RECEIVED
+            .with_label_values(&["send_test_push_notification"])
+            .inc();
+        handle_send_test_push_notification_signal::<T>(app_ws, state, cell_id, test_signal).await?;
+    }
+    if let Ok(new_clone_request) = signal.into_inner().decode::<NewCloneRequest>() {
+        metrics::SIGNALS_RECEIVED
+            .with_label_values(&["new_clone_request"])
+            .inc();
+        handle_new_clone_request_signal(
+            admin_ws,
+            app_ws,
+            new_clone_request,
+            state.config.reconcile.clone_request_retries,
         )
         .await?;
     }
-    if let Ok(new_clone_request) = signal.into_inner().decode::<NewCloneRequest>() {
-        handle_new_clone_request_signal(admin_ws, app_ws, new_clone_request).await?;
+    Ok(())
+}
+
+async fn handle_send_push_notification_signal<T: FcmClient>(
+    app_ws: &AppWebsocket,
+    state: &ProviderState,
+    send_push_notification_signal: SendPushNotificationSignal,
+) -> Result<()> {
+    let idempotency_key = idempotency_key(&send_push_notification_signal);
+    if let Some(key) = &idempotency_key {
+        if !state.sent_notifications.claim(key.clone())? {
+            log::info!("Skipping push notification that was already sent.");
+            return Ok(());
+        }
     }
+
+    match send_push_notification_signal.send_at {
+        _ if !state.in_flight_notifications.is_accepting() => {
+            log::info!("Shutting down: persisting push notification to be sent on the next start.");
+            state
+                .scheduled_notifications
+                .schedule(send_push_notification_signal)?;
+        }
+        Some(send_at) if send_at > Timestamp::now() => {
+            log::info!("Scheduling push notification for {send_at}.");
+            state
+                .scheduled_notifications
+                .schedule(send_push_notification_signal)?;
+        }
+        _ => {
+            match send_push_notification::<T>(state, send_push_notification_signal.clone()).await {
+                Ok(()) => {
+                    record_sent_push_notifications(app_ws, &[send_push_notification_signal]).await
+                }
+                Err(err) => {
+                    // Logged here so that the error is recorded within the span of the notification
+                    log::error!("Failed to send push notification: {err:?}");
+                    if let Some(key) = &idempotency_key {
+                        state.sent_notifications.release(key)?;
+                    }
+                }
+            }
+        }
+    }
+
     Ok(())
 }
 
+async fn handle_send_push_notification_batch_signal<T: FcmClient>(
+    app_ws: &AppWebsocket,
+    state: &ProviderState,
+    batch_signal: SendPushNotificationBatchSignal,
+) -> Result<()> {
+    let mut to_send: Vec<SendPushNotificationSignal> = Vec::new();
+    for signal in batch_signal.into_signals() {
+        let span = notification_span(&signal);
+        let _entered = span.enter();
+        if let Some(key) = idempotency_key(&signal) {
+            if !state.sent_notifications.claim(key)? {
+                log::info!("Skipping push notification that was already sent.");
+                continue;
+            }
+        }
+
+        match signal.send_at {
+            _ if !state.in_flight_notifications.is_accepting() => {
+                log::info!(
+                    "Shutting down: persisting push notification to be sent on the next start."
+                );
+                state.scheduled_notifications.schedule(signal)?;
+            }
+            Some(send_at) if send_at > Timestamp::now() => {
+                log::info!("Scheduling push notification for {send_at}.");
+                state.scheduled_notifications.schedule(signal)?;
+            }
+            _ => to_send.push(signal),
+        }
+    }
+
+    if to_send.is_empty() {
+        return Ok(());
+    }
+
+    let results = send_push_notification_batch::<T>(state, to_send.clone()).await;
+    let mut sent: Vec<SendPushNotificationSignal> = Vec::new();
+    for (signal, result) in to_send.into_iter().zip(results) {
+        let Err(err) = result else {
+            sent.push(signal);
+            continue;
+        };
+        let span = notification_span(&signal);
+        let _entered = span.enter();
+        // Logged here so that the error is recorded within the span of the notification
+        log::error!("Failed to send push notification: {err:?}");
+        if let Some(key) = &idempotency_key(&signal) {
+            state.sent_notifications.release(key)?;
+        }
+    }
+    record_sent_push_notifications(app_ws, &sent).await;
+
+    Ok(())
+}
+
+/// Records the push notifications that were sent in the cell of the service, so that the badge
+/// count and the history of their recipients are only updated once FCM has accepted them
+async fn record_sent_push_notifications(
+    app_ws: &AppWebsocket,
+    signals: &[SendPushNotificationSignal],
+) {
+    let inputs: Vec<RecordSentPushNotificationInput> = signals
+        .iter()
+        .filter(|signal| signal.notification.increment_badge || signal.notification.keep_in_history)
+        .filter_map(|signal| {
+            Some(RecordSentPushNotificationInput {
+                provenance: signal.provenance.clone(),
+                recipient: signal.recipient.clone()?,
+                notification: signal.notification.clone(),
+                sent_at: Timestamp::now(),
+            })
+        })
+        .collect();
+    if inputs.is_empty() {
+        return;
+    }
+
+    let result = async {
+        app_ws
+            .call_zome(
+                holochain_client::ZomeCallTarget::RoleName(String::from(
+                    "push_notifications_service",
+                )),
+                "push_notifications_service".into(),
+                "record_sent_push_notifications".into(),
+                ExternIO::encode(inputs)?,
+            )
+            .await?;
+        Ok::<(), anyhow::Error>(())
+    }
+    .await;
+    if let Err(err) = result {
+        // The notification was already sent, so there is nothing left to retry
+        log::error!("Failed to record the sent push notifications: {err:?}");
+    }
+}
+
+/// Sends the test push notification requested by a progenitor, reporting the result back to them
+async fn handle_send_test_push_notification_signal<T: FcmClient>(
+    app_ws: &AppWebsocket,
+    state: &ProviderState,
+    cell_id: CellId,
+    signal: SendTestPushNotificationSignal,
+) -> Result<()> {
+    let notification = PushNotification {
+        title: String::from("Test notification"),
+        body: String::from("This is a test notification from the push notifications service."),
+        ..Default::default()
+    };
+
+    let notification = with_project_notification_style(notification, &signal.service_account_key);
+
+    let result = if signal.dry_run {
+        T::validate_push_notification(
+            state.fcm_client.clone(),
+            signal.fcm_project_id.clone(),
+            crate::into(signal.service_account_key),
+            signal.token,
+            notification,
+        )
+        .await
+    } else {
+        let send_push_notification_signal = SendPushNotificationSignal {
+            token: signal.token,
+            fcm_project_id: signal.fcm_project_id.clone(),
+            service_account_key: signal.service_account_key,
+            notification,
+            provenance: signal.requester.clone(),
+            id: None,
+            send_at: None,
+            recipient: None,
+            trace_id: Some(signal.request_id.clone()),
+        };
+        let span = notification_span(&send_push_notification_signal);
+        send_push_notification::<T>(state, send_push_notification_signal)
+            .instrument(span)
+            .await
+    };
+    match &result {
+        Ok(()) => log::info!(
+            "Test push notification {} for project {} succeeded.",
+            signal.request_id,
+            signal.fcm_project_id
+        ),
+        Err(err) => log::warn!(
+            "Test push notification {} for project {} failed: {err:?}",
+            signal.request_id,
+            signal.fcm_project_id
+        ),
+    }
+
+    let provider = cell_id.agent_pubkey().clone();
+    app_ws
+        .call_zome(
+            holochain_client::ZomeCallTarget::CellId(cell_id),
+            "push_notifications_service".into(),
+            "report_test_push_notification_result".into(),
+            ExternIO::encode(ReportTestPushNotificationResultInput {
+                requester: signal.requester,
+                result: TestPushNotificationResult {
+                    request_id: signal.request_id,
+                    provider,
+                    dry_run: signal.dry_run,
+                    error: result.err().map(|err| format!("{err:#}")),
+                },
+            })?,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Span that identifies the notification in all the logs related to it.
+fn notification_span(signal: &SendPushNotificationSignal) -> tracing::Span {
+    push_notification_span(
+        signal.trace_id.as_deref(),
+        signal.id.as_deref(),
+        &signal.fcm_project_id,
+        signal.recipient.as_ref(),
+    )
+}
+
+fn scheduled_notification_span(notification: &ScheduledNotification) -> tracing::Span {
+    push_notification_span(
+        notification.trace_id.as_deref(),
+        notification.id.as_deref(),
+        &notification.fcm_project_id,
+        notification.recipient.as_ref(),
+    )
+}
+
+/// The recipient is hashed so that the logs don't leak who receives which notifications.
+fn push_notification_span(
+    trace_id: Option<&str>,
+    id: Option<&str>,
+    fcm_project_id: &str,
+    recipient: Option<&AgentPubKey>,
+) -> tracing::Span {
+    tracing::info_span!(
+        "push_notification",
+        trace_id = trace_id.unwrap_or_default(),
+        notification_id = id.unwrap_or_default(),
+        fcm_project_id = fcm_project_id,
+        recipient = %recipient.map(hashed_agent).unwrap_or_default(),
+        cell_id = tracing::field::Empty,
+    )
+}
+
+fn hashed_agent(agent: &AgentPubKey) -> String {
+    sha256::digest(agent.get_raw_39())[..16].to_string()
+}
+
+/// Notifications without an id can't be deduplicated.
+fn idempotency_key(signal: &SendPushNotificationSignal) -> Option<String> {
+    sent_notifications_key(&signal.provenance, &signal.token, signal.id.as_ref())
+}
+
+fn sent_notifications_key(
+    provenance: &AgentPubKey,
+    token: &String,
+    id: Option<&String>,
+) -> Option<String> {
+    let id = id?;
+    Some(format!("{provenance}:{token}:{id}"))
+}
+
+/// Sends the scheduled notification with the current service account key for its FCM project,
+/// retrying it later with a backoff if it fails
+///
+/// The notification is prepared again by the cell right before it's sent, so that it's not sent
+/// if the recipient has muted it since it was scheduled
+async fn send_scheduled_push_notification<T: FcmClient>(
+    app_ws: &AppWebsocket,
+    state: &ProviderState,
+    notification: ScheduledNotification,
+) {
+    let key = sent_notifications_key(
+        &notification.provenance,
+        &notification.token,
+        notification.id.as_ref(),
+    );
+    let result = async {
+        let Some(prepared) = prepare_scheduled_push_notification(app_ws, &notification).await?
+        else {
+            return Ok(None);
+        };
+        let service_account_key =
+            get_current_service_account_key(app_ws, &notification.fcm_project_id).await?;
+        let mut signal = notification.clone().into_signal(service_account_key);
+        signal.notification = prepared;
+        send_push_notification::<T>(state, signal.clone()).await?;
+        Ok::<Option<SendPushNotificationSignal>, anyhow::Error>(Some(signal))
+    }
+    .await;
+    let err = match result {
+        Ok(Some(signal)) => {
+            record_sent_push_notifications(app_ws, &[signal]).await;
+            return;
+        }
+        Ok(None) => {
+            log::info!("Not sending scheduled push notification: the recipient has muted it.");
+            if let Some(key) = &key {
+                if let Err(err) = state.sent_notifications.release(key) {
+                    log::error!("Failed to release the idempotency key: {err:?}");
+                }
+            }
+            return;
+        }
+        Err(err) => err,
+    };
+    log::error!("Failed to send scheduled push notification: {err:?}");
+
+    match state
+        .scheduled_notifications
+        .retry_later(notification, Timestamp::now())
+    {
+        Ok(true) => log::info!("Scheduled push notification will be retried later."),
+        Ok(false) => {
+            log::error!(
+                "Giving up on scheduled push notification after {MAX_SCHEDULED_SEND_ATTEMPTS} attempts."
+            );
+            if let Some(key) = &key {
+                if let Err(err) = state.sent_notifications.release(key) {
+                    log::error!("Failed to release the idempotency key: {err:?}");
+                }
+            }
+        }
+        Err(err) => log::error!("Failed to reschedule push notification: {err:?}"),
+    }
+}
+
+/// Prepares the scheduled notification with the preferences of its recipient at the time of sending it
+///
+/// Returns None if the recipient has muted it
+async fn prepare_scheduled_push_notification(
+    app_ws: &AppWebsocket,
+    notification: &ScheduledNotification,
+) -> Result<Option<PushNotification>> {
+    let Some(recipient) = notification.recipient.clone() else {
+        return Ok(Some(notification.notification.clone()));
+    };
+    let prepared: Option<PushNotification> = app_ws
+        .call_zome(
+            holochain_client::ZomeCallTarget::RoleName(String::from("push_notifications_service")),
+            "push_notifications_service".into(),
+            "prepare_scheduled_push_notification".into(),
+            ExternIO::encode(PrepareScheduledPushNotificationInput {
+                provenance: notification.provenance.clone(),
+                recipient,
+                notification: notification.notification.clone(),
+            })?,
+        )
+        .await?
+        .decode()?;
+    Ok(prepared)
+}
+
+/// Looks up the service account key that is currently active for the FCM project
+async fn get_current_service_account_key(
+    app_ws: &AppWebsocket,
+    fcm_project_id: &String,
+) -> Result<ServiceAccountKey> {
+    let service_account_key: Option<ServiceAccountKey> = app_ws
+        .call_zome(
+            holochain_client::ZomeCallTarget::RoleName(String::from("push_notifications_service")),
+            "push_notifications_service".into(),
+            "get_current_service_account_key".into(),
+            ExternIO::encode(fcm_project_id.clone())?,
+        )
+        .await?
+        .decode()?;
+    service_account_key.ok_or(anyhow!(
+        "No service account key found for project {fcm_project_id}"
+    ))
+}
+
+async fn send_push_notification<T: FcmClient>(
+    state: &ProviderState,
+    signal: SendPushNotificationSignal,
+) -> Result<()> {
+    let _in_flight = state.in_flight_notifications.start(&signal);
+    let _permit = state.send_limiter.acquire().await?;
+    let fcm_project_id = signal.fcm_project_id.clone();
+    let start = Instant::now();
+    let notification =
+        with_project_notification_style(signal.notification, &signal.service_account_key);
+    let result = T::send_push_notification(
+        state.fcm_client.clone(),
+        signal.fcm_project_id,
+        crate::into(signal.service_account_key),
+        signal.token,
+        notification,
+    )
+    .await;
+    metrics::SEND_LATENCY
+        .with_label_values(&[&fcm_project_id])
+        .observe(start.elapsed().as_secs_f64());
+    match &result {
+        Ok(()) => metrics::NOTIFICATIONS_SENT
+            .with_label_values(&[&fcm_project_id])
+            .inc(),
+        Err(err) => metrics::NOTIFICATIONS_FAILED
+            .with_label_values(&[&fcm_project_id, metrics::fcm_error_class(err)])
+            .inc(),
+    }
+    result
+}
+
+/// Applies the default notification style of the FCM project, published with its service account key,
+/// to the notifications that don't set their own
+fn with_project_notification_style(
+    mut notification: PushNotification,
+    service_account_key: &ServiceAccountKey,
+) -> PushNotification {
+    if notification.style.is_none() {
+        notification.style = service_account_key.notification_style;
+    }
+    notification
+}
+
+/// Sends the push notifications, which must all be for the same FCM project, in one batch
+///
+/// Returns the result for each of them, in the same order
+async fn send_push_notification_batch<T: FcmClient>(
+    state: &ProviderState,
+    signals: Vec<SendPushNotificationSignal>,
+) -> Vec<Result<()>> {
+    let Some(first) = signals.first() else {
+        return vec![];
+    };
+    let _in_flight: Vec<_> = signals
+        .iter()
+        .map(|signal| state.in_flight_notifications.start(signal))
+        .collect();
+    let fcm_project_id = first.fcm_project_id.clone();
+    let project_service_account_key = first.service_account_key.clone();
+    let service_account_key = crate::into(project_service_account_key.clone());
+    let count = signals.len();
+    let notifications = signals
+        .into_iter()
+        .map(|signal| {
+            (
+                signal.token,
+                with_project_notification_style(signal.notification, &project_service_account_key),
+            )
+        })
+        .collect();
+
+    let start = Instant::now();
+    let results = match T::send_push_notification_batch(
+        state.fcm_client.clone(),
+        fcm_project_id.clone(),
+        service_account_key,
+        notifications,
+        state.send_limiter.clone(),
+    )
+    .await
+    {
+        Ok(results) => results,
+        Err(err) => (0..count)
+            .map(|_| match err.downcast_ref::<FcmError>() {
+                // Keeps the FCM error code for the metrics
+                Some(fcm_error) => Err(anyhow::Error::new(fcm_error.clone())),
+                None => Err(anyhow!("{err:#}")),
+            })
+            .collect(),
+    };
+    metrics::SEND_LATENCY
+        .with_label_values(&[&fcm_project_id])
+        .observe(start.elapsed().as_secs_f64());
+    for result in &results {
+        match result {
+            Ok(()) => metrics::NOTIFICATIONS_SENT
+                .with_label_values(&[&fcm_project_id])
+                .inc(),
+            Err(err) => metrics::NOTIFICATIONS_FAILED
+                .with_label_values(&[&fcm_project_id, metrics::fcm_error_class(err)])
+                .inc(),
+        }
+    }
+    results
+}
+
 async fn handle_new_clone_request_signal(
     admin_ws: &AdminWebsocket,
     app_ws: &AppWebsocket,
     new_clone_request: NewCloneRequest,
+    retries: usize,
 ) -> Result<()> {
     let a = app_ws.clone();
     with_retries(
@@ -168,7 +924,7 @@ async fn handle_new_clone_request_signal(
 
             Ok(())
         },
-        20,
+        retries,
     )
     .await?;
 
@@ -215,5 +971,6 @@ pub fn from(key: fcm_v1::auth::ServiceAccountKey) -> push_notifications_types::S
         token_uri: key.token_uri,
         auth_provider_x509_cert_url: key.auth_provider_x509_cert_url,
         client_x509_cert_url: key.client_x509_cert_url,
+        notification_style: None,
     }
 }
@@ -1,34 +1,38 @@
 use anyhow::{anyhow, Result};
-use clap::Parser;
-use env_logger::Builder;
+use clap::{Parser, Subcommand};
 use holochain::core::AgentPubKeyB64;
 use holochain_client::InstalledAppId;
-use holochain_runtime::NetworkConfig;
-use log::Level;
-use std::io::Write;
 use std::path::PathBuf;
-use std::str::FromStr;
 
-use push_notifications_service_provider::fcm_client::RealFcmClient;
+use push_notifications_service_provider::{
+    config::{LogFormat, ProviderConfig},
+    fcm_client::RealFcmClient,
+};
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
-    push_notifications_service_provider_happ: PathBuf,
+    #[arg(required = true)]
+    push_notifications_service_provider_happ: Option<PathBuf>,
 
-    #[arg(long)]
-    app_id: InstalledAppId,
-
-    #[arg(long)]
-    admin_port: Option<u16>,
+    #[arg(long, required = true)]
+    app_id: Option<InstalledAppId>,
 
     /// Directory to store all holochain data
-    #[arg(long)]
-    data_dir: PathBuf,
+    #[arg(long, required = true)]
+    data_dir: Option<PathBuf>,
 
     #[arg(long, required = true, num_args = 1)]
     progenitors: Vec<AgentPubKeyB64>,
 
+    /// TOML or YAML configuration file. Its values can be overridden with environment variables
+    /// like `PUSH_NOTIFICATIONS_PROVIDER__FCM__REQUEST_TIMEOUT_SECS`, and with the flags below
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    #[arg(long)]
+    admin_port: Option<u16>,
+
     #[arg(long)]
     bootstrap_url: Option<String>,
 
@@ -37,25 +41,50 @@ struct Args {
 
     #[arg(long)]
     mdns_discovery: bool,
-}
 
-fn network_config(bootstrap_url: Option<String>, signal_url: Option<String>) -> NetworkConfig {
-    let mut network_config = NetworkConfig::default();
+    /// Port in which to serve the Prometheus metrics at `/metrics`
+    #[arg(long)]
+    metrics_port: Option<u16>,
+
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
 
-    if let Some(bootstrap_url) = bootstrap_url {
-        network_config.bootstrap_url = url2::Url2::parse(bootstrap_url);
-    }
-    if let Some(signal_url) = signal_url {
-        network_config.signal_url = url2::Url2::parse(signal_url);
-    }
+    /// Port in which to serve the liveness (`/healthz`) and readiness (`/readyz`) probes
+    #[arg(long)]
+    health_port: Option<u16>,
 
-    network_config
+    #[command(subcommand)]
+    command: Option<Commands>,
 }
 
-fn log_level() -> Level {
-    match std::env::var("RUST_LOG") {
-        Ok(s) => Level::from_str(s.as_str()).expect("Invalid RUST_LOG level"),
-        _ => Level::Info,
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Prints the default configuration, to be used as a starting point for the config file
+    PrintDefaultConfig,
+}
+
+/// Applies the flags given in the command line on top of the configuration
+fn apply_args(config: &mut ProviderConfig, args: &Args) {
+    if let Some(admin_port) = args.admin_port {
+        config.network.admin_port = Some(admin_port);
+    }
+    if let Some(bootstrap_url) = &args.bootstrap_url {
+        config.network.bootstrap_url = Some(bootstrap_url.clone());
+    }
+    if let Some(signal_url) = &args.signal_url {
+        config.network.signal_url = Some(signal_url.clone());
+    }
+    if args.mdns_discovery {
+        config.network.mdns_discovery = true;
+    }
+    if let Some(metrics_port) = args.metrics_port {
+        config.metrics.port = Some(metrics_port);
+    }
+    if let Some(health_port) = args.health_port {
+        config.health.port = Some(health_port);
+    }
+    if let Some(log_format) = args.log_format {
+        config.logging.format = log_format;
     }
 }
 
@@ -72,18 +101,27 @@ fn set_wasm_level() {
 async fn main() -> Result<()> {
     let args = Args::parse();
 
-    Builder::new()
-        .format(|buf, record| writeln!(buf, "[{}] {}", record.level(), record.args()))
-        .target(env_logger::Target::Stdout)
-        .filter(None, log_level().to_level_filter())
-        .filter_module("holochain_sqlite", log::LevelFilter::Off)
-        .filter_module("tracing::span", log::LevelFilter::Off)
-        .filter_module("iroh", log::LevelFilter::Warn)
-        .filter_module("kitsune2", log::LevelFilter::Warn)
-        .init();
+    if let Some(Commands::PrintDefaultConfig) = args.command {
+        print!("{}", ProviderConfig::default_toml()?);
+        return Ok(());
+    }
+
+    let mut config = ProviderConfig::load(args.config.as_ref())?;
+    apply_args(&mut config, &args);
+    config.validate()?;
+
+    config.logging.init()?;
     set_wasm_level();
 
-    let data_dir = args.data_dir;
+    let (Some(happ_path), Some(app_id), Some(data_dir)) = (
+        args.push_notifications_service_provider_happ,
+        args.app_id,
+        args.data_dir,
+    ) else {
+        return Err(anyhow!(
+            "The hApp path, --app-id and --data-dir are required."
+        ));
+    };
     if data_dir.exists() {
         if !std::fs::read_dir(&data_dir).is_ok() {
             return Err(anyhow!("The given data dir is not a directory."));
@@ -94,12 +132,10 @@ async fn main() -> Result<()> {
 
     push_notifications_service_provider::run::<RealFcmClient>(
         data_dir,
-        network_config(args.bootstrap_url, args.signal_url),
-        args.app_id,
-        args.push_notifications_service_provider_happ,
+        app_id,
+        happ_path,
         args.progenitors.into_iter().map(|p| p.into()).collect(),
-        args.mdns_discovery,
-        args.admin_port
+        config,
     )
     .await
 }
@@ -0,0 +1,126 @@
+use std::{net::SocketAddr, sync::LazyLock};
+
+use anyhow::Result;
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, HistogramVec,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+
+use crate::fcm_client::{FcmError, FcmErrorCode};
+
+pub static SIGNALS_RECEIVED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "push_notifications_signals_received_total",
+        "Signals received from the push notifications service cells, by kind",
+        &["kind"]
+    )
+    .expect("Failed to register metric")
+});
+
+pub static NOTIFICATIONS_SENT: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "push_notifications_sent_total",
+        "Push notifications successfully sent to FCM, by FCM project",
+        &["fcm_project_id"]
+    )
+    .expect("Failed to register metric")
+});
+
+pub static NOTIFICATIONS_FAILED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "push_notifications_failed_total",
+        "Push notifications that failed to be sent to FCM, by FCM project and error class",
+        &["fcm_project_id", "error_class"]
+    )
+    .expect("Failed to register metric")
+});
+
+pub static SEND_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "push_notifications_send_duration_seconds",
+        "Time spent sending a push notification to FCM, by FCM project",
+        &["fcm_project_id"]
+    )
+    .expect("Failed to register metric")
+});
+
+pub static SCHEDULED_QUEUE_DEPTH: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "push_notifications_scheduled_queue_depth",
+        "Push notifications scheduled to be sent in the future"
+    )
+    .expect("Failed to register metric")
+});
+
+pub static CLONED_SERVICE_CELLS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "push_notifications_cloned_service_cells",
+        "Cloned service cells, as reconciled by reconcile_cloned_cells"
+    )
+    .expect("Failed to register metric")
+});
+
+pub static CONNECTED_PEERS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "push_notifications_connected_peers",
+        "Peer agents known to the conductor across all the cells"
+    )
+    .expect("Failed to register metric")
+});
+
+/// Classifies the error returned by the FCM client by its FCM v1 error code,
+/// to keep the cardinality of the failures metric bounded
+pub fn fcm_error_class(err: &anyhow::Error) -> &'static str {
+    if let Some(fcm_error) = err.chain().find_map(|err| err.downcast_ref::<FcmError>()) {
+        return match fcm_error.code {
+            Some(FcmErrorCode::Unregistered) => "unregistered",
+            Some(FcmErrorCode::InvalidArgument) => "invalid_argument",
+            Some(FcmErrorCode::SenderIdMismatch) => "sender_id_mismatch",
+            Some(FcmErrorCode::QuotaExceeded) => "quota_exceeded",
+            Some(FcmErrorCode::Unavailable) => "unavailable",
+            Some(FcmErrorCode::Internal) => "internal",
+            Some(FcmErrorCode::ThirdPartyAuthError) => "third_party_auth_error",
+            None => match fcm_error.status {
+                reqwest::StatusCode::UNAUTHORIZED => "unauthenticated",
+                reqwest::StatusCode::FORBIDDEN => "permission_denied",
+                reqwest::StatusCode::NOT_FOUND => "not_found",
+                _ => "other",
+            },
+        };
+    }
+    let timed_out = err
+        .chain()
+        .filter_map(|err| err.downcast_ref::<reqwest::Error>())
+        .any(|err| err.is_timeout());
+    if timed_out {
+        return "timeout";
+    }
+    "other"
+}
+
+/// Serves the metrics in the Prometheus text format at `/metrics`
+pub async fn serve_metrics(port: u16) -> Result<()> {
+    let app = Router::new().route("/metrics", get(metrics));
+    let listener = tokio::net::TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port))).await?;
+    log::info!("Serving metrics at port {port}.");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn metrics() -> impl IntoResponse {
+    match TextEncoder::new().encode_to_string(&prometheus::gather()) {
+        Ok(body) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            body,
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
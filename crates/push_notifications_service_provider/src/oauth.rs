@@ -0,0 +1,99 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use fcm_v1::auth::ServiceAccountKey;
+use ring::{
+    rand::SystemRandom,
+    signature::{RsaKeyPair, RSA_PKCS1_SHA256},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+const FCM_SCOPE: &str = "https://www.googleapis.com/auth/firebase.messaging";
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Exchanges a JWT signed with the service account key for an access token to the FCM API,
+/// following the OAuth 2.0 JWT bearer flow used by Google service accounts
+pub async fn fetch_access_token(
+    client: &reqwest::Client,
+    service_account_key: &ServiceAccountKey,
+    token_uri: &str,
+) -> Result<String> {
+    let assertion = signed_jwt(service_account_key, token_uri)?;
+
+    let response = client
+        .post(token_uri)
+        .form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", assertion.as_str()),
+        ])
+        .send()
+        .await
+        .with_context(|| format!("Failed to request an access token from {token_uri}"))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!(
+            "Failed to get an access token from {token_uri}: {status} {body}"
+        ));
+    }
+
+    let token: TokenResponse = response
+        .json()
+        .await
+        .context("Invalid response from the OAuth token endpoint")?;
+    Ok(token.access_token)
+}
+
+fn signed_jwt(service_account_key: &ServiceAccountKey, audience: &str) -> Result<String> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    let header = json!({
+        "alg": "RS256",
+        "typ": "JWT",
+        "kid": service_account_key.private_key_id,
+    });
+    let claims = json!({
+        "iss": service_account_key.client_email,
+        "scope": FCM_SCOPE,
+        "aud": audience,
+        "iat": now,
+        "exp": now + 3600,
+    });
+    let message = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?)
+    );
+
+    let key_pair = RsaKeyPair::from_pkcs8(&pem_to_der(&service_account_key.private_key)?)
+        .map_err(|err| anyhow!("Invalid service account private key: {err}"))?;
+    let mut signature = vec![0; key_pair.public().modulus_len()];
+    key_pair
+        .sign(
+            &RSA_PKCS1_SHA256,
+            &SystemRandom::new(),
+            message.as_bytes(),
+            &mut signature,
+        )
+        .map_err(|err| anyhow!("Failed to sign the JWT: {err}"))?;
+
+    Ok(format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature)))
+}
+
+fn pem_to_der(pem: &str) -> Result<Vec<u8>> {
+    let base64_body: String = pem
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(base64_body)
+        .context("Service account private key is not valid PEM")
+}
@@ -0,0 +1,168 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use holochain_types::prelude::{AgentPubKey, Timestamp};
+use push_notifications_types::{PushNotification, SendPushNotificationSignal, ServiceAccountKey};
+use serde::{Deserialize, Serialize};
+
+/// How many times a scheduled notification is tried before giving up on it
+pub const MAX_SCHEDULED_SEND_ATTEMPTS: u32 = 5;
+
+/// Delay before retrying a scheduled notification that failed to be sent, doubled on each attempt
+pub const SCHEDULED_SEND_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Notification waiting to be sent, without the service account key: the current key
+/// for the FCM project is looked up when it's due, so that no private key is written to disk
+/// and rotated keys are never used.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduledNotification {
+    pub token: String,
+    pub fcm_project_id: String,
+    pub notification: PushNotification,
+    pub provenance: AgentPubKey,
+    pub id: Option<String>,
+    pub send_at: Option<Timestamp>,
+    pub recipient: Option<AgentPubKey>,
+    pub trace_id: Option<String>,
+    /// Failed attempts to send this notification so far
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+impl From<SendPushNotificationSignal> for ScheduledNotification {
+    fn from(signal: SendPushNotificationSignal) -> Self {
+        Self {
+            token: signal.token,
+            fcm_project_id: signal.fcm_project_id,
+            notification: signal.notification,
+            provenance: signal.provenance,
+            id: signal.id,
+            send_at: signal.send_at,
+            recipient: signal.recipient,
+            trace_id: signal.trace_id,
+            attempts: 0,
+        }
+    }
+}
+
+impl ScheduledNotification {
+    pub fn into_signal(self, service_account_key: ServiceAccountKey) -> SendPushNotificationSignal {
+        SendPushNotificationSignal {
+            token: self.token,
+            fcm_project_id: self.fcm_project_id,
+            service_account_key,
+            notification: self.notification,
+            provenance: self.provenance,
+            id: self.id,
+            send_at: self.send_at,
+            recipient: self.recipient,
+            trace_id: self.trace_id,
+        }
+    }
+}
+
+/// Notifications waiting for their `send_at` time, persisted in the data dir of the provider
+/// so that they survive restarts.
+#[derive(Clone)]
+pub struct ScheduledNotifications {
+    path: PathBuf,
+    notifications: Arc<Mutex<Vec<ScheduledNotification>>>,
+}
+
+impl ScheduledNotifications {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let notifications = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?)?
+        } else {
+            vec![]
+        };
+
+        Ok(Self {
+            path,
+            notifications: Arc::new(Mutex::new(notifications)),
+        })
+    }
+
+    pub fn schedule(&self, notification: impl Into<ScheduledNotification>) -> Result<()> {
+        let mut notifications = self.lock()?;
+        notifications.push(notification.into());
+        self.persist(&notifications)
+    }
+
+    /// Schedules the notification that failed to be sent to be retried later, with exponential backoff.
+    ///
+    /// Returns false if it has already been tried `MAX_SCHEDULED_SEND_ATTEMPTS` times, in which case it's dropped.
+    pub fn retry_later(
+        &self,
+        mut notification: ScheduledNotification,
+        now: Timestamp,
+    ) -> Result<bool> {
+        notification.attempts += 1;
+        if notification.attempts >= MAX_SCHEDULED_SEND_ATTEMPTS {
+            return Ok(false);
+        }
+        let delay = SCHEDULED_SEND_RETRY_DELAY * 2u32.pow(notification.attempts - 1);
+        notification.send_at = Some(Timestamp::from_micros(
+            now.as_micros() + delay.as_micros() as i64,
+        ));
+        self.schedule(notification)?;
+        Ok(true)
+    }
+
+    /// Returns whether a scheduled notification with the given id was found.
+    pub fn cancel(
+        &self,
+        provenance: &AgentPubKey,
+        recipient: &AgentPubKey,
+        id: &String,
+    ) -> Result<bool> {
+        let mut notifications = self.lock()?;
+        let count = notifications.len();
+        notifications.retain(|n| {
+            !(n.provenance.eq(provenance)
+                && n.recipient.as_ref().eq(&Some(recipient))
+                && n.id.as_ref().eq(&Some(id)))
+        });
+        let cancelled = notifications.len() < count;
+        if cancelled {
+            self.persist(&notifications)?;
+        }
+        Ok(cancelled)
+    }
+
+    /// Removes and returns the notifications that are due at the given time.
+    pub fn take_due(&self, now: Timestamp) -> Result<Vec<ScheduledNotification>> {
+        let mut notifications = self.lock()?;
+        let (due, pending): (Vec<_>, Vec<_>) = notifications
+            .drain(..)
+            .partition(|n| n.send_at.map(|send_at| send_at <= now).unwrap_or(true));
+        *notifications = pending;
+        if !due.is_empty() {
+            self.persist(&notifications)?;
+        }
+        Ok(due)
+    }
+
+    pub fn count(&self) -> Result<usize> {
+        Ok(self.lock()?.len())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Vec<ScheduledNotification>>> {
+        self.notifications
+            .lock()
+            .map_err(|_| anyhow!("Scheduled notifications lock is poisoned"))
+    }
+
+    fn persist(&self, notifications: &[ScheduledNotification]) -> Result<()> {
+        // Write to a temporary file first so that a crash doesn't leave a corrupted file
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(notifications)?)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use tokio::{
+    sync::{Mutex, OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+/// Limits the concurrency and the rate of the requests to FCM.
+#[derive(Clone)]
+pub struct SendLimiter {
+    concurrency: Arc<Semaphore>,
+    min_interval: Option<Duration>,
+    next_slot: Arc<Mutex<Instant>>,
+}
+
+impl SendLimiter {
+    pub fn new(max_concurrent_sends: usize, max_sends_per_second: Option<u32>) -> Self {
+        Self {
+            concurrency: Arc::new(Semaphore::new(max_concurrent_sends)),
+            min_interval: max_sends_per_second
+                .map(|rate| Duration::from_secs_f64(1.0 / rate as f64)),
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Waits until a new send is allowed. The send must happen while the returned permit is held.
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit> {
+        let permit = self.concurrency.clone().acquire_owned().await?;
+
+        if let Some(min_interval) = self.min_interval {
+            let slot = {
+                let mut next_slot = self.next_slot.lock().await;
+                let slot = (*next_slot).max(Instant::now());
+                *next_slot = slot + min_interval;
+                slot
+            };
+            tokio::time::sleep_until(slot).await;
+        }
+
+        Ok(permit)
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use holochain_types::prelude::Timestamp;
+
+/// Idempotency keys of the recently sent notifications, persisted in the data dir of the provider
+/// so that retried requests don't notify the recipient twice.
+///
+/// The keys are not shared between providers: deduplication relies on the gateway routing every
+/// notification for a recipient to the same provider. A retry that fails over to another provider,
+/// because the assigned one was unreachable, may be sent again.
+#[derive(Clone)]
+pub struct SentNotifications {
+    path: PathBuf,
+    ttl: Duration,
+    /// Idempotency key -> when it was claimed, in microseconds since the UNIX epoch
+    keys: Arc<Mutex<HashMap<String, i64>>>,
+}
+
+impl SentNotifications {
+    pub fn load(path: PathBuf, ttl: Duration) -> Result<Self> {
+        let keys = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path,
+            ttl,
+            keys: Arc::new(Mutex::new(keys)),
+        })
+    }
+
+    /// Returns false if the key was already claimed within the TTL, in which case the
+    /// notification must not be sent again.
+    pub fn claim(&self, key: String) -> Result<bool> {
+        let mut keys = self.lock()?;
+        let now = Timestamp::now().as_micros();
+        let ttl = self.ttl.as_micros() as i64;
+        keys.retain(|_, claimed_at| now - *claimed_at < ttl);
+
+        if keys.contains_key(&key) {
+            return Ok(false);
+        }
+        keys.insert(key, now);
+        self.persist(&keys)?;
+        Ok(true)
+    }
+
+    /// Releases a claimed key, so that the notification can be retried after a failed send.
+    pub fn release(&self, key: &String) -> Result<()> {
+        let mut keys = self.lock()?;
+        if keys.remove(key).is_some() {
+            self.persist(&keys)?;
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, i64>>> {
+        self.keys
+            .lock()
+            .map_err(|_| anyhow!("Sent notifications lock is poisoned"))
+    }
+
+    fn persist(&self, keys: &HashMap<String, i64>) -> Result<()> {
+        // Write to a temporary file first so that a crash doesn't leave a corrupted file
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(keys)?)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+}
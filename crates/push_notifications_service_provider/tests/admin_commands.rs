@@ -0,0 +1,84 @@
+#[allow(dead_code)]
+mod common;
+use anyhow::anyhow;
+use common::*;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn list_show_and_delete_fcm_projects() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+    let (_tmp, client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    let fcm_projects = client.list_fcm_projects().await.unwrap();
+    assert_eq!(fcm_projects, vec![fcm_project_id.clone()]);
+
+    let project = client
+        .get_fcm_project(fcm_project_id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(project.fcm_project_id, fcm_project_id);
+    assert_eq!(project.client_email, "random@email.com");
+    assert_eq!(project.token_uri, "random://token.uri");
+
+    // The private key is never part of the output of the admin commands
+    let json = serde_json::to_string(&project).unwrap();
+    assert!(!json.contains("private_key_1"));
+
+    assert!(client
+        .get_fcm_project(String::from("UNKNOWN_PROJECT"))
+        .await
+        .unwrap()
+        .is_none());
+
+    client
+        .delete_fcm_project(fcm_project_id.clone())
+        .await
+        .unwrap();
+
+    with_retries(
+        async || {
+            let fcm_projects = client.list_fcm_projects().await?;
+            if !fcm_projects.is_empty() {
+                return Err(anyhow!("FCM project not deleted yet: {fcm_projects:?}"));
+            }
+            Ok(())
+        },
+        10,
+    )
+    .await
+    .unwrap();
+
+    assert!(client
+        .get_fcm_project(fcm_project_id)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn list_clone_requests_hides_the_network_seeds() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+    let (_tmp, client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    let clone_requests = client.list_clone_requests(false).await.unwrap();
+    assert_eq!(clone_requests.len(), 1);
+    assert_eq!(clone_requests[0].network_seed, None);
+
+    let json = serde_json::to_string(&clone_requests).unwrap();
+    assert!(!json.contains(&scenario.network_seed));
+
+    let clone_requests = client.list_clone_requests(true).await.unwrap();
+    assert_eq!(clone_requests.len(), 1);
+    assert_eq!(
+        clone_requests[0].network_seed,
+        Some(scenario.network_seed.clone())
+    );
+}
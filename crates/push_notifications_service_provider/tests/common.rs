@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
 use std::{io::Write, time::Duration};
 
 use anyhow::anyhow;
@@ -6,15 +7,32 @@ use env_logger::Builder;
 use fixt::fixt;
 use holo_hash::fixt::AgentPubKeyFixturator;
 use holochain::prelude::{DnaModifiersOpt, RoleSettings, RoleSettingsMap, YamlProperties};
-use holochain_client::{AgentPubKey, AppWebsocket};
+use holochain_client::{AgentPubKey, AppWebsocket, ExternIO, ZomeCallTarget};
 use holochain_runtime::{vec_to_locked, HolochainRuntime, HolochainRuntimeConfig, NetworkConfig};
 use kitsune2_bootstrap_srv::BootstrapSrv;
 use log::Level;
+use push_notifications_service_client::{into, PushNotificationsServiceClient};
+use push_notifications_service_provider::config::ProviderConfig;
 use push_notifications_service_provider::fcm_client::MockFcmClient;
-use push_notifications_service_provider::{read_from_file, run};
+use push_notifications_service_provider::{read_from_file, run, SERVICES_ROLE_NAME};
+use push_notifications_types::{NotificationStyle, RegisterFcmTokenInput, ServiceAccountKey};
 use roles_types::Properties;
+use serde::{de::DeserializeOwned, Serialize};
+use service_providers_types::MakeServiceRequestInput;
+use service_providers_utils::make_service_request;
+use tempdir::TempDir;
 use url2::url2;
 
+// The expectations of the MockFcmClient are global,
+// so tests that use them can't run concurrently
+static MOCK_FCM_CLIENT_LOCK: Mutex<()> = Mutex::new(());
+
+pub fn lock_mock_fcm_client() -> MutexGuard<'static, ()> {
+    MOCK_FCM_CLIENT_LOCK
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+}
+
 pub fn service_provider_happ_path() -> PathBuf {
     std::option_env!("SERVICE_PROVIDER_HAPP")
         .expect("Failed to find SERVICE_PROVIDER_HAPP")
@@ -42,6 +60,15 @@ pub fn network_config(bootstrap_srv: &BootstrapSrv) -> NetworkConfig {
     network_config
 }
 
+pub fn provider_config(bootstrap_srv: &BootstrapSrv) -> ProviderConfig {
+    let address = bootstrap_srv.listen_addrs()[0].clone();
+
+    let mut config = ProviderConfig::default();
+    config.network.bootstrap_url = Some(format!("http://{}", address));
+    config.network.signal_url = Some(format!("ws://{}", address));
+    config
+}
+
 pub async fn run_bootstrap_server() -> BootstrapSrv {
     tokio::task::spawn_blocking(|| {
         let config = kitsune2_bootstrap_srv::Config::testing();
@@ -111,6 +138,9 @@ pub async fn launch(
     (app_ws, runtime)
 }
 
+/// Number of providers that `setup` starts
+pub const PROVIDERS_COUNT: usize = 2;
+
 pub struct Scenario {
     pub network_seed: String,
     pub progenitors: Vec<AgentPubKey>,
@@ -120,6 +150,10 @@ pub struct Scenario {
 }
 
 pub async fn setup() -> Scenario {
+    setup_with_progenitors(vec![fixt!(AgentPubKey)]).await
+}
+
+pub async fn setup_with_progenitors(progenitors: Vec<AgentPubKey>) -> Scenario {
     Builder::new()
         .format(|buf, record| writeln!(buf, "[{}] {}", record.level(), record.args()))
         .target(env_logger::Target::Stdout)
@@ -129,42 +163,38 @@ pub async fn setup() -> Scenario {
         .filter_module("tracing::span", log::LevelFilter::Off)
         .filter_module("kitsune2", log::LevelFilter::Warn)
         .filter_module("iroh", log::LevelFilter::Error)
-        .init();
+        .try_init()
+        .ok();
 
     let network_seed = String::from("somesecret");
-    let progenitors = vec![fixt!(AgentPubKey)];
     let bootstrap_srv = run_bootstrap_server().await;
 
     let p = progenitors.clone();
-    let nc = network_config(&bootstrap_srv);
+    let config = provider_config(&bootstrap_srv);
     tokio::spawn(async move {
         run::<MockFcmClient>(
             tempdir::TempDir::new("test")
                 .expect("Could not make tempdir")
                 .into_path(),
-            nc,
             String::from("test-app"),
             service_provider_happ_path(),
             p.clone(),
-            false,
-            None
+            config,
         )
         .await
         .unwrap();
     });
     let p = progenitors.clone();
-    let nc = network_config(&bootstrap_srv);
+    let config = provider_config(&bootstrap_srv);
     tokio::spawn(async move {
         run::<MockFcmClient>(
             tempdir::TempDir::new("test2")
                 .expect("Could not make tempdir")
                 .into_path(),
-            nc,
             String::from("test-app"),
             service_provider_happ_path(),
             p.clone(),
-            false,
-            None
+            config,
         )
         .await
         .unwrap();
@@ -219,3 +249,265 @@ pub async fn with_retries<T>(
         }
     }
 }
+
+pub fn service_account_key(fcm_project_id: &String) -> ServiceAccountKey {
+    ServiceAccountKey {
+        key_type: None,
+        project_id: Some(fcm_project_id.clone()),
+        private_key_id: None,
+        client_id: None,
+        auth_uri: None,
+        auth_provider_x509_cert_url: None,
+        client_x509_cert_url: None,
+        private_key: String::from("private_key_1"),
+        client_email: String::from("random@email.com"),
+        token_uri: String::from("random://token.uri"),
+        notification_style: None,
+    }
+}
+
+pub async fn wait_for_service_providers(app_ws: &AppWebsocket) {
+    let push_notifications_service_trait_service_id =
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec();
+
+    with_retries(
+        async || {
+            let service_providers: Vec<AgentPubKey> = app_ws
+                .call_zome(
+                    ZomeCallTarget::RoleName(SERVICES_ROLE_NAME.into()),
+                    "service_providers".into(),
+                    "get_providers_for_service".into(),
+                    ExternIO::encode(push_notifications_service_trait_service_id.clone()).unwrap(),
+                )
+                .await?
+                .decode()?;
+            if service_providers.is_empty() {
+                return Err(anyhow!("No service providers yet"));
+            }
+            Ok(())
+        },
+        30,
+    )
+    .await
+    .unwrap();
+}
+
+/// Waits until all the providers started by `setup` are visible, and returns them
+pub async fn get_all_service_providers(app_ws: &AppWebsocket) -> Vec<AgentPubKey> {
+    with_retries(
+        async || {
+            let service_providers: Vec<AgentPubKey> = app_ws
+                .call_zome(
+                    ZomeCallTarget::RoleName(SERVICES_ROLE_NAME.into()),
+                    "service_providers".into(),
+                    "get_providers_for_service".into(),
+                    ExternIO::encode(
+                        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+                    )
+                    .unwrap(),
+                )
+                .await?
+                .decode()?;
+            if service_providers.len() < PROVIDERS_COUNT {
+                return Err(anyhow!("Not all the service providers are visible yet"));
+            }
+            Ok(service_providers)
+        },
+        30,
+    )
+    .await
+    .unwrap()
+}
+
+/// Makes the request to the push notifications service through the given provider,
+/// instead of letting `make_service_request` pick one
+pub async fn make_service_request_to_provider<P, R>(
+    app_ws: &AppWebsocket,
+    provider: &AgentPubKey,
+    fn_name: &str,
+    payload: P,
+) -> anyhow::Result<R>
+where
+    P: Serialize + std::fmt::Debug,
+    R: DeserializeOwned + std::fmt::Debug,
+{
+    let response: ExternIO = app_ws
+        .call_zome(
+            ZomeCallTarget::RoleName(SERVICES_ROLE_NAME.into()),
+            "service_providers".into(),
+            "make_service_request".into(),
+            ExternIO::encode(MakeServiceRequestInput {
+                service_provider: provider.clone(),
+                service_id: push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH
+                    .to_vec(),
+                fn_name: fn_name.into(),
+                payload: ExternIO::encode(payload)?,
+            })?,
+        )
+        .await?
+        .decode()?;
+    Ok(response.decode()?)
+}
+
+/// Creates a client, publishes the service account key for the FCM project, clones the services cell
+/// in the providers and registers the FCM token for the recipient.
+///
+/// The returned client needs to be kept alive for the duration of the test.
+pub async fn setup_push_notifications(
+    scenario: &Scenario,
+    fcm_project_id: &String,
+    token: &String,
+) -> (TempDir, PushNotificationsServiceClient) {
+    setup_push_notifications_with_style(scenario, fcm_project_id, token, None).await
+}
+
+/// Same as [`setup_push_notifications`], publishing the service account key with the given
+/// notification style for the project
+pub async fn setup_push_notifications_with_style(
+    scenario: &Scenario,
+    fcm_project_id: &String,
+    token: &String,
+    notification_style: Option<NotificationStyle>,
+) -> (TempDir, PushNotificationsServiceClient) {
+    let tmp = TempDir::new("pns").unwrap();
+
+    let client = PushNotificationsServiceClient::create(
+        tmp.path().to_path_buf(),
+        network_config(&scenario.bootstrap_srv),
+        "client-happ".into(),
+        client_happ_path(),
+        scenario.progenitors.clone(),
+        false,
+    )
+    .await
+    .unwrap();
+
+    register_push_notifications_with_style(
+        scenario,
+        &client,
+        fcm_project_id,
+        token,
+        notification_style,
+    )
+    .await;
+
+    (tmp, client)
+}
+
+/// Installs the client hApp with a new agent, so that the client created in the returned directory
+/// can be one of the progenitors
+pub async fn progenitor_client_data_dir() -> (TempDir, AgentPubKey) {
+    let tmp = TempDir::new("pns").unwrap();
+
+    let runtime = HolochainRuntime::launch(
+        vec_to_locked(vec![]),
+        HolochainRuntimeConfig::new(tmp.path().to_path_buf(), NetworkConfig::default()),
+    )
+    .await
+    .unwrap();
+    let agent = runtime
+        .admin_websocket()
+        .await
+        .unwrap()
+        .generate_agent_pub_key()
+        .await
+        .unwrap();
+
+    // Same settings as the client uses to install it
+    let roles_properties = Properties {
+        progenitors: vec![agent.clone().into()],
+    };
+    let properties_bytes = YamlProperties::new(serde_yaml::to_value(roles_properties).unwrap());
+    let mut roles_settings = RoleSettingsMap::new();
+    roles_settings.insert(
+        String::from("push_notifications_service"),
+        RoleSettings::Provisioned {
+            membrane_proof: None,
+            modifiers: Some(DnaModifiersOpt {
+                properties: Some(properties_bytes.clone()),
+                ..Default::default()
+            }),
+        },
+    );
+    roles_settings.insert(
+        String::from("services"),
+        RoleSettings::Provisioned {
+            membrane_proof: None,
+            modifiers: Some(DnaModifiersOpt {
+                properties: Some(properties_bytes),
+                network_seed: Some("throwaway".into()),
+            }),
+        },
+    );
+    runtime
+        .install_app(
+            String::from("client-happ"),
+            read_from_file(&client_happ_path()).await.unwrap(),
+            Some(roles_settings),
+            Some(agent.clone()),
+            None,
+        )
+        .await
+        .unwrap();
+    runtime.shutdown().await.unwrap();
+
+    (tmp, agent)
+}
+
+/// Publishes the service account key for the FCM project with the given client, clones the services cell
+/// in the providers and registers the FCM token for the recipient.
+pub async fn register_push_notifications(
+    scenario: &Scenario,
+    client: &PushNotificationsServiceClient,
+    fcm_project_id: &String,
+    token: &String,
+) {
+    register_push_notifications_with_style(scenario, client, fcm_project_id, token, None).await
+}
+
+async fn register_push_notifications_with_style(
+    scenario: &Scenario,
+    client: &PushNotificationsServiceClient,
+    fcm_project_id: &String,
+    token: &String,
+    notification_style: Option<NotificationStyle>,
+) {
+    let service_account_key = service_account_key(fcm_project_id);
+
+    with_retries(
+        async || {
+            client
+                .publish_service_account_key(into(service_account_key.clone()), notification_style)
+                .await
+                .unwrap();
+            Ok(())
+        },
+        5,
+    )
+    .await
+    .unwrap();
+
+    client
+        .create_clone_request(scenario.network_seed.clone())
+        .await
+        .unwrap();
+
+    wait_for_service_providers(&scenario.recipient.0).await;
+
+    let _response: () = make_service_request(
+        &scenario.recipient.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "register_fcm_token".into(),
+        RegisterFcmTokenInput {
+            fcm_project_id: fcm_project_id.clone(),
+            token: token.clone(),
+            locale: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    std::thread::sleep(Duration::from_secs(5));
+
+    wait_for_service_providers(&scenario.sender.0).await;
+}
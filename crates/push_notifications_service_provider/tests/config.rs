@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+
+use push_notifications_service_provider::config::ProviderConfig;
+use tempdir::TempDir;
+
+fn write_config(tmp: &TempDir, file_name: &str, contents: &str) -> PathBuf {
+    let path = tmp.path().join(file_name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn load_config_file_with_env_overrides() {
+    let tmp = TempDir::new("config").unwrap();
+    let path = write_config(
+        &tmp,
+        "config.toml",
+        r#"
+[network]
+bootstrap_url = "https://bootstrap.example.com"
+
+[fcm]
+request_timeout_secs = 10
+max_sends_per_second = 100
+"#,
+    );
+
+    // Passed explicitly instead of with set_var, which would race with the other tests
+    let env = [
+        (
+            "PUSH_NOTIFICATIONS_PROVIDER__FCM__REQUEST_TIMEOUT_SECS",
+            "5",
+        ),
+        (
+            "PUSH_NOTIFICATIONS_PROVIDER__NETWORK__SIGNAL_URL",
+            "wss://signal.example.com",
+        ),
+        ("UNRELATED_VARIABLE", "ignored"),
+    ];
+    let config = ProviderConfig::load_with_env(
+        Some(&path),
+        env.map(|(key, value)| (key.to_string(), value.to_string())),
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.network.bootstrap_url,
+        Some(String::from("https://bootstrap.example.com"))
+    );
+    assert_eq!(
+        config.network.signal_url,
+        Some(String::from("wss://signal.example.com"))
+    );
+    assert_eq!(config.fcm.request_timeout_secs, 5);
+    assert_eq!(config.fcm.max_sends_per_second, Some(100));
+    // Not present in the file, so it keeps its default
+    assert_eq!(config.reconcile.interval_secs, 60);
+}
+
+#[test]
+fn env_overrides_of_string_fields_are_not_decoded_as_json() {
+    let load = |env: &[(&str, &str)]| {
+        ProviderConfig::load_with_env(
+            None,
+            env.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string())),
+        )
+    };
+
+    // Reaches the validation as a string instead of failing to deserialize a boolean
+    let err = load(&[("PUSH_NOTIFICATIONS_PROVIDER__LOGGING__LEVEL", "true")]).unwrap_err();
+    assert!(format!("{err:?}").contains("Invalid logging.level \"true\""));
+
+    // Unset optional fields also fall back to strings
+    let err = load(&[("PUSH_NOTIFICATIONS_PROVIDER__NETWORK__SIGNAL_URL", "123")]).unwrap_err();
+    assert!(format!("{err:?}").contains("Invalid network.signal_url \"123\""));
+
+    let config = load(&[
+        ("PUSH_NOTIFICATIONS_PROVIDER__LOGGING__LEVEL", "debug"),
+        ("PUSH_NOTIFICATIONS_PROVIDER__NETWORK__ADMIN_PORT", "4000"),
+        (
+            "PUSH_NOTIFICATIONS_PROVIDER__NETWORK__MDNS_DISCOVERY",
+            "true",
+        ),
+    ])
+    .unwrap();
+    assert_eq!(config.logging.level, "debug");
+    assert_eq!(config.network.admin_port, Some(4000));
+    assert!(config.network.mdns_discovery);
+}
+
+#[test]
+fn load_yaml_config_file() {
+    let tmp = TempDir::new("config").unwrap();
+    let path = write_config(
+        &tmp,
+        "config.yaml",
+        r#"
+metrics:
+  port: 9090
+health:
+  port: 8081
+"#,
+    );
+
+    let config = ProviderConfig::load(Some(&path)).unwrap();
+
+    assert_eq!(config.metrics.port, Some(9090));
+    assert_eq!(config.health.port, Some(8081));
+}
+
+#[test]
+fn reject_unknown_fields() {
+    let tmp = TempDir::new("config").unwrap();
+    let path = write_config(&tmp, "config.toml", "[fcm]\nrequest_timeout = 10\n");
+
+    let err = ProviderConfig::load(Some(&path)).unwrap_err();
+
+    assert!(format!("{err:?}").contains("unknown field `request_timeout`"));
+}
+
+#[test]
+fn reject_invalid_values() {
+    let tmp = TempDir::new("config").unwrap();
+    let path = write_config(
+        &tmp,
+        "config.toml",
+        "[metrics]\nport = 8080\n[health]\nport = 8080\n",
+    );
+
+    let err = ProviderConfig::load(Some(&path)).unwrap_err();
+
+    assert!(err.to_string().contains("can't be the same port"));
+}
+
+#[test]
+fn default_config_roundtrips() {
+    let default_config = ProviderConfig::default_toml().unwrap();
+    let tmp = TempDir::new("config").unwrap();
+    let path = write_config(&tmp, "config.toml", &default_config);
+
+    ProviderConfig::load(Some(&path)).unwrap();
+}
+
+#[test]
+fn fcm_endpoints_are_threaded_into_the_fcm_client_config() {
+    let tmp = TempDir::new("config").unwrap();
+    let path = write_config(
+        &tmp,
+        "config.toml",
+        r#"
+[fcm]
+base_url = "http://localhost:9099"
+token_url = "http://localhost:9099/token"
+proxy = "http://proxy.internal:3128"
+request_timeout_secs = 7
+"#,
+    );
+
+    let config = ProviderConfig::load(Some(&path)).unwrap();
+    let fcm_client_config = config.fcm_client_config();
+
+    assert_eq!(fcm_client_config.fcm_base_url, "http://localhost:9099");
+    assert_eq!(
+        fcm_client_config.token_url,
+        Some(String::from("http://localhost:9099/token"))
+    );
+    assert_eq!(
+        fcm_client_config.proxy,
+        Some(String::from("http://proxy.internal:3128"))
+    );
+    assert_eq!(fcm_client_config.request_timeout.as_secs(), 7);
+}
+
+#[test]
+fn reject_missing_root_certificates() {
+    let tmp = TempDir::new("config").unwrap();
+    let path = write_config(
+        &tmp,
+        "config.toml",
+        "[fcm]\ntls_root_certificates = [\"/nonexistent/ca.pem\"]\n",
+    );
+
+    let err = ProviderConfig::load(Some(&path)).unwrap_err();
+
+    assert!(format!("{err:?}").contains("Failed to read root certificate"));
+}
@@ -0,0 +1,133 @@
+#[allow(dead_code)]
+mod common;
+use anyhow::anyhow;
+use common::*;
+use push_notifications_service_client::into;
+use push_notifications_service_provider::fcm_client::MockFcmClient;
+use push_notifications_types::{ServiceAccountKey, ServiceAccountKeyStatus};
+
+fn rotated_service_account_key(fcm_project_id: &String) -> ServiceAccountKey {
+    ServiceAccountKey {
+        private_key: String::from("private_key_2"),
+        private_key_id: Some(String::from("key_2")),
+        ..service_account_key(fcm_project_id)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rotated_key_is_activated_once_all_providers_acknowledge_it() {
+    let _lock = lock_mock_fcm_client();
+
+    // The providers only acknowledge the new key after validating it with FCM
+    let ctx = MockFcmClient::validate_fcm_project_context();
+    ctx.expect()
+        .withf(|_fcm_client, _fcm_project_id, service_account_key| {
+            service_account_key.private_key.eq("private_key_2")
+        })
+        .returning(|_, _, _| Ok(()));
+
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+    let (_tmp, client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    client
+        .rotate_service_account_key(
+            into(rotated_service_account_key(&fcm_project_id)),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    // Acknowledged on the next reconcile of each provider, which happens every minute
+    let history = with_retries(
+        async || {
+            let history = client
+                .get_service_account_key_history(fcm_project_id.clone())
+                .await?;
+            if !matches!(
+                history.first().map(|item| &item.status),
+                Some(ServiceAccountKeyStatus::Active)
+            ) || history.len() != 2
+            {
+                return Err(anyhow!("Rotation not finalized yet: {history:?}"));
+            }
+            Ok(history)
+        },
+        150,
+    )
+    .await
+    .unwrap();
+
+    // Finalized exactly once, even though all the providers try to finalize it
+    assert_eq!(history[0].private_key_id, Some(String::from("key_2")));
+    assert!(matches!(
+        history[1].status,
+        ServiceAccountKeyStatus::Retired { .. }
+    ));
+    assert_eq!(history[1].private_key_id, None);
+
+    let project = client
+        .get_fcm_project(fcm_project_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(project.private_key_id, Some(String::from("key_2")));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn invalid_rotated_key_is_not_acknowledged() {
+    let _lock = lock_mock_fcm_client();
+
+    let ctx = MockFcmClient::validate_fcm_project_context();
+    ctx.expect()
+        .returning(|_, _, _| Err(anyhow!("Invalid service account key")));
+
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+    let (_tmp, client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    client
+        .rotate_service_account_key(
+            into(rotated_service_account_key(&fcm_project_id)),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let history = client
+        .get_service_account_key_history(fcm_project_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(matches!(
+        history
+            .iter()
+            .find(|item| item.private_key_id.is_none())
+            .unwrap()
+            .status,
+        ServiceAccountKeyStatus::Active
+    ));
+    assert!(matches!(
+        history
+            .iter()
+            .find(|item| item.private_key_id.is_some())
+            .unwrap()
+            .status,
+        ServiceAccountKeyStatus::Pending {
+            acknowledgements: 0
+        }
+    ));
+
+    let project = client
+        .get_fcm_project(fcm_project_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(project.private_key_id, None);
+}
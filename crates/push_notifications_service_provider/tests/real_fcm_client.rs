@@ -0,0 +1,423 @@
+use fake_fcm_server::{FakeFcmServer, FcmErrorCode, UNTRUSTED_PRIVATE_KEY};
+use fcm_v1::auth::ServiceAccountKey;
+use push_notifications_service_provider::{
+    fcm_client::{FcmClient, FcmClientConfig, FcmHttpClient, RealFcmClient},
+    metrics::fcm_error_class,
+    send_limiter::SendLimiter,
+};
+use push_notifications_types::{
+    AndroidNotificationOptions, AndroidNotificationPriority, AndroidVisibility, InterruptionLevel,
+    IosNotificationOptions, NotificationStyle, PushNotification,
+};
+
+fn fcm_client_config(server: &FakeFcmServer) -> FcmClientConfig {
+    FcmClientConfig {
+        fcm_base_url: server.base_url(),
+        ..Default::default()
+    }
+}
+
+fn fcm_client(server: &FakeFcmServer) -> FcmHttpClient {
+    FcmHttpClient::new(fcm_client_config(server)).unwrap()
+}
+
+fn service_account_key(server: &FakeFcmServer) -> ServiceAccountKey {
+    serde_json::from_value(server.service_account_key("test-project")).unwrap()
+}
+
+fn notification() -> PushNotification {
+    PushNotification {
+        title: String::from("Hello"),
+        body: String::from("World"),
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_sends_apns_and_android_payloads() {
+    let server = FakeFcmServer::start().await.unwrap();
+
+    RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        String::from("device-token"),
+        notification(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(server.issued_tokens(), 1);
+    let messages = server.received_messages();
+    assert_eq!(messages.len(), 1);
+
+    let received = &messages[0];
+    assert_eq!(received.fcm_project_id, "test-project");
+    assert!(!received.validate_only);
+    assert_eq!(received.token(), Some("device-token"));
+    assert_eq!(
+        received.authorization.as_deref(),
+        Some("Bearer fake-access-token-1")
+    );
+    assert_eq!(
+        received.message["apns"]["payload"]["aps"]["alert"]["title"],
+        "Hello"
+    );
+    assert_eq!(
+        received.message["apns"]["payload"]["aps"]["mutable-content"],
+        1
+    );
+    assert_eq!(received.message["android"]["data"]["body"], "World");
+    assert!(received.message["android"]["notification"].is_null());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_signs_the_jwt_with_the_service_account_key() {
+    let server = FakeFcmServer::start().await.unwrap();
+
+    // Signed with a key that the OAuth endpoint doesn't trust for this service account
+    let mut untrusted_key = service_account_key(&server);
+    untrusted_key.private_key = String::from(UNTRUSTED_PRIVATE_KEY);
+    let err = RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        untrusted_key,
+        String::from("device-token"),
+        notification(),
+    )
+    .await
+    .unwrap_err();
+    assert!(format!("{err:#}").contains("Invalid JWT signature"));
+    assert_eq!(server.rejected_assertions(), 1);
+    assert_eq!(server.issued_tokens(), 0);
+    assert!(server.received_messages().is_empty());
+
+    RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        String::from("device-token"),
+        notification(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(server.rejected_assertions(), 1);
+    assert_eq!(server.issued_tokens(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_validates_fcm_project_without_sending() {
+    let server = FakeFcmServer::start().await.unwrap();
+
+    RealFcmClient::validate_fcm_project(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+    )
+    .await
+    .unwrap();
+
+    let messages = server.received_messages();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].validate_only);
+    assert_eq!(messages[0].message["topic"], "test");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_reports_fcm_error_codes() {
+    let server = FakeFcmServer::start().await.unwrap();
+    server.fail_token_with("stale-token", FcmErrorCode::Unregistered);
+
+    let err = RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        String::from("stale-token"),
+        notification(),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(fcm_error_class(&err), "unregistered");
+
+    server.fail_with(FcmErrorCode::QuotaExceeded);
+    let err = RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        String::from("device-token"),
+        notification(),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(fcm_error_class(&err), "quota_exceeded");
+
+    server.clear_errors();
+    RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        String::from("device-token"),
+        notification(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(server.received_messages().len(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_uses_the_configured_token_url() {
+    let server = FakeFcmServer::start().await.unwrap();
+    let mut service_account_key = service_account_key(&server);
+    // Unreachable: the token must be requested from the configured token URL instead
+    service_account_key.token_uri = String::from("http://127.0.0.1:1/token");
+
+    RealFcmClient::send_push_notification(
+        FcmHttpClient::new(FcmClientConfig {
+            token_url: Some(server.token_uri()),
+            ..fcm_client_config(&server)
+        })
+        .unwrap(),
+        String::from("test-project"),
+        service_account_key,
+        String::from("device-token"),
+        notification(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(server.issued_tokens(), 1);
+    assert_eq!(server.received_messages().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_sends_a_batch_with_one_access_token() {
+    let server = FakeFcmServer::start().await.unwrap();
+    server.fail_token_with("stale-token", FcmErrorCode::Unregistered);
+
+    let results = RealFcmClient::send_push_notification_batch(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        vec![
+            (String::from("device-token-1"), notification()),
+            (String::from("stale-token"), notification()),
+            (String::from("device-token-2"), notification()),
+        ],
+        SendLimiter::new(2, None),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(server.issued_tokens(), 1);
+    assert_eq!(server.received_messages().len(), 3);
+    assert!(results[0].is_ok());
+    assert_eq!(
+        fcm_error_class(results[1].as_ref().unwrap_err()),
+        "unregistered"
+    );
+    assert!(results[2].is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_maps_ios_options_to_the_aps_dictionary() {
+    let server = FakeFcmServer::start().await.unwrap();
+
+    RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        String::from("device-token"),
+        PushNotification {
+            ios: Some(IosNotificationOptions {
+                category: Some(String::from("MESSAGE")),
+                thread_id: Some(String::from("chat-1")),
+                interruption_level: Some(InterruptionLevel::TimeSensitive),
+                relevance_score: Some(0.5),
+                target_content_id: Some(String::from("chat-window")),
+            }),
+            ..notification()
+        },
+    )
+    .await
+    .unwrap();
+
+    let messages = server.received_messages();
+    let aps = &messages[0].message["apns"]["payload"]["aps"];
+    assert_eq!(aps["category"], "MESSAGE");
+    assert_eq!(aps["thread-id"], "chat-1");
+    assert_eq!(aps["interruption-level"], "time-sensitive");
+    assert_eq!(aps["relevance-score"], 0.5);
+    assert_eq!(aps["target-content-id"], "chat-window");
+    assert_eq!(aps["alert"]["title"], "Hello");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_maps_android_options_to_the_android_notification() {
+    let server = FakeFcmServer::start().await.unwrap();
+
+    RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        String::from("device-token"),
+        PushNotification {
+            android: Some(AndroidNotificationOptions {
+                channel_id: Some(String::from("messages")),
+                tag: Some(String::from("chat-1")),
+                notification_priority: Some(AndroidNotificationPriority::High),
+                visibility: Some(AndroidVisibility::Private),
+                icon: Some(String::from("ic_notification")),
+                color: Some(String::from("#ff0000")),
+                sticky: true,
+                direct_boot_ok: true,
+                notification_count: Some(3),
+            }),
+            ..notification()
+        },
+    )
+    .await
+    .unwrap();
+
+    let messages = server.received_messages();
+    let android = &messages[0].message["android"];
+    assert_eq!(android["direct_boot_ok"], true);
+    assert_eq!(android["data"]["title"], "Hello");
+    let android_notification = &android["notification"];
+    assert_eq!(android_notification["title"], "Hello");
+    assert_eq!(android_notification["body"], "World");
+    assert_eq!(android_notification["channel_id"], "messages");
+    assert_eq!(android_notification["tag"], "chat-1");
+    assert_eq!(
+        android_notification["notification_priority"],
+        "PRIORITY_HIGH"
+    );
+    assert_eq!(android_notification["visibility"], "PRIVATE");
+    assert_eq!(android_notification["icon"], "ic_notification");
+    assert_eq!(android_notification["color"], "#ff0000");
+    assert_eq!(android_notification["sticky"], true);
+    assert_eq!(android_notification["notification_count"], 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_applies_the_notification_style_on_every_platform() {
+    let server = FakeFcmServer::start().await.unwrap();
+
+    for style in [NotificationStyle::Display, NotificationStyle::DataOnly] {
+        RealFcmClient::send_push_notification(
+            fcm_client(&server),
+            String::from("test-project"),
+            service_account_key(&server),
+            String::from("device-token"),
+            PushNotification {
+                style: Some(style),
+                ..notification()
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let messages = server.received_messages();
+
+    let display = &messages[0].message;
+    assert!(display["data"].is_null());
+    assert!(display["android"]["data"].is_null());
+    assert_eq!(display["android"]["notification"]["title"], "Hello");
+    assert_eq!(display["apns"]["payload"]["aps"]["alert"]["title"], "Hello");
+    assert_eq!(display["webpush"]["notification"]["body"], "World");
+
+    let data_only = &messages[1].message;
+    assert_eq!(data_only["data"]["title"], "Hello");
+    assert_eq!(data_only["android"]["data"]["title"], "Hello");
+    assert!(data_only["android"]["notification"].is_null());
+    assert!(data_only["apns"]["payload"]["aps"]["alert"].is_null());
+    assert!(data_only["apns"]["payload"]["aps"]["mutable-content"].is_null());
+    assert_eq!(data_only["apns"]["payload"]["aps"]["content-available"], 1);
+    assert_eq!(data_only["apns"]["headers"]["apns-push-type"], "background");
+    assert_eq!(data_only["webpush"]["data"]["body"], "World");
+    assert!(data_only["webpush"]["notification"].is_null());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_keeps_the_ios_options_of_data_only_notifications() {
+    let server = FakeFcmServer::start().await.unwrap();
+
+    RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        String::from("device-token"),
+        PushNotification {
+            style: Some(NotificationStyle::DataOnly),
+            ios: Some(IosNotificationOptions {
+                category: Some(String::from("MESSAGE")),
+                thread_id: Some(String::from("chat-1")),
+                interruption_level: None,
+                relevance_score: None,
+                target_content_id: None,
+            }),
+            ..notification()
+        },
+    )
+    .await
+    .unwrap();
+
+    let messages = server.received_messages();
+    let aps = &messages[0].message["apns"]["payload"]["aps"];
+    assert!(aps["alert"].is_null());
+    assert_eq!(aps["content-available"], 1);
+    assert_eq!(aps["category"], "MESSAGE");
+    assert_eq!(aps["thread-id"], "chat-1");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_injects_the_badge() {
+    let server = FakeFcmServer::start().await.unwrap();
+
+    RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        String::from("device-token"),
+        PushNotification {
+            style: Some(NotificationStyle::Both),
+            badge: Some(4),
+            ..notification()
+        },
+    )
+    .await
+    .unwrap();
+
+    let messages = server.received_messages();
+    let message = &messages[0].message;
+    assert_eq!(message["apns"]["payload"]["aps"]["badge"], 4);
+    assert_eq!(message["android"]["notification"]["notification_count"], 4);
+    assert_eq!(message["android"]["data"]["badge"], "4");
+    assert_eq!(message["data"]["badge"], "4");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn real_fcm_client_delivers_the_badge_of_data_only_notifications_as_data() {
+    let server = FakeFcmServer::start().await.unwrap();
+
+    RealFcmClient::send_push_notification(
+        fcm_client(&server),
+        String::from("test-project"),
+        service_account_key(&server),
+        String::from("device-token"),
+        PushNotification {
+            style: Some(NotificationStyle::DataOnly),
+            badge: Some(4),
+            ..notification()
+        },
+    )
+    .await
+    .unwrap();
+
+    let messages = server.received_messages();
+    let message = &messages[0].message;
+    assert!(message["apns"]["payload"]["aps"]["badge"].is_null());
+    assert!(message["apns"]["payload"]["aps"]["mutable-content"].is_null());
+    assert_eq!(message["apns"]["headers"]["apns-push-type"], "background");
+    assert_eq!(message["data"]["badge"], "4");
+}
@@ -0,0 +1,166 @@
+use fixt::fixt;
+use holo_hash::fixt::AgentPubKeyFixturator;
+use holochain_client::AgentPubKey;
+use holochain_types::prelude::Timestamp;
+use push_notifications_service_provider::scheduled_notifications::{
+    ScheduledNotification, ScheduledNotifications, MAX_SCHEDULED_SEND_ATTEMPTS,
+    SCHEDULED_SEND_RETRY_DELAY,
+};
+use push_notifications_types::PushNotification;
+use tempdir::TempDir;
+
+fn scheduled_notification(
+    provenance: &AgentPubKey,
+    recipient: &AgentPubKey,
+    id: &str,
+    send_at: Timestamp,
+) -> ScheduledNotification {
+    ScheduledNotification {
+        token: String::from("myfcmtoken"),
+        fcm_project_id: String::from("FCM_PROJECT_1"),
+        notification: PushNotification {
+            title: String::from("Hey"),
+            body: String::from("there"),
+            ..Default::default()
+        },
+        provenance: provenance.clone(),
+        id: Some(id.to_string()),
+        send_at: Some(send_at),
+        recipient: Some(recipient.clone()),
+        trace_id: None,
+        attempts: 0,
+    }
+}
+
+#[test]
+fn scheduled_notifications_are_taken_when_due() {
+    let tmp = TempDir::new("scheduled").unwrap();
+    let scheduled =
+        ScheduledNotifications::load(tmp.path().join("scheduled_notifications.json")).unwrap();
+    let sender = fixt!(AgentPubKey);
+    let recipient = fixt!(AgentPubKey);
+
+    scheduled
+        .schedule(scheduled_notification(
+            &sender,
+            &recipient,
+            "1",
+            Timestamp::from_micros(1_000),
+        ))
+        .unwrap();
+    scheduled
+        .schedule(scheduled_notification(
+            &sender,
+            &recipient,
+            "2",
+            Timestamp::from_micros(2_000),
+        ))
+        .unwrap();
+
+    assert!(scheduled
+        .take_due(Timestamp::from_micros(500))
+        .unwrap()
+        .is_empty());
+
+    let due = scheduled.take_due(Timestamp::from_micros(1_500)).unwrap();
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].id, Some(String::from("1")));
+    assert_eq!(scheduled.count().unwrap(), 1);
+}
+
+#[test]
+fn scheduled_notifications_are_cancelled_only_for_their_sender_and_recipient() {
+    let tmp = TempDir::new("scheduled").unwrap();
+    let scheduled =
+        ScheduledNotifications::load(tmp.path().join("scheduled_notifications.json")).unwrap();
+    let sender = fixt!(AgentPubKey);
+    let recipient = fixt!(AgentPubKey);
+    let other_recipient = fixt!(AgentPubKey);
+    let send_at = Timestamp::from_micros(1_000);
+
+    scheduled
+        .schedule(scheduled_notification(&sender, &recipient, "1", send_at))
+        .unwrap();
+    scheduled
+        .schedule(scheduled_notification(
+            &sender,
+            &other_recipient,
+            "1",
+            send_at,
+        ))
+        .unwrap();
+
+    // Another sender can't cancel them
+    assert!(!scheduled
+        .cancel(&fixt!(AgentPubKey), &recipient, &String::from("1"))
+        .unwrap());
+
+    assert!(scheduled
+        .cancel(&sender, &recipient, &String::from("1"))
+        .unwrap());
+    assert!(!scheduled
+        .cancel(&sender, &recipient, &String::from("1"))
+        .unwrap());
+
+    let remaining = scheduled.take_due(send_at).unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].recipient, Some(other_recipient));
+}
+
+#[test]
+fn scheduled_notifications_survive_restarts() {
+    let tmp = TempDir::new("scheduled").unwrap();
+    let path = tmp.path().join("scheduled_notifications.json");
+    let sender = fixt!(AgentPubKey);
+    let recipient = fixt!(AgentPubKey);
+
+    let scheduled = ScheduledNotifications::load(path.clone()).unwrap();
+    for id in ["1", "2"] {
+        scheduled
+            .schedule(scheduled_notification(
+                &sender,
+                &recipient,
+                id,
+                Timestamp::from_micros(1_000),
+            ))
+            .unwrap();
+    }
+    scheduled
+        .cancel(&sender, &recipient, &String::from("1"))
+        .unwrap();
+    drop(scheduled);
+
+    // The private key of the service account is never written to disk
+    let persisted = std::fs::read_to_string(&path).unwrap();
+    assert!(!persisted.contains("private_key"));
+
+    let reloaded = ScheduledNotifications::load(path).unwrap();
+    let due = reloaded.take_due(Timestamp::from_micros(1_000)).unwrap();
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].id, Some(String::from("2")));
+}
+
+#[test]
+fn failed_scheduled_notifications_are_retried_with_backoff() {
+    let tmp = TempDir::new("scheduled").unwrap();
+    let scheduled =
+        ScheduledNotifications::load(tmp.path().join("scheduled_notifications.json")).unwrap();
+    let sender = fixt!(AgentPubKey);
+    let recipient = fixt!(AgentPubKey);
+    let now = Timestamp::from_micros(1_000);
+
+    let mut notification = scheduled_notification(&sender, &recipient, "1", now);
+    assert!(scheduled.retry_later(notification.clone(), now).unwrap());
+
+    // Not due until the retry delay has passed
+    assert!(scheduled.take_due(now).unwrap().is_empty());
+    let retry_at =
+        Timestamp::from_micros(now.as_micros() + SCHEDULED_SEND_RETRY_DELAY.as_micros() as i64);
+    let due = scheduled.take_due(retry_at).unwrap();
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].attempts, 1);
+
+    notification.attempts = MAX_SCHEDULED_SEND_ATTEMPTS - 1;
+    assert!(!scheduled.retry_later(notification, now).unwrap());
+    assert_eq!(scheduled.count().unwrap(), 0);
+}
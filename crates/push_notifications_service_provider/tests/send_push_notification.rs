@@ -1,19 +1,26 @@
-use std::time::Duration;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 mod common;
 use anyhow::anyhow;
 use common::*;
 use holochain_client::{AgentPubKey, ExternIO, ZomeCallTarget};
+use holochain_types::prelude::{Signal, Timestamp};
 use push_notifications_service_client::{into, PushNotificationsServiceClient};
 use push_notifications_service_provider::{fcm_client::MockFcmClient, SERVICES_ROLE_NAME};
 use push_notifications_types::{
-    PushNotification, RegisterFcmTokenInput, SendPushNotificationToAgentInput, ServiceAccountKey,
+    NotificationHistoryItem, NotificationPreferences, NotificationStyle, PushNotification,
+    PushNotificationRoute, RegisterFcmTokenInput, RemotePushNotification,
+    SendPushNotificationToAgentInput, SendPushNotificationToAgentsInput, ServiceAccountKey,
 };
 use service_providers_utils::make_service_request;
 use tempdir::TempDir;
 
 #[tokio::test(flavor = "multi_thread")]
 async fn send_push_notification() {
+    let _lock = lock_mock_fcm_client();
     let Scenario {
         network_seed,
         bootstrap_srv,
@@ -35,6 +42,7 @@ async fn send_push_notification() {
         private_key: String::from("private_key_1"),
         client_email: String::from("random@email.com"),
         token_uri: String::from("random://token.uri"),
+        notification_style: None,
     };
 
     let tmp = TempDir::new("pns").unwrap();
@@ -53,7 +61,7 @@ async fn send_push_notification() {
     with_retries(
         async || {
             client
-                .publish_service_account_key(into(service_account_key.clone()))
+                .publish_service_account_key(into(service_account_key.clone()), None)
                 .await
                 .unwrap();
             Ok(())
@@ -99,6 +107,7 @@ async fn send_push_notification() {
         RegisterFcmTokenInput {
             fcm_project_id: fcm_project_id.clone(),
             token: token.clone(),
+            locale: None,
         },
     )
     .await
@@ -108,7 +117,7 @@ async fn send_push_notification() {
 
     let ctx = MockFcmClient::send_push_notification_context();
     ctx.expect().once().returning(
-        |_fcm_project_id, _service_account_key, _token, _push_notification| {
+        |_fcm_client, _fcm_project_id, _service_account_key, _token, _push_notification| {
             Box::pin(async { Ok(()) })
         },
     );
@@ -144,7 +153,10 @@ async fn send_push_notification() {
             notification: PushNotification {
                 title: String::from("Hey"),
                 body: String::from("there"),
+                ..Default::default()
             },
+            id: None,
+            send_at: None,
         }],
     )
     .await
@@ -153,3 +165,675 @@ async fn send_push_notification() {
     std::thread::sleep(Duration::from_secs(5));
     ctx.checkpoint();
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retried_push_notification_is_sent_exactly_once() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+
+    let (_tmp, _client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    let ctx = MockFcmClient::send_push_notification_context();
+    ctx.expect().once().returning(
+        |_fcm_client, _fcm_project_id, _service_account_key, _token, _push_notification| {
+            Box::pin(async { Ok(()) })
+        },
+    );
+
+    // Simulate retries of the same request reaching each of the providers:
+    // all of them are routed to the provider assigned to the recipient, which deduplicates them
+    let providers = get_all_service_providers(&scenario.sender.0).await;
+    for provider in providers.iter().chain(providers.iter()) {
+        let _response: () = make_service_request_to_provider(
+            &scenario.sender.0,
+            provider,
+            "send_push_notifications",
+            vec![SendPushNotificationToAgentInput {
+                agent: scenario.recipient.0.my_pub_key.clone(),
+                notification: PushNotification {
+                    title: String::from("Hey"),
+                    body: String::from("there"),
+                    ..Default::default()
+                },
+                id: Some(String::from("notification-1")),
+                send_at: None,
+            }],
+        )
+        .await
+        .unwrap();
+    }
+
+    std::thread::sleep(Duration::from_secs(5));
+    ctx.checkpoint();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn each_push_notification_is_delivered_by_a_single_provider() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+
+    let (_tmp, _client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    let ctx = MockFcmClient::send_push_notification_context();
+    ctx.expect().times(4).returning(
+        |_fcm_client, _fcm_project_id, _service_account_key, _token, _push_notification| {
+            Box::pin(async { Ok(()) })
+        },
+    );
+
+    // Requests reaching any of the providers are only delivered by the one assigned to the recipient
+    let providers = get_all_service_providers(&scenario.sender.0).await;
+    for (i, provider) in providers.iter().chain(providers.iter()).enumerate() {
+        let _response: () = make_service_request_to_provider(
+            &scenario.sender.0,
+            provider,
+            "send_push_notifications",
+            vec![SendPushNotificationToAgentInput {
+                agent: scenario.recipient.0.my_pub_key.clone(),
+                notification: PushNotification {
+                    title: format!("Notification {i}"),
+                    body: String::from("there"),
+                    ..Default::default()
+                },
+                id: None,
+                send_at: None,
+            }],
+        )
+        .await
+        .unwrap();
+    }
+
+    std::thread::sleep(Duration::from_secs(5));
+    ctx.checkpoint();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn push_notifications_for_the_same_project_are_sent_in_one_batch() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+
+    let (_tmp, _client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    let single_ctx = MockFcmClient::send_push_notification_context();
+    single_ctx.expect().never();
+    let ctx = MockFcmClient::send_push_notification_batch_context();
+    ctx.expect()
+        .once()
+        .withf(
+            |_fcm_client, _fcm_project_id, _service_account_key, notifications, _send_limiter| {
+                notifications.len() == 3
+            },
+        )
+        .returning(
+            |_fcm_client, _fcm_project_id, _service_account_key, notifications, _send_limiter| {
+                let results = notifications.iter().map(|_| Ok(())).collect();
+                Box::pin(async move { Ok(results) })
+            },
+        );
+
+    let _response: () = make_service_request(
+        &scenario.sender.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "send_push_notifications".into(),
+        (0..3)
+            .map(|i| SendPushNotificationToAgentInput {
+                agent: scenario.recipient.0.my_pub_key.clone(),
+                notification: PushNotification {
+                    title: format!("Notification {i}"),
+                    body: String::from("there"),
+                    ..Default::default()
+                },
+                id: None,
+                send_at: None,
+            })
+            .collect::<Vec<_>>(),
+    )
+    .await
+    .unwrap();
+
+    std::thread::sleep(Duration::from_secs(5));
+    ctx.checkpoint();
+    single_ctx.checkpoint();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn send_push_notification_to_agents_with_a_shared_payload() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+
+    let (_tmp, _client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    // With three recipients and two providers, at least two of them are assigned to the same provider
+    let third = launch(
+        scenario.progenitors.clone(),
+        vec![String::from("services")],
+        end_user_happ_path(),
+        scenario.network_seed.clone(),
+        network_config(&scenario.bootstrap_srv),
+    )
+    .await;
+    wait_for_service_providers(&third.0).await;
+    for (app_ws, token) in [
+        (&scenario.sender.0, "senderfcmtoken"),
+        (&third.0, "thirdfcmtoken"),
+    ] {
+        let _response: () = make_service_request(
+            app_ws,
+            push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+            "register_fcm_token".into(),
+            RegisterFcmTokenInput {
+                fcm_project_id: fcm_project_id.clone(),
+                token: token.to_string(),
+                locale: None,
+            },
+        )
+        .await
+        .unwrap();
+    }
+    std::thread::sleep(Duration::from_secs(5));
+
+    let sent_tokens: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(vec![]));
+
+    let single_ctx = MockFcmClient::send_push_notification_context();
+    let single_sent_tokens = sent_tokens.clone();
+    single_ctx
+        .expect()
+        .times(0..=1)
+        .withf(
+            |_fcm_client, _fcm_project_id, _service_account_key, _token, push_notification| {
+                push_notification.title == "Group message"
+            },
+        )
+        .returning(
+            move |_fcm_client, _fcm_project_id, _service_account_key, token, _push_notification| {
+                single_sent_tokens.lock().unwrap().push(token);
+                Box::pin(async { Ok(()) })
+            },
+        );
+    let batch_ctx = MockFcmClient::send_push_notification_batch_context();
+    let batch_sent_tokens = sent_tokens.clone();
+    batch_ctx
+        .expect()
+        .times(1..=2)
+        .withf(
+            |_fcm_client, _fcm_project_id, _service_account_key, notifications, _send_limiter| {
+                notifications.len() >= 2
+                    && notifications
+                        .iter()
+                        .all(|(_token, notification)| notification.title == "Group message")
+            },
+        )
+        .returning(
+            move |_fcm_client,
+                  _fcm_project_id,
+                  _service_account_key,
+                  notifications,
+                  _send_limiter| {
+                let results = notifications.iter().map(|_| Ok(())).collect();
+                batch_sent_tokens
+                    .lock()
+                    .unwrap()
+                    .extend(notifications.into_iter().map(|(token, _)| token));
+                Box::pin(async move { Ok(results) })
+            },
+        );
+
+    let _response: () = make_service_request(
+        &scenario.sender.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "send_push_notification_to_agents".into(),
+        SendPushNotificationToAgentsInput {
+            agents: vec![
+                scenario.recipient.0.my_pub_key.clone(),
+                scenario.sender.0.my_pub_key.clone(),
+                third.0.my_pub_key.clone(),
+            ],
+            notification: PushNotification {
+                title: String::from("Group message"),
+                body: String::from("there"),
+                ..Default::default()
+            },
+            id: None,
+            send_at: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    std::thread::sleep(Duration::from_secs(5));
+    single_ctx.checkpoint();
+    batch_ctx.checkpoint();
+
+    let mut sent_tokens = sent_tokens.lock().unwrap().clone();
+    sent_tokens.sort();
+    assert_eq!(
+        sent_tokens,
+        vec![
+            String::from("myfcmtoken"),
+            String::from("senderfcmtoken"),
+            String::from("thirdfcmtoken"),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn push_notifications_are_kept_in_the_history_of_the_recipient() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+
+    let (_tmp, _client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    let ctx = MockFcmClient::send_push_notification_context();
+    ctx.expect().times(2).returning(
+        |_fcm_client, _fcm_project_id, _service_account_key, _token, _push_notification| {
+            Box::pin(async { Ok(()) })
+        },
+    );
+
+    for keep_in_history in [true, false] {
+        let _response: () = make_service_request(
+            &scenario.sender.0,
+            push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+            "send_push_notifications".into(),
+            vec![SendPushNotificationToAgentInput {
+                agent: scenario.recipient.0.my_pub_key.clone(),
+                notification: PushNotification {
+                    title: String::from("Hey"),
+                    body: String::from("there"),
+                    keep_in_history,
+                    ..Default::default()
+                },
+                id: None,
+                send_at: None,
+            }],
+        )
+        .await
+        .unwrap();
+    }
+
+    std::thread::sleep(Duration::from_secs(5));
+    ctx.checkpoint();
+
+    let history: Vec<NotificationHistoryItem> = make_service_request(
+        &scenario.recipient.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "get_notification_history".into(),
+        (),
+    )
+    .await
+    .unwrap();
+    assert_eq!(history.len(), 1);
+    assert!(!history[0].read);
+    assert_eq!(history[0].sender, scenario.sender.0.my_pub_key);
+    assert_eq!(
+        history[0].encrypted_notification.recipient,
+        scenario.recipient.0.my_pub_key
+    );
+
+    // Only the recipient can decrypt it
+    let notification: PushNotification = scenario
+        .recipient
+        .0
+        .call_zome(
+            ZomeCallTarget::RoleName(SERVICES_ROLE_NAME.into()),
+            "push_notifications_receiver".into(),
+            "decrypt_notification".into(),
+            ExternIO::encode(history[0].encrypted_notification.clone()).unwrap(),
+        )
+        .await
+        .unwrap()
+        .decode()
+        .unwrap();
+    assert_eq!(notification.title, "Hey");
+    assert_eq!(notification.body, "there");
+    assert!(notification.keep_in_history);
+
+    let result = scenario
+        .sender
+        .0
+        .call_zome(
+            ZomeCallTarget::RoleName(SERVICES_ROLE_NAME.into()),
+            "push_notifications_receiver".into(),
+            "decrypt_notification".into(),
+            ExternIO::encode(history[0].encrypted_notification.clone()).unwrap(),
+        )
+        .await;
+    assert!(result.is_err());
+
+    let _response: () = make_service_request(
+        &scenario.recipient.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "mark_notifications_as_read".into(),
+        vec![history[0].notification_hash.clone()],
+    )
+    .await
+    .unwrap();
+
+    with_retries(
+        async || {
+            let history: Vec<NotificationHistoryItem> = make_service_request(
+                &scenario.recipient.0,
+                push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+                "get_notification_history".into(),
+                (),
+            )
+            .await
+            .map_err(|err| anyhow!("{err:?}"))?;
+            if !history.first().map(|item| item.read).unwrap_or_default() {
+                return Err(anyhow!("Notification not marked as read yet"));
+            }
+            Ok(())
+        },
+        10,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn badge_count_is_only_incremented_for_sent_push_notifications() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+
+    let (_tmp, _client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    // The second push notification is rejected by FCM
+    let badges: Arc<Mutex<Vec<Option<u32>>>> = Arc::new(Mutex::new(Vec::new()));
+    let sent_badges = badges.clone();
+    let ctx = MockFcmClient::send_push_notification_context();
+    ctx.expect().times(4).returning(
+        move |_fcm_client, _fcm_project_id, _service_account_key, _token, push_notification| {
+            let mut badges = sent_badges.lock().unwrap();
+            badges.push(push_notification.badge);
+            let result = match badges.len() {
+                2 => Err(anyhow!("Internal error")),
+                _ => Ok(()),
+            };
+            Box::pin(async move { result })
+        },
+    );
+
+    let send = async || {
+        let _response: () = make_service_request(
+            &scenario.sender.0,
+            push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+            "send_push_notifications".into(),
+            vec![SendPushNotificationToAgentInput {
+                agent: scenario.recipient.0.my_pub_key.clone(),
+                notification: PushNotification {
+                    title: String::from("Hey"),
+                    body: String::from("there"),
+                    increment_badge: true,
+                    ..Default::default()
+                },
+                id: None,
+                send_at: None,
+            }],
+        )
+        .await
+        .unwrap();
+        std::thread::sleep(Duration::from_secs(5));
+    };
+
+    for _ in 0..3 {
+        send().await;
+    }
+
+    let _response: () = make_service_request(
+        &scenario.recipient.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "clear_badge".into(),
+        (),
+    )
+    .await
+    .unwrap();
+    std::thread::sleep(Duration::from_secs(5));
+
+    send().await;
+    ctx.checkpoint();
+
+    // The failed push notification doesn't count towards the badge
+    assert_eq!(
+        *badges.lock().unwrap(),
+        vec![Some(1), Some(2), Some(2), Some(1)]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn push_notifications_are_delivered_directly_to_online_recipients() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+
+    let (_tmp, _client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    let (signals_tx, mut signals_rx) = tokio::sync::mpsc::unbounded_channel();
+    scenario
+        .recipient
+        .0
+        .on_signal(move |signal| {
+            let Signal::App { signal, .. } = signal else {
+                return ();
+            };
+            if let Ok(notification) = signal.into_inner().decode::<RemotePushNotification>() {
+                let _ = signals_tx.send(notification);
+            }
+        })
+        .await;
+
+    // Only the notification with an id goes through FCM
+    let ctx = MockFcmClient::send_push_notification_context();
+    ctx.expect()
+        .once()
+        .withf(
+            |_fcm_client, _fcm_project_id, _service_account_key, _token, push_notification| {
+                push_notification.badge == Some(2)
+            },
+        )
+        .returning(
+            |_fcm_client, _fcm_project_id, _service_account_key, _token, _push_notification| {
+                Box::pin(async { Ok(()) })
+            },
+        );
+
+    let notification = PushNotification {
+        title: String::from("Hey"),
+        body: String::from("there"),
+        increment_badge: true,
+        keep_in_history: true,
+        ..Default::default()
+    };
+    let routes: Vec<PushNotificationRoute> = make_service_request(
+        &scenario.sender.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "send_push_notifications_with_fallback".into(),
+        vec![SendPushNotificationToAgentInput {
+            agent: scenario.recipient.0.my_pub_key.clone(),
+            notification: notification.clone(),
+            id: None,
+            send_at: None,
+        }],
+    )
+    .await
+    .unwrap();
+    assert_eq!(routes, vec![PushNotificationRoute::RemoteSignal]);
+
+    let received = tokio::time::timeout(Duration::from_secs(10), signals_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(received.sender, scenario.sender.0.my_pub_key);
+    assert_eq!(received.notification.title, "Hey");
+    // Prepared in the same way as the notifications sent through FCM
+    assert_eq!(received.notification.badge, Some(1));
+
+    std::thread::sleep(Duration::from_secs(5));
+
+    let routes: Vec<PushNotificationRoute> = make_service_request(
+        &scenario.sender.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "send_push_notifications_with_fallback".into(),
+        vec![SendPushNotificationToAgentInput {
+            agent: scenario.recipient.0.my_pub_key.clone(),
+            notification,
+            id: Some(String::from("1")),
+            send_at: None,
+        }],
+    )
+    .await
+    .unwrap();
+    assert_eq!(routes, vec![PushNotificationRoute::Fcm]);
+
+    std::thread::sleep(Duration::from_secs(5));
+    ctx.checkpoint();
+
+    // Both are kept in the history of the recipient
+    let history: Vec<NotificationHistoryItem> = make_service_request(
+        &scenario.recipient.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "get_notification_history".into(),
+        (),
+    )
+    .await
+    .unwrap();
+    assert_eq!(history.len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scheduled_push_notifications_respect_the_preferences_at_delivery_time() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+
+    let (_tmp, _client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    let ctx = MockFcmClient::send_push_notification_context();
+    ctx.expect().never();
+
+    let send_at = Timestamp::from_micros(Timestamp::now().as_micros() + 10_000_000);
+    let _response: () = make_service_request(
+        &scenario.sender.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "send_push_notifications".into(),
+        vec![SendPushNotificationToAgentInput {
+            agent: scenario.recipient.0.my_pub_key.clone(),
+            notification: PushNotification {
+                title: String::from("Hey"),
+                body: String::from("there"),
+                ..Default::default()
+            },
+            id: None,
+            send_at: Some(send_at),
+        }],
+    )
+    .await
+    .unwrap();
+
+    // Muted after the notification was scheduled
+    let _response: () = make_service_request(
+        &scenario.recipient.0,
+        push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+        "set_notification_preferences".into(),
+        NotificationPreferences {
+            muted_senders: vec![scenario.sender.0.my_pub_key.clone()],
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    std::thread::sleep(Duration::from_secs(20));
+    ctx.checkpoint();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn push_notifications_use_the_notification_style_of_the_project() {
+    let _lock = lock_mock_fcm_client();
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+
+    let (_tmp, _client) = setup_push_notifications_with_style(
+        &scenario,
+        &fcm_project_id,
+        &token,
+        Some(NotificationStyle::DataOnly),
+    )
+    .await;
+
+    let ctx = MockFcmClient::send_push_notification_context();
+    ctx.expect()
+        .withf(
+            |_fcm_client, _fcm_project_id, _service_account_key, _token, push_notification| {
+                push_notification.title.eq("Hey")
+                    && push_notification.style == Some(NotificationStyle::DataOnly)
+            },
+        )
+        .once()
+        .returning(
+            |_fcm_client, _fcm_project_id, _service_account_key, _token, _push_notification| {
+                Box::pin(async { Ok(()) })
+            },
+        );
+    // The style of the notification takes precedence over the one of the project
+    ctx.expect()
+        .withf(
+            |_fcm_client, _fcm_project_id, _service_account_key, _token, push_notification| {
+                push_notification.title.eq("Hello")
+                    && push_notification.style == Some(NotificationStyle::Display)
+            },
+        )
+        .once()
+        .returning(
+            |_fcm_client, _fcm_project_id, _service_account_key, _token, _push_notification| {
+                Box::pin(async { Ok(()) })
+            },
+        );
+
+    for (title, style) in [("Hey", None), ("Hello", Some(NotificationStyle::Display))] {
+        let _response: () = make_service_request(
+            &scenario.sender.0,
+            push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+            "send_push_notifications".into(),
+            vec![SendPushNotificationToAgentInput {
+                agent: scenario.recipient.0.my_pub_key.clone(),
+                notification: PushNotification {
+                    title: String::from(title),
+                    body: String::from("there"),
+                    style,
+                    ..Default::default()
+                },
+                id: None,
+                send_at: None,
+            }],
+        )
+        .await
+        .unwrap();
+    }
+
+    std::thread::sleep(Duration::from_secs(5));
+    ctx.checkpoint();
+}
@@ -0,0 +1,107 @@
+#[allow(dead_code)]
+mod common;
+use anyhow::anyhow;
+use common::*;
+use push_notifications_service_client::PushNotificationsServiceClient;
+use push_notifications_service_provider::fcm_client::MockFcmClient;
+use push_notifications_types::TestPushNotificationTarget;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn progenitors_receive_the_result_of_test_push_notifications() {
+    let _lock = lock_mock_fcm_client();
+
+    let validate_ctx = MockFcmClient::validate_push_notification_context();
+    validate_ctx
+        .expect()
+        .once()
+        .withf(|_config, fcm_project_id, _key, token, _notification| {
+            fcm_project_id.eq("FCM_PROJECT_1") && token.eq("myfcmtoken")
+        })
+        .returning(|_, _, _, _, _| Ok(()));
+    let send_ctx = MockFcmClient::send_push_notification_context();
+    send_ctx
+        .expect()
+        .once()
+        .returning(|_, _, _, _, _| Err(anyhow!("Requested entity was not found.")));
+
+    let (tmp, progenitor) = progenitor_client_data_dir().await;
+    let scenario = setup_with_progenitors(vec![progenitor.clone()]).await;
+
+    let client = PushNotificationsServiceClient::create(
+        tmp.path().to_path_buf(),
+        network_config(&scenario.bootstrap_srv),
+        "client-happ".into(),
+        client_happ_path(),
+        scenario.progenitors.clone(),
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!(client.agent_pub_key().await.unwrap(), progenitor);
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+    register_push_notifications(&scenario, &client, &fcm_project_id, &token).await;
+
+    let recipient = scenario
+        .recipient
+        .0
+        .app_info()
+        .await
+        .unwrap()
+        .unwrap()
+        .agent_pub_key;
+
+    // Dry run for the token registered by an agent
+    let result = client
+        .send_test_notification(
+            fcm_project_id.clone(),
+            TestPushNotificationTarget::Agent(recipient),
+            true,
+        )
+        .await
+        .unwrap();
+    assert!(result.dry_run);
+    assert_eq!(result.error, None);
+
+    // The error from FCM is reported back to the requester
+    let result = client
+        .send_test_notification(
+            fcm_project_id,
+            TestPushNotificationTarget::Token(token),
+            false,
+        )
+        .await
+        .unwrap();
+    assert!(!result.dry_run);
+    assert!(result
+        .error
+        .unwrap()
+        .contains("Requested entity was not found."));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn only_progenitors_can_request_test_push_notifications() {
+    let _lock = lock_mock_fcm_client();
+
+    let validate_ctx = MockFcmClient::validate_push_notification_context();
+    validate_ctx.expect().never();
+    let send_ctx = MockFcmClient::send_push_notification_context();
+    send_ctx.expect().never();
+
+    let scenario = setup().await;
+
+    let fcm_project_id = String::from("FCM_PROJECT_1");
+    let token = String::from("myfcmtoken");
+    let (_tmp, client) = setup_push_notifications(&scenario, &fcm_project_id, &token).await;
+
+    // The client is not one of the progenitors, so all the providers reject the request
+    let result = client
+        .send_test_notification(
+            fcm_project_id,
+            TestPushNotificationTarget::Token(token),
+            true,
+        )
+        .await;
+    assert!(result.is_err());
+}
@@ -1,10 +1,66 @@
 use hc_zome_traits::*;
 use hdk::prelude::*;
-pub use push_notifications_types::{RegisterFcmTokenInput, SendPushNotificationToAgentInput};
+pub use push_notifications_types::{
+    CancelScheduledPushNotificationInput, EncryptedNotification, NotificationHistoryItem,
+    NotificationPreferences, PushNotification, PushNotificationRoute, RegisterFcmTokenInput,
+    SendPushNotificationToAgentInput, SendPushNotificationToAgentsInput,
+};
 
 #[zome_trait]
 pub trait PushNotificationsService {
     fn register_fcm_token(input: RegisterFcmTokenInput) -> ExternResult<()>;
 
     fn send_push_notifications(input: Vec<SendPushNotificationToAgentInput>) -> ExternResult<()>;
+
+    /// Like `send_push_notifications`, but first tries to deliver each notification directly
+    /// to its recipient with a remote call, falling back to FCM if they are not reachable
+    /// within the network timeout of the conductor.
+    ///
+    /// The direct delivery is only possible if the recipient runs the `push_notifications_receiver`
+    /// zome in their services DNA, which emits the notification as a `RemotePushNotification` signal.
+    /// Scheduled notifications and notifications with an `id` always go through FCM.
+    ///
+    /// Returns the route taken by each notification, in the same order as the inputs.
+    fn send_push_notifications_with_fallback(
+        input: Vec<SendPushNotificationToAgentInput>,
+    ) -> ExternResult<Vec<PushNotificationRoute>>;
+
+    fn send_push_notification_to_agents(
+        input: SendPushNotificationToAgentsInput,
+    ) -> ExternResult<()>;
+
+    fn cancel_scheduled_push_notification(
+        input: CancelScheduledPushNotificationInput,
+    ) -> ExternResult<()>;
+
+    fn set_notification_preferences(input: NotificationPreferences) -> ExternResult<()>;
+
+    fn get_notification_preferences(input: ()) -> ExternResult<Option<NotificationPreferences>>;
+
+    /// Resets the badge counter of the caller, e.g. when they open the app
+    fn clear_badge(input: ()) -> ExternResult<()>;
+
+    /// Notifications kept in the inbox of the caller, newest first.
+    ///
+    /// They are encrypted for the caller, who can read them with [`decrypt_notification`].
+    fn get_notification_history(input: ()) -> ExternResult<Vec<NotificationHistoryItem>>;
+
+    fn mark_notifications_as_read(notification_hashes: Vec<ActionHash>) -> ExternResult<()>;
+}
+
+/// Decrypts a notification from the inbox of the agent calling this function
+pub fn decrypt_notification(
+    encrypted_notification: &EncryptedNotification,
+) -> ExternResult<PushNotification> {
+    let data = ed_25519_x_salsa20_poly1305_decrypt(
+        encrypted_notification.recipient.clone(),
+        encrypted_notification.encrypted_by.clone(),
+        XSalsa20Poly1305EncryptedData::new(
+            encrypted_notification.nonce.into(),
+            encrypted_notification.ciphertext.clone(),
+        ),
+    )?;
+    ExternIO(data.as_ref().to_vec())
+        .decode()
+        .map_err(|err| wasm_error!(err))
 }
@@ -1,4 +1,5 @@
 use hdi::prelude::*;
+use std::collections::BTreeMap;
 
 /// JSON schema of secret service account key.
 ///
@@ -30,26 +31,450 @@ pub struct ServiceAccountKey {
     pub auth_provider_x509_cert_url: Option<String>,
     /// client_x509_cert_url
     pub client_x509_cert_url: Option<String>,
+    /// Style of the notifications of the project that don't set their own [`PushNotification::style`]
+    #[serde(default)]
+    pub notification_style: Option<NotificationStyle>,
+}
+
+/// Longest grace period of a service account key rotation, of 30 days
+pub const MAX_ROTATION_GRACE_PERIOD_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Tag of the links to the service account keys that are being rotated in.
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct PendingServiceAccountKeyTag {
+    pub published_at: Timestamp,
+    /// After this period the key is activated even if not all the providers have acknowledged it
+    ///
+    /// At most [`MAX_ROTATION_GRACE_PERIOD_SECS`]
+    pub grace_period_secs: u64,
+}
+
+/// Tag of the link from an agent to themselves that holds their badge counter
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct BadgeCountTag {
+    pub count: u32,
+}
+
+/// Notification kept in the inbox of its recipient, encrypted so that only they can read it
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptedNotification {
+    pub recipient: AgentPubKey,
+    /// Agent that encrypted the notification, whose key is needed to decrypt it
+    pub encrypted_by: AgentPubKey,
+    pub nonce: [u8; 24],
+    /// The `PushNotification`, encrypted with XSalsa20Poly1305
+    pub ciphertext: Vec<u8>,
+}
+
+/// Tag of the links from the recipient to the notifications in their inbox
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct NotificationHistoryTag {
+    pub sender: AgentPubKey,
+    pub sent_at: Timestamp,
+    #[serde(default)]
+    pub read: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotificationHistoryItem {
+    pub notification_hash: ActionHash,
+    pub sender: AgentPubKey,
+    pub sent_at: Timestamp,
+    pub read: bool,
+    pub encrypted_notification: EncryptedNotification,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+pub struct MarkNotificationsAsReadForAgentInput {
+    pub agent: AgentPubKey,
+    pub notification_hashes: Vec<ActionHash>,
+}
+
+/// Tag of the links to the service account keys that were replaced, kept as the history of the project.
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct RetiredServiceAccountKeyTag {
+    pub retired_at: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RotateServiceAccountKeyInput {
+    pub service_account_key: ServiceAccountKey,
+    /// Defaults to one hour
+    #[serde(default)]
+    pub grace_period_secs: Option<u64>,
+}
+
+/// A service account key waiting to be acknowledged by the providers before it replaces the current one.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingServiceAccountKey {
+    pub key_hash: ActionHash,
+    pub fcm_project_id: String,
+    pub service_account_key: ServiceAccountKey,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status")]
+pub enum ServiceAccountKeyStatus {
+    Active,
+    Pending { acknowledgements: usize },
+    Retired { retired_at: Timestamp },
+}
+
+/// Metadata of a service account key of a project, without its private key.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceAccountKeyHistoryItem {
+    pub key_hash: ActionHash,
+    pub private_key_id: Option<String>,
+    pub client_email: String,
+    pub published_at: Timestamp,
+    pub status: ServiceAccountKeyStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct PushNotification {
     pub title: String,
     pub body: String,
+    /// Render the title and body from a published [`NotificationTemplate`] in the recipient's preferred locale.
+    ///
+    /// The given `title` and `body` are used as a fallback if the template can't be found.
+    #[serde(default)]
+    pub template: Option<NotificationTemplateArgs>,
+    /// Category of the notification, which recipients can mute in their [`NotificationPreferences`]
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Urgent notifications are delivered even during the quiet hours of the recipient
+    #[serde(default)]
+    pub urgent: bool,
+    /// Options that only apply to iOS devices
+    #[serde(default)]
+    pub ios: Option<IosNotificationOptions>,
+    /// Options that only apply to Android devices
+    #[serde(default)]
+    pub android: Option<AndroidNotificationOptions>,
+    /// If not set, the title and body are delivered as data on Android and displayed on iOS
+    #[serde(default)]
+    pub style: Option<NotificationStyle>,
+    /// Increments the badge counter of the recipient, which is reset when they call `clear_badge`
+    #[serde(default)]
+    pub increment_badge: bool,
+    /// Number shown on the app icon.
+    ///
+    /// Set by the service right before the notification is sent, to the badge counter of the recipient
+    /// including this notification, when `increment_badge` is set. The counter itself is only
+    /// incremented once the notification is sent.
+    #[serde(default)]
+    pub badge: Option<u32>,
+    /// Keeps the notification in the inbox of the recipient, see `get_notification_history`
+    #[serde(default)]
+    pub keep_in_history: bool,
+}
+
+/// How the devices handle the notification, applied on Android, iOS and web alike
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationStyle {
+    /// The system displays the title and body, without the app having to handle the message
+    Display,
+    /// The title and body are only delivered as data to the app, which must build the notification
+    DataOnly,
+    /// The system displays the notification, and the title and body are also delivered as data
+    Both,
+}
+
+/// Options mapped to the `aps` dictionary of the APNs payload
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct IosNotificationOptions {
+    /// Notification category registered by the app, which defines the actions shown with the notification
+    ///
+    /// Not to be confused with [`PushNotification::category`], which recipients can mute
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Notifications with the same thread id are grouped together
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    #[serde(default)]
+    pub interruption_level: Option<InterruptionLevel>,
+    /// Between 0 and 1, used to pick the notification featured in the notification summary
+    #[serde(default)]
+    pub relevance_score: Option<f64>,
+    /// Identifier of the window brought forward when the notification is opened
+    #[serde(default)]
+    pub target_content_id: Option<String>,
+}
+
+/// Options mapped to the Android config of the FCM message
+///
+/// Setting any of them other than `direct_boot_ok` makes Android display the notification itself,
+/// instead of delivering only its data to the app
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AndroidNotificationOptions {
+    /// Notification channel created by the app, required to display the notification on Android 8+
+    #[serde(default)]
+    pub channel_id: Option<String>,
+    /// Replaces the notification with the same tag, instead of adding a new one
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub notification_priority: Option<AndroidNotificationPriority>,
+    #[serde(default)]
+    pub visibility: Option<AndroidVisibility>,
+    /// Drawable resource of the small icon
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Color of the icon, in `#rrggbb` format
+    #[serde(default)]
+    pub color: Option<String>,
+    /// Keeps the notification when the user taps it
+    #[serde(default)]
+    pub sticky: bool,
+    /// Delivers the message before the device is unlocked
+    #[serde(default)]
+    pub direct_boot_ok: bool,
+    /// Number of items the notification represents, shown on the app icon badge
+    #[serde(default)]
+    pub notification_count: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AndroidNotificationPriority {
+    Min,
+    Low,
+    Default,
+    High,
+    Max,
+}
+
+/// Visibility of the notification on the lock screen
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AndroidVisibility {
+    Private,
+    Public,
+    Secret,
+}
+
+/// How the notification interrupts the user, see
+/// https://developer.apple.com/documentation/usernotifications/unnotificationinterruptionlevel
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InterruptionLevel {
+    Passive,
+    Active,
+    TimeSensitive,
+    /// Requires the critical alerts entitlement
+    Critical,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NotificationTemplateArgs {
+    pub template_id: String,
+    /// Values for the `{placeholder}`s in the template
+    pub args: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LocalizedNotification {
+    pub title: String,
+    pub body: String,
+}
+
+/// Named notification published by the app developer for an FCM project.
+///
+/// Titles and bodies can contain `{placeholder}`s, which get replaced with the arguments given by the sender.
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq, Eq)]
+pub struct NotificationTemplate {
+    pub fcm_project_id: String,
+    pub template_id: String,
+    /// Locale used when there is no translation for the recipient's locale
+    pub default_locale: String,
+    /// Translations keyed by locale (e.g. "en", "es-ES")
+    pub translations: BTreeMap<String, LocalizedNotification>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+pub struct GetNotificationTemplateInput {
+    pub fcm_project_id: String,
+    pub template_id: String,
+}
+
+/// What an agent wants to receive, set by the recipient themselves.
+#[hdk_entry_helper]
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct NotificationPreferences {
+    pub muted_senders: Vec<AgentPubKey>,
+    pub muted_categories: Vec<String>,
+    /// Non-urgent notifications received during quiet hours are dropped
+    pub quiet_hours: Option<QuietHours>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct QuietHours {
+    /// Minutes after midnight in the recipient's time zone, from 0 to 1439
+    pub start_minute: u32,
+    /// Minutes after midnight in the recipient's time zone, from 0 to 1439.
+    ///
+    /// Can be lower than `start_minute` for quiet hours spanning midnight.
+    pub end_minute: u32,
+    /// Offset from UTC of the recipient's time zone, in minutes
+    pub utc_offset_minutes: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetNotificationPreferencesForAgentInput {
+    pub agent: AgentPubKey,
+    pub preferences: NotificationPreferences,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SendPushNotificationSignal {
     pub token: String,
     pub fcm_project_id: String,
     pub service_account_key: ServiceAccountKey,
     pub notification: PushNotification,
+    pub provenance: AgentPubKey,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub send_at: Option<Timestamp>,
+    #[serde(default)]
+    pub recipient: Option<AgentPubKey>,
+    /// Identifies the notification in the logs, from the gateway zome call to the response from FCM
+    #[serde(default)]
+    pub trace_id: Option<String>,
+}
+
+/// Push notification of a `SendPushNotificationBatchSignal`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchedPushNotification {
+    pub token: String,
+    pub notification: PushNotification,
+    pub provenance: AgentPubKey,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub send_at: Option<Timestamp>,
+    #[serde(default)]
+    pub recipient: Option<AgentPubKey>,
+    #[serde(default)]
+    pub trace_id: Option<String>,
+}
+
+/// Push notifications for the same FCM project, to be sent with the same access token
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SendPushNotificationBatchSignal {
+    pub fcm_project_id: String,
+    pub service_account_key: ServiceAccountKey,
+    pub notifications: Vec<BatchedPushNotification>,
+}
+
+impl SendPushNotificationBatchSignal {
+    pub fn into_signals(self) -> Vec<SendPushNotificationSignal> {
+        self.notifications
+            .into_iter()
+            .map(|notification| SendPushNotificationSignal {
+                token: notification.token,
+                fcm_project_id: self.fcm_project_id.clone(),
+                service_account_key: self.service_account_key.clone(),
+                notification: notification.notification,
+                provenance: notification.provenance,
+                id: notification.id,
+                send_at: notification.send_at,
+                recipient: notification.recipient,
+                trace_id: notification.trace_id,
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CancelScheduledPushNotificationSignal {
+    pub provenance: AgentPubKey,
+    pub recipient: AgentPubKey,
+    pub id: String,
+}
+
+/// Scheduled push notification that a provider is about to send
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrepareScheduledPushNotificationInput {
+    pub provenance: AgentPubKey,
+    pub recipient: AgentPubKey,
+    pub notification: PushNotification,
+}
+
+/// Push notification that a provider has sent to FCM, recorded for its recipient
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordSentPushNotificationInput {
+    pub provenance: AgentPubKey,
+    pub recipient: AgentPubKey,
+    pub notification: PushNotification,
+    pub sent_at: Timestamp,
+}
+
+/// Recipient of a test push notification
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TestPushNotificationTarget {
+    Token(String),
+    /// Agent that has registered their FCM token for the project
+    Agent(AgentPubKey),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RequestTestPushNotificationInput {
+    /// Provider that will deliver the test push notification
+    pub provider: AgentPubKey,
+    pub request_id: String,
+    pub fcm_project_id: String,
+    pub target: TestPushNotificationTarget,
+    /// Only validate the push notification with FCM without delivering it
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TestPushNotificationRequest {
+    pub request_id: String,
+    pub fcm_project_id: String,
+    pub target: TestPushNotificationTarget,
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SendTestPushNotificationSignal {
+    pub request_id: String,
+    pub requester: AgentPubKey,
+    pub token: String,
+    pub fcm_project_id: String,
+    pub service_account_key: ServiceAccountKey,
+    pub dry_run: bool,
+}
+
+/// Sent back to the requester of a test push notification as a remote signal
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TestPushNotificationResult {
+    pub request_id: String,
+    pub provider: AgentPubKey,
+    pub dry_run: bool,
+    /// The error returned by FCM, if the push notification failed
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReportTestPushNotificationResultInput {
+    pub requester: AgentPubKey,
+    pub result: TestPushNotificationResult,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RegisterFcmTokenInput {
     pub fcm_project_id: String,
     pub token: String,
+    /// Preferred locale of the recipient, used to render notification templates
+    #[serde(default)]
+    pub locale: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -57,12 +482,27 @@ pub struct RegisterFcmTokenForAgentInput {
     pub fcm_project_id: String,
     pub token: String,
     pub agent: AgentPubKey,
+    #[serde(default)]
+    pub locale: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SendPushNotificationToAgentInput {
     pub agent: AgentPubKey,
     pub notification: PushNotification,
+    /// Identifier chosen by the sender, needed to cancel a scheduled notification.
+    ///
+    /// It's also the idempotency key of the notification. All the notifications for a recipient are
+    /// routed to the provider assigned to them, which skips the ids it has sent from the same sender
+    /// to the same recipient in the last 24 hours, whichever provider the retries reach first.
+    ///
+    /// This is only at-most-once while the assigned provider stays reachable: if it's offline, the
+    /// next provider in the ranking takes over and doesn't know which ids the other one has sent.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Deliver the notification at this time instead of right away
+    #[serde(default)]
+    pub send_at: Option<Timestamp>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -70,6 +510,88 @@ pub struct SendPushNotificationToAgentWithProvenanceInput {
     pub provenance: AgentPubKey,
     pub agent: AgentPubKey,
     pub notification: PushNotification,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub send_at: Option<Timestamp>,
+    #[serde(default)]
+    pub trace_id: Option<String>,
+}
+
+/// Sends the same notification to all the agents, without repeating it for each of them
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SendPushNotificationToAgentsInput {
+    pub agents: Vec<AgentPubKey>,
+    pub notification: PushNotification,
+    /// Identifier chosen by the sender, which is the idempotency key of the notification for
+    /// each of the recipients, as in `SendPushNotificationToAgentInput`
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub send_at: Option<Timestamp>,
+}
+
+/// Recipient of a `SendPushNotificationToAgentsWithProvenanceInput`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PushNotificationRecipient {
+    pub agent: AgentPubKey,
+    #[serde(default)]
+    pub trace_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SendPushNotificationToAgentsWithProvenanceInput {
+    pub provenance: AgentPubKey,
+    pub recipients: Vec<PushNotificationRecipient>,
+    pub notification: PushNotification,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub send_at: Option<Timestamp>,
+}
+
+/// Push notification delivered directly to its recipient while they are online, emitted
+/// as a signal to their app instead of going through FCM
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RemotePushNotification {
+    pub sender: AgentPubKey,
+    pub notification: PushNotification,
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+/// Push notification prepared by the service to be delivered directly to its recipient
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PreparedRemotePushNotification {
+    /// Rendered for the recipient, as it would be sent through FCM
+    Ready(PushNotification),
+    /// The recipient muted it, for the given reason
+    Muted(String),
+}
+
+/// How a push notification was handled by `send_push_notifications_with_fallback`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum PushNotificationRoute {
+    /// Delivered directly to the recipient, who was online
+    RemoteSignal,
+    /// Handed to the providers to be sent through FCM
+    Fcm,
+    /// Not delivered because the recipient muted it, for the given reason
+    Muted(String),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CancelScheduledPushNotificationInput {
+    /// Recipient of the scheduled notification
+    pub agent: AgentPubKey,
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CancelScheduledPushNotificationWithProvenanceInput {
+    pub provenance: AgentPubKey,
+    pub agent: AgentPubKey,
+    pub id: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, SerializedBytes)]
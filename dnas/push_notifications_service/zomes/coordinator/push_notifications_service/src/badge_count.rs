@@ -0,0 +1,73 @@
+use hdk::prelude::*;
+use push_notifications_service_integrity::*;
+
+#[hdk_extern]
+pub fn get_badge_count_for_agent(agent: AgentPubKey) -> ExternResult<u32> {
+    let links =
+        get_links(GetLinksInputBuilder::try_new(agent, LinkTypes::AgentToBadgeCount)?.build())?;
+
+    let Some(link) = links.into_iter().max_by_key(|link| link.timestamp) else {
+        return Ok(0);
+    };
+
+    let tag = BadgeCountTag::try_from(SerializedBytes::from(UnsafeBytes::from(link.tag.0)))
+        .map_err(|err| wasm_error!(err))?;
+
+    Ok(tag.count)
+}
+
+/// Adds the given number of notifications to the badge counter of the agent, returning its new value
+///
+/// The counter is read and written back through the DHT, so increments made concurrently
+/// by different providers for the same agent can be lost
+pub fn increment_badge_count_for_agent(agent: AgentPubKey, increment: u32) -> ExternResult<u32> {
+    let count = get_badge_count_for_agent(agent.clone())?.saturating_add(increment);
+    set_badge_count_for_agent(agent, count)?;
+    Ok(count)
+}
+
+#[hdk_extern]
+pub fn clear_badge_for_agent(agent: AgentPubKey) -> ExternResult<()> {
+    set_badge_count_for_agent(agent.clone(), 0)?;
+
+    info!("Cleared badge for agent: {agent}");
+
+    Ok(())
+}
+
+/// Replaces the badge counter of the agent with a new link, the latest one being the current counter
+///
+/// Only the links created by this provider can be deleted by it
+fn set_badge_count_for_agent(agent: AgentPubKey, count: u32) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let links = get_links(
+        GetLinksInputBuilder::try_new(agent.clone(), LinkTypes::AgentToBadgeCount)?.build(),
+    )?;
+
+    let mut links_from_other_providers = false;
+    for link in links {
+        if link.author.ne(&my_pub_key) {
+            links_from_other_providers = true;
+            continue;
+        }
+        get(link.create_link_hash.clone(), Default::default())?;
+        delete_link(link.create_link_hash)?;
+    }
+
+    // No link means a count of 0
+    if count == 0 && !links_from_other_providers {
+        return Ok(());
+    }
+
+    let tag_bytes =
+        SerializedBytes::try_from(BadgeCountTag { count }).map_err(|err| wasm_error!(err))?;
+
+    create_link(
+        agent.clone(),
+        agent,
+        LinkTypes::AgentToBadgeCount,
+        tag_bytes.bytes().to_vec(),
+    )?;
+
+    Ok(())
+}
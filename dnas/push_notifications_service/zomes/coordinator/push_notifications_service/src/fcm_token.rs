@@ -6,6 +6,8 @@ use push_notifications_types::RegisterFcmTokenForAgentInput;
 pub struct FcmTokenTag {
     pub fcm_project_id: String,
     pub token: String,
+    #[serde(default)]
+    pub locale: Option<String>,
 }
 
 #[hdk_extern]
@@ -13,6 +15,7 @@ pub fn register_fcm_token_for_agent(input: RegisterFcmTokenForAgentInput) -> Ext
     let tag = FcmTokenTag {
         fcm_project_id: input.fcm_project_id,
         token: input.token,
+        locale: input.locale,
     };
 
     if let Some(current_token) = get_fcm_token_for_agent(input.agent.clone())? {
@@ -49,6 +52,27 @@ pub fn get_fcm_token_for_agent(agent: AgentPubKey) -> ExternResult<Option<FcmTok
     let links =
         get_links(GetLinksInputBuilder::try_new(agent.clone(), LinkTypes::FcmToken)?.build())?;
 
+    fcm_token_from_links(links)
+}
+
+/// Gets the FCM tokens for all the agents with a single host call, in the same order as the agents
+pub fn get_fcm_tokens_for_agents(
+    agents: Vec<AgentPubKey>,
+) -> ExternResult<Vec<Option<FcmTokenTag>>> {
+    let inputs = agents
+        .into_iter()
+        .map(|agent| Ok(GetLinksInputBuilder::try_new(agent, LinkTypes::FcmToken)?.build()))
+        .collect::<ExternResult<Vec<GetLinksInput>>>()?;
+
+    let links_by_agent = HDK.with(|h| h.borrow().get_links(inputs))?;
+
+    links_by_agent
+        .into_iter()
+        .map(fcm_token_from_links)
+        .collect()
+}
+
+fn fcm_token_from_links(links: Vec<Link>) -> ExternResult<Option<FcmTokenTag>> {
     let Some(link) = links.first().cloned() else {
         return Ok(None);
     };
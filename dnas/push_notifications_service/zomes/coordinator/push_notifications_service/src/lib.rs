@@ -1,12 +1,20 @@
 use hdk::prelude::*;
 use push_notifications_service_integrity::*;
 
+pub mod badge_count;
 pub mod fcm_token;
+pub mod notification_history;
+pub mod notification_preferences;
+pub mod notification_template;
 pub mod send_push_notification_to_agent;
 pub mod service_account_key;
+pub mod service_account_key_rotation;
+pub mod test_push_notification;
 
 #[hdk_extern]
 pub fn init(_: ()) -> ExternResult<InitCallbackResult> {
+    test_push_notification::grant_test_push_notification_capability()?;
+
     Ok(InitCallbackResult::Pass)
 }
 
@@ -0,0 +1,200 @@
+use hdk::prelude::*;
+use push_notifications_service_integrity::*;
+use push_notifications_types::{
+    MarkNotificationsAsReadForAgentInput, NotificationHistoryItem, PushNotification,
+};
+use std::collections::BTreeMap;
+
+/// Maximum number of notifications kept in the inbox of each recipient
+pub const NOTIFICATION_HISTORY_MAX_ITEMS: usize = 100;
+
+/// Notifications older than this are removed from the inbox
+pub const NOTIFICATION_HISTORY_TTL: std::time::Duration =
+    std::time::Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Adds the notification to the inbox of the recipient, encrypted so that only they can read it,
+/// and removes the notifications that exceed the bounds of the inbox
+pub fn add_to_notification_history(
+    sender: AgentPubKey,
+    recipient: AgentPubKey,
+    notification: &PushNotification,
+    sent_at: Timestamp,
+) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let data = ExternIO::encode(notification).map_err(|err| wasm_error!(err))?;
+    let encrypted = ed_25519_x_salsa20_poly1305_encrypt(
+        my_pub_key.clone(),
+        recipient.clone(),
+        XSalsa20Poly1305Data::from(data.0),
+    )?;
+    let nonce: [u8; 24] = encrypted
+        .as_nonce_ref()
+        .as_ref()
+        .try_into()
+        .map_err(|_| wasm_error!(WasmErrorInner::Guest(String::from("Malformed nonce"))))?;
+
+    let action_hash = create_entry(EntryTypes::EncryptedNotification(EncryptedNotification {
+        recipient: recipient.clone(),
+        encrypted_by: my_pub_key,
+        nonce,
+        ciphertext: encrypted.as_encrypted_data_ref().to_vec(),
+    }))?;
+
+    create_link(
+        recipient.clone(),
+        action_hash,
+        LinkTypes::AgentToNotificationHistory,
+        history_tag_bytes(NotificationHistoryTag {
+            sender,
+            sent_at,
+            read: false,
+        })?,
+    )?;
+
+    prune_notification_history(recipient)
+}
+
+#[hdk_extern]
+pub fn get_notification_history_for_agent(
+    agent: AgentPubKey,
+) -> ExternResult<Vec<NotificationHistoryItem>> {
+    let mut items: Vec<NotificationHistoryItem> = Vec::new();
+
+    for (link, tag) in get_history_links(agent)? {
+        if is_expired(&tag)? {
+            continue;
+        }
+        let Some(action_hash) = link.target.into_action_hash() else {
+            continue;
+        };
+        let Some(record) = get(action_hash.clone(), GetOptions::default())? else {
+            continue;
+        };
+        let Ok(Some(encrypted_notification)) = record.entry().to_app_option() else {
+            continue;
+        };
+        items.push(NotificationHistoryItem {
+            notification_hash: action_hash,
+            sender: tag.sender,
+            sent_at: tag.sent_at,
+            read: tag.read,
+            encrypted_notification,
+        });
+    }
+
+    Ok(items)
+}
+
+#[hdk_extern]
+pub fn mark_notifications_as_read_for_agent(
+    input: MarkNotificationsAsReadForAgentInput,
+) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    for (link, tag) in get_history_links(input.agent.clone())? {
+        if tag.read {
+            continue;
+        }
+        let Some(action_hash) = link.target.clone().into_action_hash() else {
+            continue;
+        };
+        if !input.notification_hashes.contains(&action_hash) {
+            continue;
+        }
+
+        // Links created by other providers can't be deleted by this one:
+        // the read link takes precedence over them, see `get_history_links`
+        if link.author.eq(&my_pub_key) {
+            get(link.create_link_hash.clone(), Default::default())?;
+            delete_link(link.create_link_hash)?;
+        }
+        create_link(
+            input.agent.clone(),
+            action_hash,
+            LinkTypes::AgentToNotificationHistory,
+            history_tag_bytes(NotificationHistoryTag { read: true, ..tag })?,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Links to the notifications in the inbox of the agent, newest first
+///
+/// A notification may be linked more than once if it was marked as read by a provider other than
+/// the one that added it, in which case only the read link is returned
+fn get_history_links(agent: AgentPubKey) -> ExternResult<Vec<(Link, NotificationHistoryTag)>> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(agent, LinkTypes::AgentToNotificationHistory)?.build(),
+    )?;
+
+    let mut links_by_target: BTreeMap<AnyLinkableHash, (Link, NotificationHistoryTag)> =
+        BTreeMap::new();
+    for link in links {
+        let tag = NotificationHistoryTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+            link.tag.0.clone(),
+        )))
+        .map_err(|err| wasm_error!(err))?;
+        match links_by_target.get(&link.target) {
+            Some((_, existing_tag)) if existing_tag.read || !tag.read => {}
+            _ => {
+                links_by_target.insert(link.target.clone(), (link, tag));
+            }
+        }
+    }
+
+    let mut links: Vec<(Link, NotificationHistoryTag)> = links_by_target.into_values().collect();
+    links.sort_by(|(_, a), (_, b)| b.sent_at.cmp(&a.sent_at));
+
+    Ok(links)
+}
+
+/// Removes the notifications that exceed the bounds of the inbox
+///
+/// Only the notifications and links added by this provider can be removed by it: the rest are
+/// removed by the providers that added them, and are never returned once expired
+fn prune_notification_history(agent: AgentPubKey) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let all_links = get_links(
+        GetLinksInputBuilder::try_new(agent.clone(), LinkTypes::AgentToNotificationHistory)?
+            .build(),
+    )?;
+
+    for (i, (link, tag)) in get_history_links(agent)?.into_iter().enumerate() {
+        if i < NOTIFICATION_HISTORY_MAX_ITEMS && !is_expired(&tag)? {
+            continue;
+        }
+        // Including the links that were replaced when marking the notification as read
+        for notification_link in all_links
+            .iter()
+            .filter(|l| l.target.eq(&link.target) && l.author.eq(&my_pub_key))
+        {
+            get(
+                notification_link.create_link_hash.clone(),
+                Default::default(),
+            )?;
+            delete_link(notification_link.create_link_hash.clone())?;
+        }
+        let Some(action_hash) = link.target.into_action_hash() else {
+            continue;
+        };
+        let Some(record) = get(action_hash.clone(), GetOptions::default())? else {
+            continue;
+        };
+        if record.action().author().eq(&my_pub_key) {
+            delete_entry(action_hash)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_expired(tag: &NotificationHistoryTag) -> ExternResult<bool> {
+    let expires_at = (tag.sent_at + NOTIFICATION_HISTORY_TTL)
+        .map_err(|err| wasm_error!(WasmErrorInner::Guest(err.to_string())))?;
+    Ok(expires_at < sys_time()?)
+}
+
+fn history_tag_bytes(tag: NotificationHistoryTag) -> ExternResult<Vec<u8>> {
+    let tag_bytes = SerializedBytes::try_from(tag).map_err(|err| wasm_error!(err))?;
+    Ok(tag_bytes.bytes().to_vec())
+}
@@ -0,0 +1,253 @@
+use hdk::prelude::*;
+use push_notifications_service_integrity::*;
+use push_notifications_types::{PushNotification, SetNotificationPreferencesForAgentInput};
+
+#[hdk_extern]
+pub fn set_notification_preferences_for_agent(
+    input: SetNotificationPreferencesForAgentInput,
+) -> ExternResult<()> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(
+            input.agent.clone(),
+            LinkTypes::AgentToNotificationPreferences,
+        )?
+        .build(),
+    )?;
+
+    for link in links {
+        get(link.create_link_hash.clone(), Default::default())?;
+        delete_link(link.create_link_hash)?;
+    }
+
+    let action_hash = create_entry(EntryTypes::NotificationPreferences(input.preferences))?;
+
+    create_link(
+        input.agent.clone(),
+        action_hash,
+        LinkTypes::AgentToNotificationPreferences,
+        (),
+    )?;
+
+    info!("Set notification preferences for agent: {}", input.agent);
+
+    Ok(())
+}
+
+#[hdk_extern]
+pub fn get_notification_preferences_for_agent(
+    agent: AgentPubKey,
+) -> ExternResult<Option<NotificationPreferences>> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(agent, LinkTypes::AgentToNotificationPreferences)?.build(),
+    )?;
+
+    let Some(link) = links.into_iter().max_by_key(|link| link.timestamp) else {
+        return Ok(None);
+    };
+
+    let Some(record) = get(
+        link.target
+            .into_any_dht_hash()
+            .ok_or(wasm_error!(WasmErrorInner::Guest(String::from(
+                "Malformed link"
+            ))))?,
+        GetOptions::default(),
+    )?
+    else {
+        return Ok(None);
+    };
+
+    let preferences: NotificationPreferences = record
+        .entry()
+        .as_option()
+        .ok_or(wasm_error!(WasmErrorInner::Guest(String::from(
+            "Malformed notification preferences"
+        ))))?
+        .try_into()?;
+
+    Ok(Some(preferences))
+}
+
+/// Returns the reason why the recipient doesn't want to receive the given notification at the given time, if any.
+pub fn muted_reason_at(
+    preferences: &NotificationPreferences,
+    sender: &AgentPubKey,
+    notification: &PushNotification,
+    now: Timestamp,
+) -> Option<String> {
+    if preferences.muted_senders.contains(sender) {
+        return Some(format!("sender {sender} is muted"));
+    }
+
+    if let Some(category) = &notification.category {
+        if preferences.muted_categories.contains(category) {
+            return Some(format!("category {category} is muted"));
+        }
+    }
+
+    if let Some(quiet_hours) = &preferences.quiet_hours {
+        if !notification.urgent {
+            let minutes_since_epoch = now.as_micros() / (60 * 1_000_000);
+            let local_minute = (minutes_since_epoch + quiet_hours.utc_offset_minutes as i64)
+                .rem_euclid(24 * 60) as u32;
+
+            let in_quiet_hours = if quiet_hours.start_minute <= quiet_hours.end_minute {
+                quiet_hours.start_minute <= local_minute && local_minute < quiet_hours.end_minute
+            } else {
+                // Quiet hours span midnight
+                quiet_hours.start_minute <= local_minute || local_minute < quiet_hours.end_minute
+            };
+
+            if in_quiet_hours {
+                return Some(String::from("recipient is in quiet hours"));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use push_notifications_types::QuietHours;
+
+    use super::*;
+
+    fn agent(byte: u8) -> AgentPubKey {
+        AgentPubKey::from_raw_36(vec![byte; 36])
+    }
+
+    /// Timestamp at the given UTC time of the first day of the epoch
+    fn at(hour: i64, minute: i64) -> Timestamp {
+        Timestamp::from_micros((hour * 60 + minute) * 60 * 1_000_000)
+    }
+
+    fn notification() -> PushNotification {
+        PushNotification {
+            title: String::from("Hey"),
+            body: String::from("there"),
+            category: Some(String::from("chat")),
+            ..Default::default()
+        }
+    }
+
+    fn quiet_hours(
+        start_minute: u32,
+        end_minute: u32,
+        utc_offset_minutes: i32,
+    ) -> NotificationPreferences {
+        NotificationPreferences {
+            quiet_hours: Some(QuietHours {
+                start_minute,
+                end_minute,
+                utc_offset_minutes,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn delivers_when_nothing_is_muted() {
+        let preferences = NotificationPreferences::default();
+        assert_eq!(
+            muted_reason_at(&preferences, &agent(1), &notification(), at(12, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn mutes_muted_senders() {
+        let preferences = NotificationPreferences {
+            muted_senders: vec![agent(1)],
+            ..Default::default()
+        };
+        assert!(muted_reason_at(&preferences, &agent(1), &notification(), at(12, 0)).is_some());
+        assert_eq!(
+            muted_reason_at(&preferences, &agent(2), &notification(), at(12, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn mutes_muted_categories() {
+        let preferences = NotificationPreferences {
+            muted_categories: vec![String::from("chat")],
+            ..Default::default()
+        };
+        assert!(muted_reason_at(&preferences, &agent(1), &notification(), at(12, 0)).is_some());
+
+        let other_category = PushNotification {
+            category: Some(String::from("calls")),
+            ..notification()
+        };
+        assert_eq!(
+            muted_reason_at(&preferences, &agent(1), &other_category, at(12, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn mutes_during_quiet_hours() {
+        // 13:00 - 15:00
+        let preferences = quiet_hours(13 * 60, 15 * 60, 0);
+        assert!(muted_reason_at(&preferences, &agent(1), &notification(), at(13, 0)).is_some());
+        assert!(muted_reason_at(&preferences, &agent(1), &notification(), at(14, 59)).is_some());
+        assert_eq!(
+            muted_reason_at(&preferences, &agent(1), &notification(), at(12, 59)),
+            None
+        );
+        assert_eq!(
+            muted_reason_at(&preferences, &agent(1), &notification(), at(15, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn mutes_during_quiet_hours_spanning_midnight() {
+        // 22:00 - 07:00
+        let preferences = quiet_hours(22 * 60, 7 * 60, 0);
+        assert!(muted_reason_at(&preferences, &agent(1), &notification(), at(23, 30)).is_some());
+        assert!(muted_reason_at(&preferences, &agent(1), &notification(), at(0, 0)).is_some());
+        assert!(muted_reason_at(&preferences, &agent(1), &notification(), at(6, 59)).is_some());
+        assert_eq!(
+            muted_reason_at(&preferences, &agent(1), &notification(), at(7, 0)),
+            None
+        );
+        assert_eq!(
+            muted_reason_at(&preferences, &agent(1), &notification(), at(21, 59)),
+            None
+        );
+    }
+
+    #[test]
+    fn applies_the_utc_offset_of_the_recipient() {
+        // 22:00 - 07:00 at UTC+2, which is 20:00 - 05:00 UTC
+        let preferences = quiet_hours(22 * 60, 7 * 60, 120);
+        assert!(muted_reason_at(&preferences, &agent(1), &notification(), at(20, 30)).is_some());
+        assert_eq!(
+            muted_reason_at(&preferences, &agent(1), &notification(), at(5, 30)),
+            None
+        );
+
+        // 22:00 - 07:00 at UTC-5, which is 03:00 - 12:00 UTC
+        let preferences = quiet_hours(22 * 60, 7 * 60, -300);
+        assert!(muted_reason_at(&preferences, &agent(1), &notification(), at(3, 0)).is_some());
+        assert_eq!(
+            muted_reason_at(&preferences, &agent(1), &notification(), at(2, 59)),
+            None
+        );
+    }
+
+    #[test]
+    fn delivers_urgent_notifications_during_quiet_hours() {
+        let preferences = quiet_hours(22 * 60, 7 * 60, 0);
+        let urgent = PushNotification {
+            urgent: true,
+            ..notification()
+        };
+        assert_eq!(
+            muted_reason_at(&preferences, &agent(1), &urgent, at(23, 0)),
+            None
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use hdk::prelude::*;
+use push_notifications_service_integrity::*;
+use push_notifications_types::{
+    GetNotificationTemplateInput, LocalizedNotification, NotificationTemplateArgs, PushNotification,
+};
+
+use crate::service_account_key::fcm_project_path;
+
+#[hdk_extern]
+pub fn publish_notification_template(
+    notification_template: NotificationTemplate,
+) -> ExternResult<()> {
+    let path = fcm_project_path(&notification_template.fcm_project_id)?;
+    path.ensure()?;
+
+    let links = get_links(
+        GetLinksInputBuilder::try_new(path.path_entry_hash()?, LinkTypes::NotificationTemplates)?
+            .tag_prefix(LinkTag::from(
+                notification_template.template_id.as_bytes().to_vec(),
+            ))
+            .build(),
+    )?;
+
+    // The tag prefix also matches template ids that start with this one
+    for link in links
+        .into_iter()
+        .filter(|link| link.tag.0.eq(notification_template.template_id.as_bytes()))
+    {
+        get(link.create_link_hash.clone(), Default::default())?;
+        delete_link(link.create_link_hash)?;
+    }
+
+    let template_id = notification_template.template_id.clone();
+    let fcm_project_id = notification_template.fcm_project_id.clone();
+    let action_hash = create_entry(EntryTypes::NotificationTemplate(notification_template))?;
+
+    create_link(
+        path.path_entry_hash()?,
+        action_hash,
+        LinkTypes::NotificationTemplates,
+        template_id.as_bytes().to_vec(),
+    )?;
+
+    info!("Published notification template {template_id} for project {fcm_project_id}");
+
+    Ok(())
+}
+
+#[hdk_extern]
+pub fn get_notification_template(
+    input: GetNotificationTemplateInput,
+) -> ExternResult<Option<NotificationTemplate>> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(
+            fcm_project_path(&input.fcm_project_id)?.path_entry_hash()?,
+            LinkTypes::NotificationTemplates,
+        )?
+        .tag_prefix(LinkTag::from(input.template_id.as_bytes().to_vec()))
+        .build(),
+    )?;
+
+    // The tag prefix also matches template ids that start with this one
+    let Some(link) = links
+        .into_iter()
+        .find(|link| link.tag.0.eq(input.template_id.as_bytes()))
+    else {
+        return Ok(None);
+    };
+
+    let Some(record) = get(
+        link.target
+            .into_any_dht_hash()
+            .ok_or(wasm_error!(WasmErrorInner::Guest(String::from(
+                "Malformed link"
+            ))))?,
+        GetOptions::default(),
+    )?
+    else {
+        return Ok(None);
+    };
+
+    let template: NotificationTemplate = record
+        .entry()
+        .as_option()
+        .ok_or(wasm_error!(WasmErrorInner::Guest(String::from(
+            "Malformed notification template"
+        ))))?
+        .try_into()?;
+
+    Ok(Some(template))
+}
+
+/// Renders the given template in the given locale, falling back to the language without region
+/// (e.g. "es" for "es-ES") and then to the default locale of the template.
+///
+/// Returns `None` if the template doesn't exist.
+pub fn render_notification_template(
+    fcm_project_id: String,
+    locale: Option<String>,
+    template_args: NotificationTemplateArgs,
+) -> ExternResult<Option<LocalizedNotification>> {
+    let Some(template) = get_notification_template(GetNotificationTemplateInput {
+        fcm_project_id,
+        template_id: template_args.template_id.clone(),
+    })?
+    else {
+        return Ok(None);
+    };
+
+    let Some(localized) = select_translation(&template, locale) else {
+        return Ok(None);
+    };
+
+    Ok(Some(LocalizedNotification {
+        title: fill_placeholders(&localized.title, &template_args.args),
+        body: fill_placeholders(&localized.body, &template_args.args),
+    }))
+}
+
+fn select_translation(
+    template: &NotificationTemplate,
+    locale: Option<String>,
+) -> Option<LocalizedNotification> {
+    if let Some(locale) = locale {
+        if let Some(localized) = template.translations.get(&locale) {
+            return Some(localized.clone());
+        }
+        if let Some((_, localized)) = template
+            .translations
+            .iter()
+            .find(|(l, _)| language(l).eq(language(&locale)))
+        {
+            return Some(localized.clone());
+        }
+    }
+
+    template.translations.get(&template.default_locale).cloned()
+}
+
+fn language(locale: &str) -> &str {
+    locale.split(['-', '_']).next().unwrap_or(locale)
+}
+
+/// Replaces each `{placeholder}` with its argument in a single pass, so that argument values
+/// are never themselves scanned for placeholders.
+///
+/// Placeholders without an argument are left as they are.
+fn fill_placeholders(text: &str, args: &BTreeMap<String, String>) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        match args.get(&rest[1..end]) {
+            Some(value) => filled.push_str(value),
+            None => filled.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    filled.push_str(rest);
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> NotificationTemplate {
+        let localized = |title: &str, body: &str| LocalizedNotification {
+            title: title.into(),
+            body: body.into(),
+        };
+        NotificationTemplate {
+            fcm_project_id: "FCM_PROJECT_1".into(),
+            template_id: "new_message".into(),
+            default_locale: "en".into(),
+            translations: BTreeMap::from([
+                ("en".into(), localized("New message", "{sender} says hi")),
+                (
+                    "es".into(),
+                    localized("Nuevo mensaje", "{sender} dice hola"),
+                ),
+                (
+                    "pt-BR".into(),
+                    localized("Nova mensagem", "{sender} diz oi"),
+                ),
+            ]),
+        }
+    }
+
+    fn args(args: &[(&str, &str)]) -> BTreeMap<String, String> {
+        args.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn selects_the_exact_locale() {
+        let localized = select_translation(&template(), Some("pt-BR".into())).unwrap();
+        assert_eq!(localized.title, "Nova mensagem");
+    }
+
+    #[test]
+    fn falls_back_to_the_language_without_region() {
+        let localized = select_translation(&template(), Some("es-ES".into())).unwrap();
+        assert_eq!(localized.title, "Nuevo mensaje");
+
+        let localized = select_translation(&template(), Some("pt_PT".into())).unwrap();
+        assert_eq!(localized.title, "Nova mensagem");
+    }
+
+    #[test]
+    fn falls_back_to_the_default_locale() {
+        let localized = select_translation(&template(), Some("fr-FR".into())).unwrap();
+        assert_eq!(localized.title, "New message");
+
+        let localized = select_translation(&template(), None).unwrap();
+        assert_eq!(localized.title, "New message");
+    }
+
+    #[test]
+    fn fills_placeholders() {
+        assert_eq!(
+            fill_placeholders(
+                "{sender} sent {count} messages",
+                &args(&[("sender", "Alice"), ("count", "3")])
+            ),
+            "Alice sent 3 messages"
+        );
+    }
+
+    #[test]
+    fn does_not_fill_placeholders_inside_argument_values() {
+        assert_eq!(
+            fill_placeholders("{a} and {b}", &args(&[("a", "{b}"), ("b", "Bob")])),
+            "{b} and Bob"
+        );
+    }
+
+    #[test]
+    fn leaves_unknown_and_unterminated_placeholders_as_they_are() {
+        assert_eq!(
+            fill_placeholders("{unknown} {sender} {open", &args(&[("sender", "Alice")])),
+            "{unknown} Alice {open"
+        );
+    }
+}
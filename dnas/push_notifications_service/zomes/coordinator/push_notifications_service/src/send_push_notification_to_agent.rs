@@ -1,38 +1,366 @@
 use hdk::prelude::*;
 use push_notifications_types::{
-    SendPushNotificationSignal, SendPushNotificationToAgentWithProvenanceInput,
+    BatchedPushNotification, CancelScheduledPushNotificationSignal,
+    CancelScheduledPushNotificationWithProvenanceInput, PrepareScheduledPushNotificationInput,
+    PreparedRemotePushNotification, PushNotification, RecordSentPushNotificationInput,
+    SendPushNotificationBatchSignal, SendPushNotificationSignal,
+    SendPushNotificationToAgentWithProvenanceInput,
+    SendPushNotificationToAgentsWithProvenanceInput, ServiceAccountKey,
 };
+use std::collections::BTreeMap;
 
 use crate::{
-    fcm_token::get_fcm_token_for_agent, service_account_key::get_current_service_account_key,
+    badge_count::{get_badge_count_for_agent, increment_badge_count_for_agent},
+    fcm_token::{get_fcm_token_for_agent, get_fcm_tokens_for_agents, FcmTokenTag},
+    notification_history::add_to_notification_history,
+    notification_preferences::{get_notification_preferences_for_agent, muted_reason_at},
+    notification_template::render_notification_template,
+    service_account_key::get_current_service_account_key,
 };
 
 #[hdk_extern]
 pub fn send_push_notification_to_agent(
     input: SendPushNotificationToAgentWithProvenanceInput,
 ) -> ExternResult<()> {
-    let Some(token_tag) = get_fcm_token_for_agent(input.agent)? else {
+    let mut service_account_keys: BTreeMap<String, ServiceAccountKey> = BTreeMap::new();
+    let mut badges: BTreeMap<AgentPubKey, u32> = BTreeMap::new();
+    let token_tag = get_fcm_token_for_agent(input.agent.clone())?;
+
+    if let Some(signal) =
+        prepare_push_notification(input, token_tag, &mut service_account_keys, &mut badges)?
+    {
+        emit_signal(signal)?;
+    }
+
+    Ok(())
+}
+
+/// Sends all the push notifications, looking up the FCM tokens of the recipients in one batch
+#[hdk_extern]
+pub fn send_push_notification_batch(
+    inputs: Vec<SendPushNotificationToAgentWithProvenanceInput>,
+) -> ExternResult<()> {
+    let token_tags =
+        get_fcm_tokens_for_agents(inputs.iter().map(|input| input.agent.clone()).collect())?;
+
+    prepare_and_emit_push_notifications(inputs.into_iter().zip(token_tags).collect())
+}
+
+/// Sends the same notification to all the recipients, looking up their FCM tokens in one batch
+#[hdk_extern]
+pub fn send_push_notification_to_agents_with_provenance(
+    input: SendPushNotificationToAgentsWithProvenanceInput,
+) -> ExternResult<()> {
+    let token_tags = get_fcm_tokens_for_agents(
+        input
+            .recipients
+            .iter()
+            .map(|recipient| recipient.agent.clone())
+            .collect(),
+    )?;
+
+    let inputs = input
+        .recipients
+        .into_iter()
+        .map(|recipient| SendPushNotificationToAgentWithProvenanceInput {
+            provenance: input.provenance.clone(),
+            agent: recipient.agent,
+            notification: input.notification.clone(),
+            id: input.id.clone(),
+            send_at: input.send_at,
+            trace_id: recipient.trace_id,
+        })
+        .zip(token_tags)
+        .collect();
+
+    prepare_and_emit_push_notifications(inputs)
+}
+
+/// Prepares all the push notifications, emitting one signal per FCM project so that
+/// the provider can send them together
+///
+/// A notification that can't be prepared doesn't prevent the others from being sent
+fn prepare_and_emit_push_notifications(
+    inputs: Vec<(
+        SendPushNotificationToAgentWithProvenanceInput,
+        Option<FcmTokenTag>,
+    )>,
+) -> ExternResult<()> {
+    let mut service_account_keys: BTreeMap<String, ServiceAccountKey> = BTreeMap::new();
+    let mut badges: BTreeMap<AgentPubKey, u32> = BTreeMap::new();
+    let mut signals_by_project: BTreeMap<String, Vec<SendPushNotificationSignal>> = BTreeMap::new();
+
+    for (input, token_tag) in inputs {
+        let trace_id = input.trace_id.clone().unwrap_or_default();
+        match prepare_push_notification(input, token_tag, &mut service_account_keys, &mut badges) {
+            Ok(Some(signal)) => signals_by_project
+                .entry(signal.fcm_project_id.clone())
+                .or_default()
+                .push(signal),
+            Ok(None) => {}
+            Err(err) => warn!(trace_id = %trace_id, "Not sending push notification: {err:?}"),
+        }
+    }
+
+    for (fcm_project_id, mut signals) in signals_by_project {
+        if signals.len() == 1 {
+            if let Some(signal) = signals.pop() {
+                emit_signal(signal)?;
+            }
+            continue;
+        }
+        let Some(service_account_key) = service_account_keys.get(&fcm_project_id).cloned() else {
+            continue;
+        };
+        emit_signal(SendPushNotificationBatchSignal {
+            fcm_project_id,
+            service_account_key,
+            notifications: signals
+                .into_iter()
+                .map(|signal| BatchedPushNotification {
+                    token: signal.token,
+                    notification: signal.notification,
+                    provenance: signal.provenance,
+                    id: signal.id,
+                    send_at: signal.send_at,
+                    recipient: signal.recipient,
+                    trace_id: signal.trace_id,
+                })
+                .collect(),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Builds the signal for the provider, or returns None if the recipient has muted the notification
+///
+/// The service account keys are cached in `service_account_keys` across calls,
+/// and the badges of the notifications prepared for each recipient in `badges`
+fn prepare_push_notification(
+    input: SendPushNotificationToAgentWithProvenanceInput,
+    token_tag: Option<FcmTokenTag>,
+    service_account_keys: &mut BTreeMap<String, ServiceAccountKey>,
+    badges: &mut BTreeMap<AgentPubKey, u32>,
+) -> ExternResult<Option<SendPushNotificationSignal>> {
+    let trace_id = input.trace_id.clone().unwrap_or_default();
+    debug!(trace_id = %trace_id, "Sending push notification");
+
+    let Some(token_tag) = token_tag else {
         return Err(wasm_error!(WasmErrorInner::Guest(String::from(
             "Agent hasn't registered their FCM token yet"
         ))));
     };
 
-    let Some(service_account_key) =
-        get_current_service_account_key(token_tag.fcm_project_id.clone())?
-    else {
-        return Err(wasm_error!(WasmErrorInner::Guest(String::from(
-            "FCM authority hasn't registered a service account key yet"
-        ))));
+    let notification = match prepare_notification(&input, Some(&token_tag), badges)? {
+        PreparedRemotePushNotification::Ready(notification) => notification,
+        PreparedRemotePushNotification::Muted(reason) => {
+            info!(
+                trace_id = %trace_id,
+                "Not sending push notification to {}: {reason}", input.agent
+            );
+            return Ok(None);
+        }
+    };
+
+    let service_account_key = match service_account_keys.get(&token_tag.fcm_project_id) {
+        Some(service_account_key) => service_account_key.clone(),
+        None => {
+            let Some(service_account_key) =
+                get_current_service_account_key(token_tag.fcm_project_id.clone())?
+            else {
+                return Err(wasm_error!(WasmErrorInner::Guest(String::from(
+                    "FCM authority hasn't registered a service account key yet"
+                ))));
+            };
+            service_account_keys.insert(
+                token_tag.fcm_project_id.clone(),
+                service_account_key.clone(),
+            );
+            service_account_key
+        }
     };
 
-    let signal = SendPushNotificationSignal {
+    Ok(Some(SendPushNotificationSignal {
         token: token_tag.token,
         fcm_project_id: token_tag.fcm_project_id,
-        notification: input.notification,
+        notification,
         service_account_key,
+        provenance: input.provenance,
+        id: input.id,
+        send_at: input.send_at,
+        recipient: Some(input.agent),
+        trace_id: input.trace_id,
+    }))
+}
+
+/// Prepares the push notifications that the gateway delivers directly to their recipients,
+/// in the same way as the ones that are sent through FCM
+///
+/// Like those, they only count towards the badge and the history of their recipients once
+/// they are recorded with `record_sent_push_notifications`
+#[hdk_extern]
+pub fn prepare_remote_push_notifications(
+    inputs: Vec<SendPushNotificationToAgentWithProvenanceInput>,
+) -> ExternResult<Vec<PreparedRemotePushNotification>> {
+    let token_tags =
+        get_fcm_tokens_for_agents(inputs.iter().map(|input| input.agent.clone()).collect())?;
+    let mut badges: BTreeMap<AgentPubKey, u32> = BTreeMap::new();
+
+    inputs
+        .iter()
+        .zip(token_tags)
+        .map(|(input, token_tag)| prepare_notification(input, token_tag.as_ref(), &mut badges))
+        .collect()
+}
+
+/// Applies the preferences of the recipient, their badge count and the template of the notification,
+/// which is rendered for the FCM project and locale of their token
+fn prepare_notification(
+    input: &SendPushNotificationToAgentWithProvenanceInput,
+    token_tag: Option<&FcmTokenTag>,
+    badges: &mut BTreeMap<AgentPubKey, u32>,
+) -> ExternResult<PreparedRemotePushNotification> {
+    // Scheduled notifications are checked against the quiet hours at which they will be delivered,
+    // and again right before they are sent, see `prepare_scheduled_push_notification`
+    let now = sys_time()?;
+    let delivered_at = input
+        .send_at
+        .filter(|send_at| *send_at > now)
+        .unwrap_or(now);
+    if let Some(preferences) = get_notification_preferences_for_agent(input.agent.clone())? {
+        if let Some(reason) = muted_reason_at(
+            &preferences,
+            &input.provenance,
+            &input.notification,
+            delivered_at,
+        ) {
+            return Ok(PreparedRemotePushNotification::Muted(reason));
+        }
+    }
+
+    let mut notification = input.notification.clone();
+
+    // Scheduled notifications get their badge right before they are sent
+    if notification.increment_badge && delivered_at == now {
+        notification.badge = Some(next_badge(&input.agent, badges)?);
+    }
+
+    let notification = match (notification.template.clone(), token_tag) {
+        (Some(template_args), Some(token_tag)) => {
+            let template_id = template_args.template_id.clone();
+            match render_notification_template(
+                token_tag.fcm_project_id.clone(),
+                token_tag.locale.clone(),
+                template_args,
+            )? {
+                Some(rendered) => PushNotification {
+                    title: rendered.title,
+                    body: rendered.body,
+                    template: None,
+                    ..notification
+                },
+                None => {
+                    warn!("Notification template {template_id} not found: using fallback");
+                    notification
+                }
+            }
+        }
+        (Some(template_args), None) => {
+            warn!(
+                "Notification template {} can't be rendered without an FCM project: using fallback",
+                template_args.template_id
+            );
+            notification
+        }
+        (None, _) => notification,
     };
 
-    emit_signal(signal)?;
+    Ok(PreparedRemotePushNotification::Ready(notification))
+}
+
+/// Badge to show in the next notification for the agent: the notifications sent in the same call
+/// are numbered in order, since the counter is only incremented once they are sent,
+/// see `record_sent_push_notifications`
+fn next_badge(agent: &AgentPubKey, badges: &mut BTreeMap<AgentPubKey, u32>) -> ExternResult<u32> {
+    let badge = match badges.get(agent) {
+        Some(badge) => badge.saturating_add(1),
+        None => get_badge_count_for_agent(agent.clone())?.saturating_add(1),
+    };
+    badges.insert(agent.clone(), badge);
+    Ok(badge)
+}
+
+/// Called by the provider right before it sends a scheduled push notification, so that the
+/// preferences of the recipient at the time of the delivery are respected
+///
+/// Returns None if the recipient has muted the notification since it was scheduled,
+/// or the notification with the current badge of the recipient
+#[hdk_extern]
+pub fn prepare_scheduled_push_notification(
+    input: PrepareScheduledPushNotificationInput,
+) -> ExternResult<Option<PushNotification>> {
+    if let Some(preferences) = get_notification_preferences_for_agent(input.recipient.clone())? {
+        if let Some(reason) = muted_reason_at(
+            &preferences,
+            &input.provenance,
+            &input.notification,
+            sys_time()?,
+        ) {
+            info!(
+                "Not sending scheduled push notification to {}: {reason}",
+                input.recipient
+            );
+            return Ok(None);
+        }
+    }
+
+    let mut notification = input.notification;
+    if notification.increment_badge {
+        notification.badge = Some(next_badge(&input.recipient, &mut BTreeMap::new())?);
+    }
+
+    Ok(Some(notification))
+}
+
+/// Called by the provider once it has sent the push notifications, so that the ones that are muted,
+/// cancelled, duplicated or fail to be sent don't count towards the badge of their recipients,
+/// nor are kept in their history
+#[hdk_extern]
+pub fn record_sent_push_notifications(
+    inputs: Vec<RecordSentPushNotificationInput>,
+) -> ExternResult<()> {
+    let mut badge_increments: BTreeMap<AgentPubKey, u32> = BTreeMap::new();
+    for input in inputs {
+        if input.notification.increment_badge {
+            *badge_increments.entry(input.recipient.clone()).or_default() += 1;
+        }
+        if input.notification.keep_in_history {
+            add_to_notification_history(
+                input.provenance,
+                input.recipient,
+                &input.notification,
+                input.sent_at,
+            )?;
+        }
+    }
+
+    for (recipient, increment) in badge_increments {
+        increment_badge_count_for_agent(recipient, increment)?;
+    }
+
+    Ok(())
+}
+
+#[hdk_extern]
+pub fn cancel_scheduled_push_notification_for_agent(
+    input: CancelScheduledPushNotificationWithProvenanceInput,
+) -> ExternResult<()> {
+    emit_signal(CancelScheduledPushNotificationSignal {
+        provenance: input.provenance,
+        recipient: input.agent,
+        id: input.id,
+    })?;
 
     Ok(())
 }
@@ -1,11 +1,11 @@
 use hdk::prelude::*;
 use push_notifications_service_integrity::*;
 
-fn fcm_project_path(fcm_project_id: &String) -> ExternResult<TypedPath> {
+pub fn fcm_project_path(fcm_project_id: &String) -> ExternResult<TypedPath> {
     Path::from(format!("fcm_projects.{}", fcm_project_id)).typed(LinkTypes::FcmProjectPath)
 }
 
-fn fcm_projects_path() -> ExternResult<TypedPath> {
+pub fn fcm_projects_path() -> ExternResult<TypedPath> {
     Path::from("fcm_projects").typed(LinkTypes::FcmProjectPath)
 }
 
@@ -16,7 +16,7 @@ pub fn publish_service_account_key(service_account_key: ServiceAccountKey) -> Ex
             "Invalid ServiceAccountKey: project_id is null."
         ));
     };
-    delete_all_service_account_keys(&project_id)?;
+    retire_all_service_account_keys(&project_id)?;
     let path = fcm_project_path(&project_id)?;
     path.ensure()?;
 
@@ -44,7 +44,8 @@ pub fn publish_service_account_key(service_account_key: ServiceAccountKey) -> Ex
     Ok(())
 }
 
-fn delete_all_service_account_keys(fcm_project_id: &String) -> ExternResult<()> {
+/// Deactivates all the current service account keys of the project, keeping them in its history
+pub fn retire_all_service_account_keys(fcm_project_id: &String) -> ExternResult<()> {
     let path = fcm_project_path(fcm_project_id)?;
 
     let links = get_links(
@@ -52,16 +53,27 @@ fn delete_all_service_account_keys(fcm_project_id: &String) -> ExternResult<()>
             .build(),
     )?;
 
+    let tag = RetiredServiceAccountKeyTag {
+        retired_at: sys_time()?,
+    };
+    let tag_bytes = SerializedBytes::try_from(tag).map_err(|err| wasm_error!(err))?;
+
     for link in links {
         get(link.create_link_hash.clone(), Default::default())?;
         delete_link(link.create_link_hash)?;
+        create_link(
+            path.path_entry_hash()?,
+            link.target,
+            LinkTypes::RetiredServiceAccountKeys,
+            tag_bytes.bytes().to_vec(),
+        )?;
     }
     Ok(())
 }
 
 #[hdk_extern]
 pub fn delete_fcm_project(fcm_project_id: String) -> ExternResult<()> {
-    delete_all_service_account_keys(&fcm_project_id)?;
+    retire_all_service_account_keys(&fcm_project_id)?;
 
     let path = fcm_projects_path()?;
     let fcm_project_path_entry_hash = fcm_project_path(&fcm_project_id)?.path_entry_hash()?;
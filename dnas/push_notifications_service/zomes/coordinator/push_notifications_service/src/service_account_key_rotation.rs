@@ -0,0 +1,427 @@
+use hdk::prelude::*;
+use push_notifications_service_integrity::*;
+use push_notifications_types::{
+    PendingServiceAccountKey, RotateServiceAccountKeyInput, ServiceAccountKeyHistoryItem,
+    ServiceAccountKeyStatus,
+};
+
+use crate::service_account_key::{
+    fcm_project_path, get_all_fcm_projects, get_current_service_account_key,
+    publish_service_account_key, retire_all_service_account_keys,
+};
+
+/// Grace period after which a pending key is activated even if not all providers have acknowledged it
+pub const DEFAULT_ROTATION_GRACE_PERIOD_SECS: u64 = 60 * 60;
+
+/// Publishes the new service account key as pending: the current key stays active until
+/// all the providers have acknowledged the new one, or the grace period has passed
+#[hdk_extern]
+pub fn rotate_service_account_key(input: RotateServiceAccountKeyInput) -> ExternResult<()> {
+    let Some(project_id) = input.service_account_key.project_id.clone() else {
+        return Err(wasm_error!(
+            "Invalid ServiceAccountKey: project_id is null."
+        ));
+    };
+
+    // Nothing to overlap with: the key can be activated right away
+    let Some(current_key) = get_current_service_account_key(project_id.clone())? else {
+        return publish_service_account_key(input.service_account_key);
+    };
+
+    let grace_period_secs = input
+        .grace_period_secs
+        .unwrap_or(DEFAULT_ROTATION_GRACE_PERIOD_SECS);
+    if grace_period_secs > MAX_ROTATION_GRACE_PERIOD_SECS {
+        return Err(wasm_error!(WasmErrorInner::Guest(format!(
+            "The grace period can't be longer than {MAX_ROTATION_GRACE_PERIOD_SECS} seconds."
+        ))));
+    }
+
+    let path = fcm_project_path(&project_id)?;
+    path.ensure()?;
+
+    // The new key keeps the notification style of the project unless it sets its own
+    let mut service_account_key = input.service_account_key;
+    if service_account_key.notification_style.is_none() {
+        service_account_key.notification_style = current_key.notification_style;
+    }
+    let action_hash = create_entry(EntryTypes::ServiceAccountKey(service_account_key))?;
+
+    let tag = PendingServiceAccountKeyTag {
+        published_at: sys_time()?,
+        grace_period_secs,
+    };
+    let tag_bytes = SerializedBytes::try_from(tag).map_err(|err| wasm_error!(err))?;
+
+    create_link(
+        path.path_entry_hash()?,
+        action_hash,
+        LinkTypes::PendingServiceAccountKeys,
+        tag_bytes.bytes().to_vec(),
+    )?;
+
+    info!("Created pending service account key for project {project_id}");
+
+    Ok(())
+}
+
+#[hdk_extern]
+pub fn get_pending_service_account_keys() -> ExternResult<Vec<PendingServiceAccountKey>> {
+    let mut pending_keys: Vec<PendingServiceAccountKey> = Vec::new();
+
+    for fcm_project_id in get_all_fcm_projects()? {
+        for (link, _tag) in get_pending_links(&fcm_project_id)? {
+            let Some(key_hash) = link.target.into_action_hash() else {
+                continue;
+            };
+            let Some(service_account_key) = get_service_account_key(key_hash.clone())? else {
+                continue;
+            };
+            pending_keys.push(PendingServiceAccountKey {
+                key_hash,
+                fcm_project_id: fcm_project_id.clone(),
+                service_account_key,
+            });
+        }
+    }
+
+    Ok(pending_keys)
+}
+
+/// Called by the providers once they have verified that they can use the pending key
+#[hdk_extern]
+pub fn acknowledge_service_account_key(key_hash: ActionHash) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+
+    if get_acknowledgements(&key_hash)?.contains(&my_pub_key) {
+        return Ok(());
+    }
+
+    create_link(
+        key_hash,
+        my_pub_key,
+        LinkTypes::ServiceAccountKeyAcknowledgements,
+        (),
+    )?;
+
+    Ok(())
+}
+
+/// Activates the pending keys that have been acknowledged by all the providers, or whose
+/// grace period has passed, retiring the keys that they replace
+///
+/// All the providers call this periodically, but only one of them finalizes each rotation
+/// so that they don't race to retire and activate the keys; activating a key that is
+/// already active only removes its pending link, so finalizing twice has no effect
+#[hdk_extern]
+pub fn finalize_service_account_key_rotations() -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let providers = get_clone_providers()?;
+    let now = sys_time()?;
+
+    for fcm_project_id in get_all_fcm_projects()? {
+        for (link, tag) in get_pending_links(&fcm_project_id)? {
+            let Some(key_hash) = link.target.clone().into_action_hash() else {
+                continue;
+            };
+            if !is_rotation_finalizer(&my_pub_key, &providers, &tag, now) {
+                continue;
+            }
+            let acknowledgements = get_acknowledgements(&key_hash)?;
+            if !is_ready_to_finalize(&acknowledgements, &providers, &tag, now) {
+                continue;
+            }
+
+            let path_hash = fcm_project_path(&fcm_project_id)?.path_entry_hash()?;
+            let active_links = get_links(
+                GetLinksInputBuilder::try_new(path_hash.clone(), LinkTypes::ServiceAccountKeys)?
+                    .build(),
+            )?;
+            let already_active = active_links
+                .iter()
+                .any(|active_link| active_link.target.eq(&link.target));
+
+            if !already_active {
+                retire_all_service_account_keys(&fcm_project_id)?;
+                create_link(path_hash, key_hash, LinkTypes::ServiceAccountKeys, ())?;
+                info!("Activated rotated service account key for project {fcm_project_id}");
+            }
+            delete_link(link.create_link_hash)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The provider with the lowest agent key finalizes the rotations; the others take over
+/// only if it hasn't done so after twice the grace period, e.g. because it's offline
+fn is_rotation_finalizer(
+    me: &AgentPubKey,
+    providers: &[AgentPubKey],
+    tag: &PendingServiceAccountKeyTag,
+    now: Timestamp,
+) -> bool {
+    if providers.iter().min().is_some_and(|first| first.eq(me)) {
+        return true;
+    }
+    now.as_micros() >= grace_period_end(tag, 2)
+}
+
+fn is_ready_to_finalize(
+    acknowledgements: &[AgentPubKey],
+    providers: &[AgentPubKey],
+    tag: &PendingServiceAccountKeyTag,
+    now: Timestamp,
+) -> bool {
+    let acknowledged_by_all = !providers.is_empty()
+        && providers
+            .iter()
+            .all(|provider| acknowledgements.contains(provider));
+    let grace_period_passed = now.as_micros() >= grace_period_end(tag, 1);
+
+    acknowledged_by_all || grace_period_passed
+}
+
+/// Microseconds timestamp at which the given number of grace periods have passed since the key was published
+///
+/// Saturates instead of overflowing, as tags published before the grace period was bounded can hold any value
+fn grace_period_end(tag: &PendingServiceAccountKeyTag, grace_periods: i64) -> i64 {
+    let grace_period_micros = i64::try_from(tag.grace_period_secs)
+        .unwrap_or(i64::MAX)
+        .saturating_mul(1_000_000)
+        .saturating_mul(grace_periods);
+    tag.published_at
+        .as_micros()
+        .saturating_add(grace_period_micros)
+}
+
+#[hdk_extern]
+pub fn get_service_account_key_history(
+    fcm_project_id: String,
+) -> ExternResult<Vec<ServiceAccountKeyHistoryItem>> {
+    let path_hash = fcm_project_path(&fcm_project_id)?.path_entry_hash()?;
+    let mut history: Vec<ServiceAccountKeyHistoryItem> = Vec::new();
+
+    let active_links = get_links(
+        GetLinksInputBuilder::try_new(path_hash.clone(), LinkTypes::ServiceAccountKeys)?.build(),
+    )?;
+    for link in active_links {
+        if let Some(item) = history_item(link.target, ServiceAccountKeyStatus::Active)? {
+            history.push(item);
+        }
+    }
+
+    for (link, _tag) in get_pending_links(&fcm_project_id)? {
+        let Some(key_hash) = link.target.clone().into_action_hash() else {
+            continue;
+        };
+        let acknowledgements = get_acknowledgements(&key_hash)?.len();
+        if let Some(item) = history_item(
+            link.target,
+            ServiceAccountKeyStatus::Pending { acknowledgements },
+        )? {
+            history.push(item);
+        }
+    }
+
+    let retired_links = get_links(
+        GetLinksInputBuilder::try_new(path_hash, LinkTypes::RetiredServiceAccountKeys)?.build(),
+    )?;
+    for link in retired_links {
+        let tag = RetiredServiceAccountKeyTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+            link.tag.0,
+        )))
+        .map_err(|err| wasm_error!(err))?;
+        if let Some(item) = history_item(
+            link.target,
+            ServiceAccountKeyStatus::Retired {
+                retired_at: tag.retired_at,
+            },
+        )? {
+            history.push(item);
+        }
+    }
+
+    history.sort_by_key(|item| std::cmp::Reverse(item.published_at));
+
+    Ok(history)
+}
+
+fn get_pending_links(
+    fcm_project_id: &String,
+) -> ExternResult<Vec<(Link, PendingServiceAccountKeyTag)>> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(
+            fcm_project_path(fcm_project_id)?.path_entry_hash()?,
+            LinkTypes::PendingServiceAccountKeys,
+        )?
+        .build(),
+    )?;
+
+    links
+        .into_iter()
+        .map(|link| {
+            let tag = PendingServiceAccountKeyTag::try_from(SerializedBytes::from(
+                UnsafeBytes::from(link.tag.0.clone()),
+            ))
+            .map_err(|err| wasm_error!(err))?;
+            Ok((link, tag))
+        })
+        .collect()
+}
+
+fn get_acknowledgements(key_hash: &ActionHash) -> ExternResult<Vec<AgentPubKey>> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(
+            key_hash.clone(),
+            LinkTypes::ServiceAccountKeyAcknowledgements,
+        )?
+        .build(),
+    )?;
+
+    Ok(links
+        .into_iter()
+        .filter_map(|link| link.target.into_agent_pub_key())
+        .collect())
+}
+
+fn get_clone_providers() -> ExternResult<Vec<AgentPubKey>> {
+    let response = call(
+        CallTargetCell::Local,
+        ZomeName::from("clone_manager"),
+        "get_clone_providers".into(),
+        None,
+        (),
+    )?;
+    let ZomeCallResponse::Ok(result) = response else {
+        return Err(wasm_error!("Failed to get clone providers: {response:?}"));
+    };
+    let providers: Vec<AgentPubKey> = result.decode().map_err(|err| wasm_error!(err))?;
+    Ok(providers)
+}
+
+fn get_service_account_key(key_hash: ActionHash) -> ExternResult<Option<ServiceAccountKey>> {
+    let Some(record) = get(key_hash, GetOptions::default())? else {
+        return Ok(None);
+    };
+    let key: ServiceAccountKey = record
+        .entry()
+        .as_option()
+        .ok_or(wasm_error!(WasmErrorInner::Guest(String::from(
+            "Malformed key"
+        ))))?
+        .try_into()?;
+    Ok(Some(key))
+}
+
+fn history_item(
+    target: AnyLinkableHash,
+    status: ServiceAccountKeyStatus,
+) -> ExternResult<Option<ServiceAccountKeyHistoryItem>> {
+    let Some(key_hash) = target.into_action_hash() else {
+        return Ok(None);
+    };
+    let Some(record) = get(key_hash.clone(), GetOptions::default())? else {
+        return Ok(None);
+    };
+    let key: ServiceAccountKey = record
+        .entry()
+        .as_option()
+        .ok_or(wasm_error!(WasmErrorInner::Guest(String::from(
+            "Malformed key"
+        ))))?
+        .try_into()?;
+
+    Ok(Some(ServiceAccountKeyHistoryItem {
+        key_hash,
+        private_key_id: key.private_key_id,
+        client_email: key.client_email,
+        published_at: record.action().timestamp(),
+        status,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(byte: u8) -> AgentPubKey {
+        AgentPubKey::from_raw_36(vec![byte; 36])
+    }
+
+    fn tag(published_at_secs: i64, grace_period_secs: u64) -> PendingServiceAccountKeyTag {
+        PendingServiceAccountKeyTag {
+            published_at: Timestamp::from_micros(published_at_secs * 1_000_000),
+            grace_period_secs,
+        }
+    }
+
+    fn at(secs: i64) -> Timestamp {
+        Timestamp::from_micros(secs * 1_000_000)
+    }
+
+    #[test]
+    fn only_the_first_provider_finalizes_within_twice_the_grace_period() {
+        let providers = vec![agent(3), agent(1), agent(2)];
+        let tag = tag(0, 60);
+
+        assert!(is_rotation_finalizer(&agent(1), &providers, &tag, at(0)));
+        assert!(!is_rotation_finalizer(&agent(2), &providers, &tag, at(0)));
+        assert!(!is_rotation_finalizer(&agent(3), &providers, &tag, at(119)));
+
+        // The first provider is presumed offline: any provider can finalize
+        assert!(is_rotation_finalizer(&agent(2), &providers, &tag, at(120)));
+    }
+
+    #[test]
+    fn rotation_is_ready_when_all_providers_acknowledged() {
+        let providers = vec![agent(1), agent(2)];
+        let tag = tag(0, 60);
+
+        assert!(!is_ready_to_finalize(&[], &providers, &tag, at(10)));
+        assert!(!is_ready_to_finalize(&[agent(1)], &providers, &tag, at(10)));
+        assert!(is_ready_to_finalize(
+            &[agent(2), agent(1)],
+            &providers,
+            &tag,
+            at(10)
+        ));
+    }
+
+    #[test]
+    fn rotation_is_ready_when_the_grace_period_passed() {
+        let providers = vec![agent(1), agent(2)];
+        let tag = tag(100, 60);
+
+        assert!(!is_ready_to_finalize(
+            &[agent(1)],
+            &providers,
+            &tag,
+            at(159)
+        ));
+        assert!(is_ready_to_finalize(&[agent(1)], &providers, &tag, at(160)));
+
+        // Without providers, only the grace period activates the key
+        assert!(!is_ready_to_finalize(&[], &[], &tag, at(100)));
+        assert!(is_ready_to_finalize(&[], &[], &tag, at(160)));
+    }
+
+    #[test]
+    fn huge_grace_periods_never_pass() {
+        let providers = vec![agent(1), agent(2)];
+        let tag = tag(100, u64::MAX);
+
+        assert!(!is_ready_to_finalize(
+            &[agent(1)],
+            &providers,
+            &tag,
+            at(i64::MAX / 1_000_000)
+        ));
+        assert!(!is_rotation_finalizer(
+            &agent(2),
+            &providers,
+            &tag,
+            at(i64::MAX / 1_000_000)
+        ));
+    }
+}
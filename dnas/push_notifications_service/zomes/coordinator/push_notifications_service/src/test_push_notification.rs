@@ -0,0 +1,163 @@
+use hdk::prelude::*;
+use push_notifications_types::{
+    ReportTestPushNotificationResultInput, RequestTestPushNotificationInput,
+    SendTestPushNotificationSignal, TestPushNotificationRequest, TestPushNotificationResult,
+    TestPushNotificationTarget,
+};
+use roles_types::Properties;
+
+use crate::{
+    fcm_token::get_fcm_token_for_agent, service_account_key::get_current_service_account_key,
+};
+
+const TEST_PUSH_NOTIFICATION_CAP_GRANT_TAG: &str = "test_push_notification";
+
+/// Lets the requesters call the providers, and the providers send the results back
+pub fn grant_test_push_notification_capability() -> ExternResult<()> {
+    let mut fns: BTreeSet<GrantedFunction> = BTreeSet::new();
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("handle_test_push_notification_request"),
+    ));
+    fns.insert((zome_info()?.name, FunctionName::from("recv_remote_signal")));
+    let functions = GrantedFunctions::Listed(fns);
+    let cap_grant = ZomeCallCapGrant {
+        tag: String::from(TEST_PUSH_NOTIFICATION_CAP_GRANT_TAG),
+        access: CapAccess::Unrestricted,
+        functions,
+    };
+    create_cap_grant(cap_grant)?;
+
+    Ok(())
+}
+
+/// `init` has already run in the cells that were installed before the test push notifications
+/// existed: this creates the capability grant in them if it's missing
+#[hdk_extern]
+pub fn ensure_test_push_notification_capability() -> ExternResult<()> {
+    let cap_grants = query(
+        ChainQueryFilter::new()
+            .entry_type(EntryType::CapGrant)
+            .include_entries(true),
+    )?;
+    let already_granted = cap_grants.into_iter().any(|record| {
+        matches!(
+            record.entry().as_option(),
+            Some(Entry::CapGrant(cap_grant)) if cap_grant.tag.eq(TEST_PUSH_NOTIFICATION_CAP_GRANT_TAG)
+        )
+    });
+
+    if !already_granted {
+        grant_test_push_notification_capability()?;
+    }
+
+    Ok(())
+}
+
+fn is_progenitor(agent: &AgentPubKey) -> ExternResult<bool> {
+    let properties =
+        Properties::try_from(dna_info()?.modifiers.properties).map_err(|err| wasm_error!(err))?;
+    Ok(properties
+        .progenitors
+        .into_iter()
+        .any(|progenitor| AgentPubKey::from(progenitor).eq(agent)))
+}
+
+/// Asks the given provider to deliver a test push notification
+///
+/// The result is received as a `TestPushNotificationResult` signal
+#[hdk_extern]
+pub fn request_test_push_notification(input: RequestTestPushNotificationInput) -> ExternResult<()> {
+    let response = call_remote(
+        input.provider.clone(),
+        zome_info()?.name,
+        FunctionName::from("handle_test_push_notification_request"),
+        None,
+        TestPushNotificationRequest {
+            request_id: input.request_id,
+            fcm_project_id: input.fcm_project_id,
+            target: input.target,
+            dry_run: input.dry_run,
+        },
+    )?;
+    let ZomeCallResponse::Ok(_) = response else {
+        return Err(wasm_error!(WasmErrorInner::Guest(format!(
+            "Provider {} failed to handle the test push notification request: {response:?}",
+            input.provider
+        ))));
+    };
+
+    Ok(())
+}
+
+/// Called remotely by the requester of a test push notification, which must be a progenitor
+#[hdk_extern]
+pub fn handle_test_push_notification_request(
+    request: TestPushNotificationRequest,
+) -> ExternResult<()> {
+    let requester = call_info()?.provenance;
+    if !is_progenitor(&requester)? {
+        return Err(wasm_error!(WasmErrorInner::Guest(String::from(
+            "Only progenitors can request test push notifications"
+        ))));
+    }
+
+    let token = match request.target {
+        TestPushNotificationTarget::Token(token) => token,
+        TestPushNotificationTarget::Agent(agent) => {
+            let Some(token_tag) = get_fcm_token_for_agent(agent.clone())? else {
+                return Err(wasm_error!(WasmErrorInner::Guest(format!(
+                    "Agent {agent} hasn't registered their FCM token yet"
+                ))));
+            };
+            if token_tag.fcm_project_id.ne(&request.fcm_project_id) {
+                return Err(wasm_error!(WasmErrorInner::Guest(format!(
+                    "Agent {agent} registered their FCM token for project {}",
+                    token_tag.fcm_project_id
+                ))));
+            }
+            token_tag.token
+        }
+    };
+
+    let Some(service_account_key) =
+        get_current_service_account_key(request.fcm_project_id.clone())?
+    else {
+        return Err(wasm_error!(WasmErrorInner::Guest(String::from(
+            "FCM authority hasn't registered a service account key yet"
+        ))));
+    };
+
+    emit_signal(SendTestPushNotificationSignal {
+        request_id: request.request_id,
+        requester,
+        token,
+        fcm_project_id: request.fcm_project_id,
+        service_account_key,
+        dry_run: request.dry_run,
+    })?;
+
+    Ok(())
+}
+
+/// Called by the provider once FCM has answered, to send the result back to the requester
+#[hdk_extern]
+pub fn report_test_push_notification_result(
+    input: ReportTestPushNotificationResultInput,
+) -> ExternResult<()> {
+    send_remote_signal(input.result, vec![input.requester])
+}
+
+/// Receives the result of a test push notification, which can only be reported by the provider
+/// that sent it
+#[hdk_extern]
+pub fn recv_remote_signal(result: TestPushNotificationResult) -> ExternResult<()> {
+    let sender = call_info()?.provenance;
+    if result.provider.ne(&sender) {
+        return Err(wasm_error!(WasmErrorInner::Guest(format!(
+            "Test push notification result for provider {} was sent by {sender}",
+            result.provider
+        ))));
+    }
+    emit_signal(result)
+}
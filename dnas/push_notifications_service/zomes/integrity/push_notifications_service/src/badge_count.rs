@@ -0,0 +1,38 @@
+use hdi::prelude::*;
+
+pub use push_notifications_types::BadgeCountTag;
+
+pub fn validate_create_link_agent_to_badge_count(
+    _action: CreateLink,
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    if base_address != target_address {
+        return Ok(ValidateCallbackResult::Invalid(
+            "AgentToBadgeCount links must point to the agent they start from".to_string(),
+        ));
+    }
+    if BadgeCountTag::try_from(SerializedBytes::from(UnsafeBytes::from(tag.0))).is_err() {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Malformed AgentToBadgeCount link tag".to_string(),
+        ));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_agent_to_badge_count(
+    action: DeleteLink,
+    original_action: CreateLink,
+    base: AnyLinkableHash,
+    _target: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    if action.author.ne(&original_action.author) && AnyLinkableHash::from(action.author).ne(&base) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the provider that created the AgentToBadgeCount link or the recipient can delete it"
+                .to_string(),
+        ));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
@@ -9,12 +9,27 @@ pub mod fcm_project_path;
 pub mod fcm_token;
 pub use fcm_token::*;
 
+pub use notification_template::*;
+pub mod notification_template;
+
+pub use notification_preferences::*;
+pub mod notification_preferences;
+
+pub use badge_count::*;
+pub mod badge_count;
+
+pub use notification_history::*;
+pub mod notification_history;
+
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[hdk_entry_types]
 #[unit_enum(UnitEntryTypes)]
 pub enum EntryTypes {
     ServiceAccountKey(ServiceAccountKey),
+    NotificationTemplate(NotificationTemplate),
+    NotificationPreferences(NotificationPreferences),
+    EncryptedNotification(EncryptedNotification),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -23,6 +38,13 @@ pub enum LinkTypes {
     FcmToken,
     FcmProjectPath,
     ServiceAccountKeys,
+    NotificationTemplates,
+    AgentToNotificationPreferences,
+    PendingServiceAccountKeys,
+    ServiceAccountKeyAcknowledgements,
+    RetiredServiceAccountKeys,
+    AgentToBadgeCount,
+    AgentToNotificationHistory,
 }
 
 // Validation you perform during the genesis process. Nobody else on the network performs it, only you.
@@ -70,6 +92,24 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                         service_account_key,
                     )
                 }
+                EntryTypes::NotificationTemplate(notification_template) => {
+                    validate_create_notification_template(
+                        EntryCreationAction::Create(action),
+                        notification_template,
+                    )
+                }
+                EntryTypes::NotificationPreferences(notification_preferences) => {
+                    validate_create_notification_preferences(
+                        EntryCreationAction::Create(action),
+                        notification_preferences,
+                    )
+                }
+                EntryTypes::EncryptedNotification(encrypted_notification) => {
+                    validate_create_encrypted_notification(
+                        EntryCreationAction::Create(action),
+                        encrypted_notification,
+                    )
+                }
             },
             OpEntry::UpdateEntry {
                 app_entry, action, ..
@@ -80,6 +120,24 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                         service_account_key,
                     )
                 }
+                EntryTypes::NotificationTemplate(notification_template) => {
+                    validate_create_notification_template(
+                        EntryCreationAction::Update(action),
+                        notification_template,
+                    )
+                }
+                EntryTypes::NotificationPreferences(notification_preferences) => {
+                    validate_create_notification_preferences(
+                        EntryCreationAction::Update(action),
+                        notification_preferences,
+                    )
+                }
+                EntryTypes::EncryptedNotification(encrypted_notification) => {
+                    validate_create_encrypted_notification(
+                        EntryCreationAction::Update(action),
+                        encrypted_notification,
+                    )
+                }
             },
             _ => Ok(ValidateCallbackResult::Valid),
         },
@@ -116,6 +174,63 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                             original_service_account_key,
                         )
                     }
+                    EntryTypes::NotificationTemplate(notification_template) => {
+                        let original_app_entry =
+                            must_get_valid_record(action.clone().original_action_address)?;
+                        let original_notification_template =
+                            match NotificationTemplate::try_from(original_app_entry) {
+                                Ok(entry) => entry,
+                                Err(e) => {
+                                    return Ok(ValidateCallbackResult::Invalid(format!(
+                                        "Expected to get NotificationTemplate from Record: {e:?}"
+                                    )));
+                                }
+                            };
+                        validate_update_notification_template(
+                            action,
+                            notification_template,
+                            original_create_action,
+                            original_notification_template,
+                        )
+                    }
+                    EntryTypes::NotificationPreferences(notification_preferences) => {
+                        let original_app_entry =
+                            must_get_valid_record(action.clone().original_action_address)?;
+                        let original_notification_preferences =
+                            match NotificationPreferences::try_from(original_app_entry) {
+                                Ok(entry) => entry,
+                                Err(e) => {
+                                    return Ok(ValidateCallbackResult::Invalid(format!(
+                                        "Expected to get NotificationPreferences from Record: {e:?}"
+                                    )));
+                                }
+                            };
+                        validate_update_notification_preferences(
+                            action,
+                            notification_preferences,
+                            original_create_action,
+                            original_notification_preferences,
+                        )
+                    }
+                    EntryTypes::EncryptedNotification(encrypted_notification) => {
+                        let original_app_entry =
+                            must_get_valid_record(action.clone().original_action_address)?;
+                        let original_encrypted_notification =
+                            match EncryptedNotification::try_from(original_app_entry) {
+                                Ok(entry) => entry,
+                                Err(e) => {
+                                    return Ok(ValidateCallbackResult::Invalid(format!(
+                                        "Expected to get EncryptedNotification from Record: {e:?}"
+                                    )));
+                                }
+                            };
+                        validate_update_encrypted_notification(
+                            action,
+                            encrypted_notification,
+                            original_create_action,
+                            original_encrypted_notification,
+                        )
+                    }
                 }
             }
             _ => Ok(ValidateCallbackResult::Valid),
@@ -167,6 +282,27 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                         original_service_account_key,
                     )
                 }
+                EntryTypes::NotificationTemplate(original_notification_template) => {
+                    validate_delete_notification_template(
+                        delete_entry.clone().action,
+                        original_action,
+                        original_notification_template,
+                    )
+                }
+                EntryTypes::NotificationPreferences(original_notification_preferences) => {
+                    validate_delete_notification_preferences(
+                        delete_entry.clone().action,
+                        original_action,
+                        original_notification_preferences,
+                    )
+                }
+                EntryTypes::EncryptedNotification(original_encrypted_notification) => {
+                    validate_delete_encrypted_notification(
+                        delete_entry.clone().action,
+                        original_action,
+                        original_encrypted_notification,
+                    )
+                }
             }
         }
         FlatOp::RegisterCreateLink {
@@ -185,6 +321,55 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
             LinkTypes::ServiceAccountKeys => {
                 validate_create_link_service_account_keys(action, base_address, target_address, tag)
             }
+            LinkTypes::NotificationTemplates => validate_create_link_notification_templates(
+                action,
+                base_address,
+                target_address,
+                tag,
+            ),
+            LinkTypes::AgentToNotificationPreferences => {
+                validate_create_link_agent_to_notification_preferences(
+                    action,
+                    base_address,
+                    target_address,
+                    tag,
+                )
+            }
+            LinkTypes::PendingServiceAccountKeys => {
+                validate_create_link_pending_service_account_keys(
+                    action,
+                    base_address,
+                    target_address,
+                    tag,
+                )
+            }
+            LinkTypes::ServiceAccountKeyAcknowledgements => {
+                validate_create_link_service_account_key_acknowledgements(
+                    action,
+                    base_address,
+                    target_address,
+                    tag,
+                )
+            }
+            LinkTypes::RetiredServiceAccountKeys => {
+                validate_create_link_retired_service_account_keys(
+                    action,
+                    base_address,
+                    target_address,
+                    tag,
+                )
+            }
+            LinkTypes::AgentToBadgeCount => {
+                validate_create_link_agent_to_badge_count(action, base_address, target_address, tag)
+            }
+            LinkTypes::AgentToNotificationHistory => {
+                validate_create_link_agent_to_notification_history(
+                    action,
+                    base_address,
+                    target_address,
+                    tag,
+                )
+            }
         },
         FlatOp::RegisterDeleteLink {
             link_type,
@@ -215,6 +400,65 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                 target_address,
                 tag,
             ),
+            LinkTypes::NotificationTemplates => validate_delete_link_notification_templates(
+                action,
+                original_action,
+                base_address,
+                target_address,
+                tag,
+            ),
+            LinkTypes::AgentToNotificationPreferences => {
+                validate_delete_link_agent_to_notification_preferences(
+                    action,
+                    original_action,
+                    base_address,
+                    target_address,
+                    tag,
+                )
+            }
+            LinkTypes::PendingServiceAccountKeys => {
+                validate_delete_link_pending_service_account_keys(
+                    action,
+                    original_action,
+                    base_address,
+                    target_address,
+                    tag,
+                )
+            }
+            LinkTypes::ServiceAccountKeyAcknowledgements => {
+                validate_delete_link_service_account_key_acknowledgements(
+                    action,
+                    original_action,
+                    base_address,
+                    target_address,
+                    tag,
+                )
+            }
+            LinkTypes::RetiredServiceAccountKeys => {
+                validate_delete_link_retired_service_account_keys(
+                    action,
+                    original_action,
+                    base_address,
+                    target_address,
+                    tag,
+                )
+            }
+            LinkTypes::AgentToBadgeCount => validate_delete_link_agent_to_badge_count(
+                action,
+                original_action,
+                base_address,
+                target_address,
+                tag,
+            ),
+            LinkTypes::AgentToNotificationHistory => {
+                validate_delete_link_agent_to_notification_history(
+                    action,
+                    original_action,
+                    base_address,
+                    target_address,
+                    tag,
+                )
+            }
         },
         FlatOp::StoreRecord(store_record) => {
             match store_record {
@@ -228,6 +472,24 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                             service_account_key,
                         )
                     }
+                    EntryTypes::NotificationTemplate(notification_template) => {
+                        validate_create_notification_template(
+                            EntryCreationAction::Create(action),
+                            notification_template,
+                        )
+                    }
+                    EntryTypes::NotificationPreferences(notification_preferences) => {
+                        validate_create_notification_preferences(
+                            EntryCreationAction::Create(action),
+                            notification_preferences,
+                        )
+                    }
+                    EntryTypes::EncryptedNotification(encrypted_notification) => {
+                        validate_create_encrypted_notification(
+                            EntryCreationAction::Create(action),
+                            encrypted_notification,
+                        )
+                    }
                 },
                 // Complementary validation to the `RegisterUpdate` Op, in which the record itself is validated
                 // If you want to optimize performance, you can remove the validation for an entry type here and keep it in `StoreEntry` and in `RegisterUpdate`
@@ -284,6 +546,106 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                                 Ok(result)
                             }
                         }
+                        EntryTypes::NotificationTemplate(notification_template) => {
+                            let result = validate_create_notification_template(
+                                EntryCreationAction::Update(action.clone()),
+                                notification_template.clone(),
+                            )?;
+                            if let ValidateCallbackResult::Valid = result {
+                                let original_notification_template: Option<NotificationTemplate> =
+                                    original_record
+                                        .entry()
+                                        .to_app_option()
+                                        .map_err(|e| wasm_error!(e))?;
+                                let original_notification_template =
+                                    match original_notification_template {
+                                        Some(notification_template) => notification_template,
+                                        None => {
+                                            return Ok(
+                                            ValidateCallbackResult::Invalid(
+                                                "The updated entry type must be the same as the original entry type"
+                                                    .to_string(),
+                                            ),
+                                        );
+                                        }
+                                    };
+                                validate_update_notification_template(
+                                    action,
+                                    notification_template,
+                                    original_action,
+                                    original_notification_template,
+                                )
+                            } else {
+                                Ok(result)
+                            }
+                        }
+                        EntryTypes::NotificationPreferences(notification_preferences) => {
+                            let result = validate_create_notification_preferences(
+                                EntryCreationAction::Update(action.clone()),
+                                notification_preferences.clone(),
+                            )?;
+                            if let ValidateCallbackResult::Valid = result {
+                                let original_notification_preferences: Option<
+                                    NotificationPreferences,
+                                > = original_record
+                                    .entry()
+                                    .to_app_option()
+                                    .map_err(|e| wasm_error!(e))?;
+                                let original_notification_preferences =
+                                    match original_notification_preferences {
+                                        Some(notification_preferences) => notification_preferences,
+                                        None => {
+                                            return Ok(
+                                            ValidateCallbackResult::Invalid(
+                                                "The updated entry type must be the same as the original entry type"
+                                                    .to_string(),
+                                            ),
+                                        );
+                                        }
+                                    };
+                                validate_update_notification_preferences(
+                                    action,
+                                    notification_preferences,
+                                    original_action,
+                                    original_notification_preferences,
+                                )
+                            } else {
+                                Ok(result)
+                            }
+                        }
+                        EntryTypes::EncryptedNotification(encrypted_notification) => {
+                            let result = validate_create_encrypted_notification(
+                                EntryCreationAction::Update(action.clone()),
+                                encrypted_notification.clone(),
+                            )?;
+                            if let ValidateCallbackResult::Valid = result {
+                                let original_encrypted_notification: Option<EncryptedNotification> =
+                                    original_record
+                                        .entry()
+                                        .to_app_option()
+                                        .map_err(|e| wasm_error!(e))?;
+                                let original_encrypted_notification =
+                                    match original_encrypted_notification {
+                                        Some(encrypted_notification) => encrypted_notification,
+                                        None => {
+                                            return Ok(
+                                            ValidateCallbackResult::Invalid(
+                                                "The updated entry type must be the same as the original entry type"
+                                                    .to_string(),
+                                            ),
+                                        );
+                                        }
+                                    };
+                                validate_update_encrypted_notification(
+                                    action,
+                                    encrypted_notification,
+                                    original_action,
+                                    original_encrypted_notification,
+                                )
+                            } else {
+                                Ok(result)
+                            }
+                        }
                     }
                 }
                 // Complementary validation to the `RegisterDelete` Op, in which the record itself is validated
@@ -343,6 +705,27 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                                 original_service_account_key,
                             )
                         }
+                        EntryTypes::NotificationTemplate(original_notification_template) => {
+                            validate_delete_notification_template(
+                                action,
+                                original_action,
+                                original_notification_template,
+                            )
+                        }
+                        EntryTypes::NotificationPreferences(original_notification_preferences) => {
+                            validate_delete_notification_preferences(
+                                action,
+                                original_action,
+                                original_notification_preferences,
+                            )
+                        }
+                        EntryTypes::EncryptedNotification(original_encrypted_notification) => {
+                            validate_delete_encrypted_notification(
+                                action,
+                                original_action,
+                                original_encrypted_notification,
+                            )
+                        }
                     }
                 }
                 // Complementary validation to the `RegisterCreateLink` Op, in which the record itself is validated
@@ -370,6 +753,60 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                         target_address,
                         tag,
                     ),
+                    LinkTypes::NotificationTemplates => {
+                        validate_create_link_notification_templates(
+                            action,
+                            base_address,
+                            target_address,
+                            tag,
+                        )
+                    }
+                    LinkTypes::AgentToNotificationPreferences => {
+                        validate_create_link_agent_to_notification_preferences(
+                            action,
+                            base_address,
+                            target_address,
+                            tag,
+                        )
+                    }
+                    LinkTypes::PendingServiceAccountKeys => {
+                        validate_create_link_pending_service_account_keys(
+                            action,
+                            base_address,
+                            target_address,
+                            tag,
+                        )
+                    }
+                    LinkTypes::ServiceAccountKeyAcknowledgements => {
+                        validate_create_link_service_account_key_acknowledgements(
+                            action,
+                            base_address,
+                            target_address,
+                            tag,
+                        )
+                    }
+                    LinkTypes::RetiredServiceAccountKeys => {
+                        validate_create_link_retired_service_account_keys(
+                            action,
+                            base_address,
+                            target_address,
+                            tag,
+                        )
+                    }
+                    LinkTypes::AgentToBadgeCount => validate_create_link_agent_to_badge_count(
+                        action,
+                        base_address,
+                        target_address,
+                        tag,
+                    ),
+                    LinkTypes::AgentToNotificationHistory => {
+                        validate_create_link_agent_to_notification_history(
+                            action,
+                            base_address,
+                            target_address,
+                            tag,
+                        )
+                    }
                 },
                 // Complementary validation to the `RegisterDeleteLink` Op, in which the record itself is validated
                 // If you want to optimize performance, you can remove the validation for an entry type here and keep it in `RegisterDeleteLink`
@@ -420,6 +857,67 @@ pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
                             create_link.target_address,
                             create_link.tag,
                         ),
+                        LinkTypes::NotificationTemplates => {
+                            validate_delete_link_notification_templates(
+                                action,
+                                create_link.clone(),
+                                base_address,
+                                create_link.target_address,
+                                create_link.tag,
+                            )
+                        }
+                        LinkTypes::AgentToNotificationPreferences => {
+                            validate_delete_link_agent_to_notification_preferences(
+                                action,
+                                create_link.clone(),
+                                base_address,
+                                create_link.target_address,
+                                create_link.tag,
+                            )
+                        }
+                        LinkTypes::PendingServiceAccountKeys => {
+                            validate_delete_link_pending_service_account_keys(
+                                action,
+                                create_link.clone(),
+                                base_address,
+                                create_link.target_address,
+                                create_link.tag,
+                            )
+                        }
+                        LinkTypes::ServiceAccountKeyAcknowledgements => {
+                            validate_delete_link_service_account_key_acknowledgements(
+                                action,
+                                create_link.clone(),
+                                base_address,
+                                create_link.target_address,
+                                create_link.tag,
+                            )
+                        }
+                        LinkTypes::RetiredServiceAccountKeys => {
+                            validate_delete_link_retired_service_account_keys(
+                                action,
+                                create_link.clone(),
+                                base_address,
+                                create_link.target_address,
+                                create_link.tag,
+                            )
+                        }
+                        LinkTypes::AgentToBadgeCount => validate_delete_link_agent_to_badge_count(
+                            action,
+                            create_link.clone(),
+                            base_address,
+                            create_link.target_address,
+                            create_link.tag,
+                        ),
+                        LinkTypes::AgentToNotificationHistory => {
+                            validate_delete_link_agent_to_notification_history(
+                                action,
+                                create_link.clone(),
+                                base_address,
+                                create_link.target_address,
+                                create_link.tag,
+                            )
+                        }
                     }
                 }
                 OpRecord::CreatePrivateEntry { .. } => Ok(ValidateCallbackResult::Valid),
@@ -0,0 +1,93 @@
+use hdi::prelude::*;
+
+pub use push_notifications_types::{EncryptedNotification, NotificationHistoryTag};
+
+pub fn validate_create_encrypted_notification(
+    _action: EntryCreationAction,
+    encrypted_notification: EncryptedNotification,
+) -> ExternResult<ValidateCallbackResult> {
+    if encrypted_notification.ciphertext.is_empty() {
+        return Ok(ValidateCallbackResult::Invalid(
+            "EncryptedNotification has no ciphertext".to_string(),
+        ));
+    }
+    // TODO: add the appropriate validation rules
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_update_encrypted_notification(
+    _action: Update,
+    _encrypted_notification: EncryptedNotification,
+    _original_action: EntryCreationAction,
+    _original_encrypted_notification: EncryptedNotification,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(
+        "Encrypted Notifications cannot be updated".to_string(),
+    ))
+}
+
+pub fn validate_delete_encrypted_notification(
+    action: Delete,
+    original_action: EntryCreationAction,
+    original_encrypted_notification: EncryptedNotification,
+) -> ExternResult<ValidateCallbackResult> {
+    if action.author.ne(original_action.author())
+        && action.author.ne(&original_encrypted_notification.recipient)
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the provider that added the notification or its recipient can delete it"
+                .to_string(),
+        ));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_create_link_agent_to_notification_history(
+    _action: CreateLink,
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let action_hash =
+        target_address
+            .into_action_hash()
+            .ok_or(wasm_error!(WasmErrorInner::Guest(
+                "No action hash associated with link".to_string()
+            )))?;
+    let record = must_get_valid_record(action_hash)?;
+    let encrypted_notification: crate::EncryptedNotification = record
+        .entry()
+        .to_app_option()
+        .map_err(|e| wasm_error!(e))?
+        .ok_or(wasm_error!(WasmErrorInner::Guest(
+            "Linked action must reference an entry".to_string()
+        )))?;
+    if AnyLinkableHash::from(encrypted_notification.recipient) != base_address {
+        return Ok(ValidateCallbackResult::Invalid(
+            "AgentToNotificationHistory links must start from the recipient of the notification"
+                .to_string(),
+        ));
+    }
+    if NotificationHistoryTag::try_from(SerializedBytes::from(UnsafeBytes::from(tag.0))).is_err() {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Malformed AgentToNotificationHistory link tag".to_string(),
+        ));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_agent_to_notification_history(
+    action: DeleteLink,
+    original_action: CreateLink,
+    base: AnyLinkableHash,
+    _target: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    if action.author.ne(&original_action.author) && AnyLinkableHash::from(action.author).ne(&base) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the provider that created the AgentToNotificationHistory link or the recipient can delete it"
+                .to_string(),
+        ));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
@@ -0,0 +1,73 @@
+use hdi::prelude::*;
+
+pub use push_notifications_types::NotificationPreferences;
+
+pub fn validate_create_notification_preferences(
+    _action: EntryCreationAction,
+    notification_preferences: NotificationPreferences,
+) -> ExternResult<ValidateCallbackResult> {
+    if let Some(quiet_hours) = notification_preferences.quiet_hours {
+        if quiet_hours.start_minute >= 24 * 60 || quiet_hours.end_minute >= 24 * 60 {
+            return Ok(ValidateCallbackResult::Invalid(
+                "Quiet hours must be between 0 and 1439 minutes after midnight".to_string(),
+            ));
+        }
+    }
+    // TODO: add the appropriate validation rules
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_update_notification_preferences(
+    _action: Update,
+    _notification_preferences: NotificationPreferences,
+    _original_action: EntryCreationAction,
+    _original_notification_preferences: NotificationPreferences,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(
+        "Notification Preferences cannot be updated".to_string(),
+    ))
+}
+
+pub fn validate_delete_notification_preferences(
+    _action: Delete,
+    _original_action: EntryCreationAction,
+    _original_notification_preferences: NotificationPreferences,
+) -> ExternResult<ValidateCallbackResult> {
+    // TODO: add the appropriate validation rules
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_create_link_agent_to_notification_preferences(
+    _action: CreateLink,
+    _base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let action_hash =
+        target_address
+            .into_action_hash()
+            .ok_or(wasm_error!(WasmErrorInner::Guest(
+                "No action hash associated with link".to_string()
+            )))?;
+    let record = must_get_valid_record(action_hash)?;
+    let _notification_preferences: crate::NotificationPreferences = record
+        .entry()
+        .to_app_option()
+        .map_err(|e| wasm_error!(e))?
+        .ok_or(wasm_error!(WasmErrorInner::Guest(
+            "Linked action must reference an entry".to_string()
+        )))?;
+    // TODO: add the appropriate validation rules
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_agent_to_notification_preferences(
+    _action: DeleteLink,
+    _original_action: CreateLink,
+    _base: AnyLinkableHash,
+    _target: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    // TODO: add the appropriate validation rules
+    Ok(ValidateCallbackResult::Valid)
+}
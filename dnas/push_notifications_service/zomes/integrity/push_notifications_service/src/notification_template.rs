@@ -0,0 +1,75 @@
+use hdi::prelude::*;
+
+pub use push_notifications_types::NotificationTemplate;
+
+pub fn validate_create_notification_template(
+    _action: EntryCreationAction,
+    notification_template: NotificationTemplate,
+) -> ExternResult<ValidateCallbackResult> {
+    if !notification_template
+        .translations
+        .contains_key(&notification_template.default_locale)
+    {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "NotificationTemplate has no translation for its default locale {}",
+            notification_template.default_locale
+        )));
+    }
+    // TODO: add the appropriate validation rules
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_update_notification_template(
+    _action: Update,
+    _notification_template: NotificationTemplate,
+    _original_action: EntryCreationAction,
+    _original_notification_template: NotificationTemplate,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(
+        "Notification Templates cannot be updated".to_string(),
+    ))
+}
+
+pub fn validate_delete_notification_template(
+    _action: Delete,
+    _original_action: EntryCreationAction,
+    _original_notification_template: NotificationTemplate,
+) -> ExternResult<ValidateCallbackResult> {
+    // TODO: add the appropriate validation rules
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_create_link_notification_templates(
+    _action: CreateLink,
+    _base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let action_hash =
+        target_address
+            .into_action_hash()
+            .ok_or(wasm_error!(WasmErrorInner::Guest(
+                "No action hash associated with link".to_string()
+            )))?;
+    let record = must_get_valid_record(action_hash)?;
+    let _notification_template: crate::NotificationTemplate = record
+        .entry()
+        .to_app_option()
+        .map_err(|e| wasm_error!(e))?
+        .ok_or(wasm_error!(WasmErrorInner::Guest(
+            "Linked action must reference an entry".to_string()
+        )))?;
+    // TODO: add the appropriate validation rules
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_notification_templates(
+    _action: DeleteLink,
+    _original_action: CreateLink,
+    _base: AnyLinkableHash,
+    _target: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    // TODO: add the appropriate validation rules
+    Ok(ValidateCallbackResult::Valid)
+}
@@ -1,6 +1,9 @@
 use hdi::prelude::*;
 
-pub use push_notifications_types::ServiceAccountKey;
+pub use push_notifications_types::{
+    PendingServiceAccountKeyTag, RetiredServiceAccountKeyTag, ServiceAccountKey,
+    MAX_ROTATION_GRACE_PERIOD_SECS,
+};
 
 pub fn validate_create_service_account_key(
     _action: EntryCreationAction,
@@ -64,3 +67,113 @@ pub fn validate_delete_link_service_account_keys(
     // TODO: add the appropriate validation rules
     Ok(ValidateCallbackResult::Valid)
 }
+
+fn must_get_service_account_key(address: AnyLinkableHash) -> ExternResult<ServiceAccountKey> {
+    let action_hash = address
+        .into_action_hash()
+        .ok_or(wasm_error!(WasmErrorInner::Guest(
+            "No action hash associated with link".to_string()
+        )))?;
+    let record = must_get_valid_record(action_hash)?;
+    record
+        .entry()
+        .to_app_option()
+        .map_err(|e| wasm_error!(e))?
+        .ok_or(wasm_error!(WasmErrorInner::Guest(
+            "Linked action must reference an entry".to_string()
+        )))
+}
+
+pub fn validate_create_link_pending_service_account_keys(
+    _action: CreateLink,
+    _base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    must_get_service_account_key(target_address)?;
+    let Ok(tag) =
+        PendingServiceAccountKeyTag::try_from(SerializedBytes::from(UnsafeBytes::from(tag.0)))
+    else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Malformed pending service account key tag".to_string(),
+        ));
+    };
+    if tag.grace_period_secs > MAX_ROTATION_GRACE_PERIOD_SECS {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "The grace period of a service account key rotation can't be longer than {MAX_ROTATION_GRACE_PERIOD_SECS} seconds"
+        )));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_pending_service_account_keys(
+    _action: DeleteLink,
+    _original_action: CreateLink,
+    _base: AnyLinkableHash,
+    _target: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    // TODO: add the appropriate validation rules
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_create_link_service_account_key_acknowledgements(
+    action: CreateLink,
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    must_get_service_account_key(base_address)?;
+    let Some(agent) = target_address.into_agent_pub_key() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Acknowledgements must point to the agent that acknowledges the key".to_string(),
+        ));
+    };
+    if agent.ne(&action.author) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Agents can only acknowledge service account keys for themselves".to_string(),
+        ));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_service_account_key_acknowledgements(
+    _action: DeleteLink,
+    _original_action: CreateLink,
+    _base: AnyLinkableHash,
+    _target: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(
+        "Service account key acknowledgements cannot be deleted".to_string(),
+    ))
+}
+
+pub fn validate_create_link_retired_service_account_keys(
+    _action: CreateLink,
+    _base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    must_get_service_account_key(target_address)?;
+    if RetiredServiceAccountKeyTag::try_from(SerializedBytes::from(UnsafeBytes::from(tag.0)))
+        .is_err()
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Malformed retired service account key tag".to_string(),
+        ));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_retired_service_account_keys(
+    _action: DeleteLink,
+    _original_action: CreateLink,
+    _base: AnyLinkableHash,
+    _target: AnyLinkableHash,
+    _tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(
+        "The history of service account keys cannot be deleted".to_string(),
+    ))
+}
@@ -9,6 +9,11 @@
       inputs.service-providers.outputs.builders.${system}.services_dna_with_gateway {
         gatewayZome = self'.packages.push_notifications_gateway;
       };
+    # Run by the end users, which receive the push notifications instead of sending them
+    packages.services_dna_with_push_notifications_receiver =
+      inputs.service-providers.outputs.builders.${system}.services_dna_with_gateway {
+        gatewayZome = self'.packages.push_notifications_receiver;
+      };
   };
 }
 
@@ -2,6 +2,7 @@ use hdk::prelude::*;
 use push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH;
 
 mod push_notifications_service;
+mod routing;
 
 #[hdk_extern]
 pub fn init(_: ()) -> ExternResult<InitCallbackResult> {
@@ -11,6 +12,51 @@ pub fn init(_: ()) -> ExternResult<InitCallbackResult> {
         zome_info()?.name,
         FunctionName::from("send_push_notifications"),
     ));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("send_push_notifications_with_fallback"),
+    ));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("send_push_notification_to_agents"),
+    ));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("cancel_scheduled_push_notification"),
+    ));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("set_notification_preferences"),
+    ));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("get_notification_preferences"),
+    ));
+    fns.insert((zome_info()?.name, FunctionName::from("clear_badge")));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("get_notification_history"),
+    ));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("mark_notifications_as_read"),
+    ));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("send_push_notification_to_agent"),
+    ));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("cancel_scheduled_push_notification_for_agent"),
+    ));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("send_push_notification_batch"),
+    ));
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("send_push_notification_to_agents_with_provenance"),
+    ));
     let functions = GrantedFunctions::Listed(fns);
     let cap_grant = ZomeCallCapGrant {
         tag: String::from("send_push_notification"),
@@ -1,10 +1,15 @@
 use hc_zome_traits::{implement_zome_trait_as_externs, implemented_zome_traits};
 use hdk::prelude::*;
 use push_notifications_service_trait::{
-    PushNotificationsService, RegisterFcmTokenInput, SendPushNotificationToAgentInput,
+    CancelScheduledPushNotificationInput, NotificationPreferences, PushNotificationsService,
+    RegisterFcmTokenInput, SendPushNotificationToAgentInput,
 };
 use push_notifications_types::*;
 
+use crate::routing::{
+    check_caller_is_provider, route_batch_to_assigned_providers, route_to_assigned_provider,
+};
+
 #[implemented_zome_traits]
 pub enum ZomeTraits {
     PushNotifications(PushNotificationsGateway),
@@ -25,6 +30,7 @@ impl PushNotificationsService for PushNotificationsGateway {
                 fcm_project_id: input.fcm_project_id,
                 token: input.token,
                 agent,
+                locale: input.locale,
             },
         )?;
         let ZomeCallResponse::Ok(_) = response else {
@@ -34,24 +40,475 @@ impl PushNotificationsService for PushNotificationsGateway {
     }
 
     fn send_push_notifications(inputs: Vec<SendPushNotificationToAgentInput>) -> ExternResult<()> {
+        let provenance = call_info()?.provenance;
+        let mut items: Vec<(AgentPubKey, SendPushNotificationToAgentWithProvenanceInput)> =
+            Vec::new();
         for input in inputs {
-            let agent = call_info()?.provenance;
-            let response = call(
-                CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
-                ZomeName::from("push_notifications_service"),
-                FunctionName::from("send_push_notification_to_agent"),
-                None,
+            let trace_id = new_trace_id()?;
+            debug!(trace_id = %trace_id, "Routing push notification");
+            items.push((
+                input.agent.clone(),
                 SendPushNotificationToAgentWithProvenanceInput {
-                    provenance: agent,
+                    provenance: provenance.clone(),
                     agent: input.agent,
                     notification: input.notification,
+                    id: input.id,
+                    send_at: input.send_at,
+                    trace_id: Some(trace_id),
                 },
-            );
-            let Ok(ZomeCallResponse::Ok(_)) = response else {
-                error!("Failed to send push notification: {response:?}");
-                continue;
-            };
+            ));
+        }
+        if let Err(err) = route_batch_to_assigned_providers(
+            items,
+            "send_push_notification_batch",
+            |items| items,
+            send_push_notifications_locally,
+        ) {
+            error!("Failed to send push notifications: {err:?}");
         }
         Ok(())
     }
+
+    fn send_push_notifications_with_fallback(
+        inputs: Vec<SendPushNotificationToAgentInput>,
+    ) -> ExternResult<Vec<PushNotificationRoute>> {
+        let provenance = call_info()?.provenance;
+        let mut routes: Vec<Option<PushNotificationRoute>> = vec![None; inputs.len()];
+
+        // Scheduled notifications can't be delivered directly, and the ones with an id
+        // go through FCM so that the providers send them only once
+        let direct: Vec<(usize, SendPushNotificationToAgentWithProvenanceInput)> = inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, input)| input.send_at.is_none() && input.id.is_none())
+            .map(|(i, input)| {
+                (
+                    i,
+                    SendPushNotificationToAgentWithProvenanceInput {
+                        provenance: provenance.clone(),
+                        agent: input.agent.clone(),
+                        notification: input.notification.clone(),
+                        id: None,
+                        send_at: None,
+                        trace_id: None,
+                    },
+                )
+            })
+            .collect();
+        let prepared = prepare_remote_push_notifications(
+            direct.iter().map(|(_, input)| input.clone()).collect(),
+        )?;
+
+        let mut ready: Vec<(usize, AgentPubKey, RemotePushNotification)> = Vec::new();
+        for ((i, input), prepared) in direct.into_iter().zip(prepared) {
+            match prepared {
+                PreparedRemotePushNotification::Muted(reason) => {
+                    info!("Not sending push notification to {}: {reason}", input.agent);
+                    routes[i] = Some(PushNotificationRoute::Muted(reason));
+                }
+                PreparedRemotePushNotification::Ready(notification) => ready.push((
+                    i,
+                    input.agent,
+                    RemotePushNotification {
+                        sender: provenance.clone(),
+                        notification,
+                        id: None,
+                    },
+                )),
+            }
+        }
+
+        let delivered = send_remote_push_notifications(
+            ready
+                .iter()
+                .map(|(_, agent, notification)| (agent.clone(), notification.clone()))
+                .collect(),
+        )?;
+        let sent_at = sys_time()?;
+        let mut sent: Vec<RecordSentPushNotificationInput> = Vec::new();
+        for ((i, agent, remote_notification), delivered) in ready.into_iter().zip(delivered) {
+            if delivered {
+                routes[i] = Some(PushNotificationRoute::RemoteSignal);
+                sent.push(RecordSentPushNotificationInput {
+                    provenance: provenance.clone(),
+                    recipient: agent,
+                    notification: remote_notification.notification,
+                    sent_at,
+                });
+            }
+        }
+        if !sent.is_empty() {
+            record_sent_push_notifications(sent)?;
+        }
+
+        let mut fcm_inputs: Vec<SendPushNotificationToAgentInput> = Vec::new();
+        for (input, route) in inputs.into_iter().zip(routes.iter_mut()) {
+            if route.is_none() {
+                *route = Some(PushNotificationRoute::Fcm);
+                fcm_inputs.push(input);
+            }
+        }
+        if !fcm_inputs.is_empty() {
+            Self::send_push_notifications(fcm_inputs)?;
+        }
+
+        Ok(routes.into_iter().flatten().collect())
+    }
+
+    fn send_push_notification_to_agents(
+        input: SendPushNotificationToAgentsInput,
+    ) -> ExternResult<()> {
+        let provenance = call_info()?.provenance;
+        let mut recipients: Vec<(AgentPubKey, PushNotificationRecipient)> = Vec::new();
+        for agent in input.agents {
+            let trace_id = new_trace_id()?;
+            debug!(trace_id = %trace_id, "Routing push notification");
+            recipients.push((
+                agent.clone(),
+                PushNotificationRecipient {
+                    agent,
+                    trace_id: Some(trace_id),
+                },
+            ));
+        }
+        if let Err(err) = route_batch_to_assigned_providers(
+            recipients,
+            "send_push_notification_to_agents_with_provenance",
+            |recipients| SendPushNotificationToAgentsWithProvenanceInput {
+                provenance: provenance.clone(),
+                recipients,
+                notification: input.notification.clone(),
+                id: input.id.clone(),
+                send_at: input.send_at,
+            },
+            send_push_notification_to_agents_locally,
+        ) {
+            error!("Failed to send push notification to agents: {err:?}");
+        }
+        Ok(())
+    }
+
+    fn cancel_scheduled_push_notification(
+        input: CancelScheduledPushNotificationInput,
+    ) -> ExternResult<()> {
+        let recipient = input.agent.clone();
+        let input = CancelScheduledPushNotificationWithProvenanceInput {
+            provenance: call_info()?.provenance,
+            agent: input.agent,
+            id: input.id,
+        };
+        route_to_assigned_provider(
+            &recipient,
+            "cancel_scheduled_push_notification_for_agent",
+            input,
+            cancel_scheduled_push_notification_locally,
+        )
+    }
+
+    fn set_notification_preferences(preferences: NotificationPreferences) -> ExternResult<()> {
+        let agent = call_info()?.provenance;
+        let response = call(
+            CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+            ZomeName::from("push_notifications_service"),
+            FunctionName::from("set_notification_preferences_for_agent"),
+            None,
+            SetNotificationPreferencesForAgentInput { agent, preferences },
+        )?;
+        let ZomeCallResponse::Ok(_) = response else {
+            return Err(wasm_error!(
+                "Failed to set notification preferences: {response:?}"
+            ));
+        };
+        Ok(())
+    }
+
+    fn get_notification_preferences(_input: ()) -> ExternResult<Option<NotificationPreferences>> {
+        let agent = call_info()?.provenance;
+        let response = call(
+            CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+            ZomeName::from("push_notifications_service"),
+            FunctionName::from("get_notification_preferences_for_agent"),
+            None,
+            agent,
+        )?;
+        let ZomeCallResponse::Ok(result) = response else {
+            return Err(wasm_error!(
+                "Failed to get notification preferences: {response:?}"
+            ));
+        };
+        let preferences: Option<NotificationPreferences> =
+            result.decode().map_err(|err| wasm_error!(err))?;
+        Ok(preferences)
+    }
+
+    fn clear_badge(_input: ()) -> ExternResult<()> {
+        let agent = call_info()?.provenance;
+        let response = call(
+            CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+            ZomeName::from("push_notifications_service"),
+            FunctionName::from("clear_badge_for_agent"),
+            None,
+            agent,
+        )?;
+        let ZomeCallResponse::Ok(_) = response else {
+            return Err(wasm_error!("Failed to clear badge: {response:?}"));
+        };
+        Ok(())
+    }
+
+    fn get_notification_history(_input: ()) -> ExternResult<Vec<NotificationHistoryItem>> {
+        let agent = call_info()?.provenance;
+        let response = call(
+            CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+            ZomeName::from("push_notifications_service"),
+            FunctionName::from("get_notification_history_for_agent"),
+            None,
+            agent,
+        )?;
+        let ZomeCallResponse::Ok(result) = response else {
+            return Err(wasm_error!(
+                "Failed to get notification history: {response:?}"
+            ));
+        };
+        let history: Vec<NotificationHistoryItem> =
+            result.decode().map_err(|err| wasm_error!(err))?;
+        Ok(history)
+    }
+
+    fn mark_notifications_as_read(notification_hashes: Vec<ActionHash>) -> ExternResult<()> {
+        let agent = call_info()?.provenance;
+        let response = call(
+            CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+            ZomeName::from("push_notifications_service"),
+            FunctionName::from("mark_notifications_as_read_for_agent"),
+            None,
+            MarkNotificationsAsReadForAgentInput {
+                agent,
+                notification_hashes,
+            },
+        )?;
+        let ZomeCallResponse::Ok(_) = response else {
+            return Err(wasm_error!(
+                "Failed to mark notifications as read: {response:?}"
+            ));
+        };
+        Ok(())
+    }
+}
+
+/// Called by other providers to deliver a push notification for which this provider
+/// is the assigned one
+#[hdk_extern]
+pub fn send_push_notification_to_agent(
+    input: SendPushNotificationToAgentWithProvenanceInput,
+) -> ExternResult<()> {
+    check_caller_is_provider()?;
+    // Answered with Ok so that the calling provider doesn't fail over to another one
+    if let Err(err) = send_push_notification_locally(input) {
+        error!("Failed to send push notification: {err:?}");
+    }
+    Ok(())
+}
+
+/// Called by other providers to deliver the push notifications for which this provider
+/// is the assigned one
+#[hdk_extern]
+pub fn send_push_notification_batch(
+    inputs: Vec<SendPushNotificationToAgentWithProvenanceInput>,
+) -> ExternResult<()> {
+    check_caller_is_provider()?;
+    // Answered with Ok so that the calling provider doesn't fail over to another one
+    if let Err(err) = send_push_notifications_locally(inputs) {
+        error!("Failed to send push notifications: {err:?}");
+    }
+    Ok(())
+}
+
+/// Called by other providers to deliver the push notification to the recipients for which
+/// this provider is the assigned one
+#[hdk_extern]
+pub fn send_push_notification_to_agents_with_provenance(
+    input: SendPushNotificationToAgentsWithProvenanceInput,
+) -> ExternResult<()> {
+    check_caller_is_provider()?;
+    // Answered with Ok so that the calling provider doesn't fail over to another one
+    if let Err(err) = send_push_notification_to_agents_locally(input) {
+        error!("Failed to send push notification to agents: {err:?}");
+    }
+    Ok(())
+}
+
+/// Called by other providers to cancel a scheduled push notification for which this provider
+/// is the assigned one
+#[hdk_extern]
+pub fn cancel_scheduled_push_notification_for_agent(
+    input: CancelScheduledPushNotificationWithProvenanceInput,
+) -> ExternResult<()> {
+    check_caller_is_provider()?;
+    // Answered with Ok so that the calling provider doesn't fail over to another one
+    if let Err(err) = cancel_scheduled_push_notification_locally(input) {
+        error!("Failed to cancel scheduled push notification: {err:?}");
+    }
+    Ok(())
+}
+
+/// Delivers the notifications with remote calls to the `push_notifications_receiver` zome of
+/// their recipients, which fail if they are offline or don't run that zome
+///
+/// The calls are made concurrently, so that the whole delivery takes at most the network timeout
+/// of the conductor, instead of one timeout for each unreachable recipient
+///
+/// Returns whether each recipient received their notification
+fn send_remote_push_notifications(
+    notifications: Vec<(AgentPubKey, RemotePushNotification)>,
+) -> ExternResult<Vec<bool>> {
+    if notifications.is_empty() {
+        return Ok(vec![]);
+    }
+    let calls = notifications
+        .iter()
+        .map(|(agent, notification)| {
+            Ok(Call::new(
+                CallTarget::NetworkAgent(agent.clone()),
+                ZomeName::from("push_notifications_receiver"),
+                FunctionName::from("receive_remote_push_notification"),
+                None,
+                ExternIO::encode(notification).map_err(|err| wasm_error!(err))?,
+            ))
+        })
+        .collect::<ExternResult<Vec<Call>>>()?;
+    let responses = HDK.with(|h| h.borrow().call(calls))?;
+
+    Ok(notifications
+        .iter()
+        .zip(responses)
+        .map(|((agent, _), response)| match response {
+            ZomeCallResponse::Ok(_) => true,
+            other => {
+                debug!(
+                    "Could not deliver push notification directly to {agent}: {other:?}. Falling back to FCM."
+                );
+                false
+            }
+        })
+        .collect())
+}
+
+fn prepare_remote_push_notifications(
+    inputs: Vec<SendPushNotificationToAgentWithProvenanceInput>,
+) -> ExternResult<Vec<PreparedRemotePushNotification>> {
+    if inputs.is_empty() {
+        return Ok(vec![]);
+    }
+    let response = call(
+        CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+        ZomeName::from("push_notifications_service"),
+        FunctionName::from("prepare_remote_push_notifications"),
+        None,
+        inputs,
+    )?;
+    let ZomeCallResponse::Ok(result) = response else {
+        return Err(wasm_error!(
+            "Failed to prepare the push notifications: {response:?}"
+        ));
+    };
+    let prepared: Vec<PreparedRemotePushNotification> =
+        result.decode().map_err(|err| wasm_error!(err))?;
+    Ok(prepared)
+}
+
+fn record_sent_push_notifications(
+    inputs: Vec<RecordSentPushNotificationInput>,
+) -> ExternResult<()> {
+    let response = call(
+        CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+        ZomeName::from("push_notifications_service"),
+        FunctionName::from("record_sent_push_notifications"),
+        None,
+        inputs,
+    )?;
+    let ZomeCallResponse::Ok(_) = response else {
+        return Err(wasm_error!(
+            "Failed to record the sent push notifications: {response:?}"
+        ));
+    };
+    Ok(())
+}
+
+fn send_push_notification_locally(
+    input: SendPushNotificationToAgentWithProvenanceInput,
+) -> ExternResult<()> {
+    let response = call(
+        CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+        ZomeName::from("push_notifications_service"),
+        FunctionName::from("send_push_notification_to_agent"),
+        None,
+        input,
+    )?;
+    let ZomeCallResponse::Ok(_) = response else {
+        return Err(wasm_error!(
+            "Failed to send push notification: {response:?}"
+        ));
+    };
+    Ok(())
+}
+
+fn send_push_notifications_locally(
+    inputs: Vec<SendPushNotificationToAgentWithProvenanceInput>,
+) -> ExternResult<()> {
+    let response = call(
+        CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+        ZomeName::from("push_notifications_service"),
+        FunctionName::from("send_push_notification_batch"),
+        None,
+        inputs,
+    )?;
+    let ZomeCallResponse::Ok(_) = response else {
+        return Err(wasm_error!(
+            "Failed to send push notifications: {response:?}"
+        ));
+    };
+    Ok(())
+}
+
+fn send_push_notification_to_agents_locally(
+    input: SendPushNotificationToAgentsWithProvenanceInput,
+) -> ExternResult<()> {
+    let response = call(
+        CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+        ZomeName::from("push_notifications_service"),
+        FunctionName::from("send_push_notification_to_agents_with_provenance"),
+        None,
+        input,
+    )?;
+    let ZomeCallResponse::Ok(_) = response else {
+        return Err(wasm_error!(
+            "Failed to send push notification to agents: {response:?}"
+        ));
+    };
+    Ok(())
+}
+
+fn cancel_scheduled_push_notification_locally(
+    input: CancelScheduledPushNotificationWithProvenanceInput,
+) -> ExternResult<()> {
+    let response = call(
+        CallTargetCell::OtherRole(RoleName::from("push_notifications_service")),
+        ZomeName::from("push_notifications_service"),
+        FunctionName::from("cancel_scheduled_push_notification_for_agent"),
+        None,
+        input,
+    )?;
+    let ZomeCallResponse::Ok(_) = response else {
+        return Err(wasm_error!(
+            "Failed to cancel scheduled push notification: {response:?}"
+        ));
+    };
+    Ok(())
+}
+
+/// Random identifier to follow a push notification in the logs of the zomes and the provider
+fn new_trace_id() -> ExternResult<String> {
+    let bytes = random_bytes(8)?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
 }
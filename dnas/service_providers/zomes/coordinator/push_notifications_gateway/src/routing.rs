@@ -0,0 +1,198 @@
+use hdk::prelude::*;
+use push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH;
+
+/// Gets all the agents that have announced themselves as providers for the push notifications service
+pub fn get_push_notifications_providers() -> ExternResult<Vec<AgentPubKey>> {
+    let response = call(
+        CallTargetCell::Local,
+        ZomeName::from("service_providers"),
+        "get_providers_for_service".into(),
+        None,
+        PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+    )?;
+    let ZomeCallResponse::Ok(result) = response else {
+        return Err(wasm_error!(
+            "Failed to get push notifications providers: {response:?}"
+        ));
+    };
+    let providers: Vec<AgentPubKey> = result.decode().map_err(|err| wasm_error!(err))?;
+    Ok(providers)
+}
+
+/// Orders the providers by their preference to deliver the notifications for the given recipient,
+/// using rendezvous hashing: every provider computes the same order, and adding or removing
+/// one provider only reassigns the recipients for which that provider was the first choice
+pub fn rank_providers_for_recipient(
+    providers: Vec<AgentPubKey>,
+    recipient: &AgentPubKey,
+) -> ExternResult<Vec<AgentPubKey>> {
+    let mut scored_providers = providers
+        .into_iter()
+        .map(|provider| {
+            let score = hash_blake2b([provider.get_raw_39(), recipient.get_raw_39()].concat(), 32)?;
+            Ok((score, provider))
+        })
+        .collect::<ExternResult<Vec<(Vec<u8>, AgentPubKey)>>>()?;
+    scored_providers.sort_by(|(score_a, _), (score_b, _)| score_b.cmp(score_a));
+    scored_providers.dedup_by(|(_, a), (_, b)| a == b);
+
+    Ok(scored_providers
+        .into_iter()
+        .map(|(_, provider)| provider)
+        .collect())
+}
+
+/// Routes the call to the provider assigned to the recipient, so that only one provider
+/// handles each notification
+///
+/// If the assigned provider is not reachable, fails over to the next one in the ranking.
+/// If this agent comes up first in the ranking, or no other provider is reachable, the call
+/// is handled locally with `local_handler`
+///
+/// A provider that is reached but fails to handle the call is not failed over, see [`handled_by_provider`]
+pub fn route_to_assigned_provider<I, F>(
+    recipient: &AgentPubKey,
+    fn_name: &str,
+    payload: I,
+    local_handler: F,
+) -> ExternResult<()>
+where
+    I: Serialize + std::fmt::Debug,
+    F: FnOnce(I) -> ExternResult<()>,
+{
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let providers = rank_providers_for_recipient(get_push_notifications_providers()?, recipient)?;
+
+    for provider in providers {
+        if provider.eq(&my_pub_key) {
+            return local_handler(payload);
+        }
+        let response = call_remote(
+            provider.clone(),
+            zome_info()?.name,
+            FunctionName::from(fn_name),
+            None,
+            &payload,
+        );
+        if handled_by_provider(&provider, fn_name, response) {
+            return Ok(());
+        }
+    }
+
+    local_handler(payload)
+}
+
+/// Routes each item to the provider assigned to its recipient, grouping the items assigned to
+/// the same provider in a single call whose payload is built with `build_payload`
+///
+/// The items of a provider that is not reachable fail over to the next provider in their
+/// ranking. The items for which this agent is the assigned provider, or for which no other
+/// provider is reachable, are handled locally with `local_handler`
+pub fn route_batch_to_assigned_providers<I, P, B, F>(
+    items: Vec<(AgentPubKey, I)>,
+    fn_name: &str,
+    build_payload: B,
+    local_handler: F,
+) -> ExternResult<()>
+where
+    I: Clone,
+    P: Serialize + std::fmt::Debug,
+    B: Fn(Vec<I>) -> P,
+    F: FnOnce(P) -> ExternResult<()>,
+{
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let providers = get_push_notifications_providers()?;
+
+    let mut pending = items
+        .into_iter()
+        .map(|(recipient, item)| {
+            let ranking = rank_providers_for_recipient(providers.clone(), &recipient)?;
+            Ok((ranking, item))
+        })
+        .collect::<ExternResult<Vec<(Vec<AgentPubKey>, I)>>>()?;
+    let mut failed_providers: Vec<AgentPubKey> = Vec::new();
+    let mut local_items: Vec<I> = Vec::new();
+
+    while !pending.is_empty() {
+        let mut batches: Vec<(AgentPubKey, Vec<(Vec<AgentPubKey>, I)>)> = Vec::new();
+        for (ranking, item) in pending.drain(..) {
+            let assigned_provider = ranking
+                .iter()
+                .find(|provider| !failed_providers.contains(provider))
+                .cloned();
+            match assigned_provider {
+                Some(provider) if provider.ne(&my_pub_key) => {
+                    match batches.iter_mut().find(|(p, _)| p.eq(&provider)) {
+                        Some((_, batch)) => batch.push((ranking, item)),
+                        None => batches.push((provider, vec![(ranking, item)])),
+                    }
+                }
+                _ => local_items.push(item),
+            }
+        }
+
+        for (provider, batch) in batches {
+            let payload = build_payload(batch.iter().map(|(_, item)| item.clone()).collect());
+            let response = call_remote(
+                provider.clone(),
+                zome_info()?.name,
+                FunctionName::from(fn_name),
+                None,
+                &payload,
+            );
+            if handled_by_provider(&provider, fn_name, response) {
+                continue;
+            }
+            failed_providers.push(provider);
+            pending.extend(batch);
+        }
+    }
+
+    if local_items.is_empty() {
+        return Ok(());
+    }
+    local_handler(build_payload(local_items))
+}
+
+/// Whether the remote provider received and handled the call, or it must be failed over
+///
+/// The provider-facing externs log their own errors and answer with `ZomeCallResponse::Ok` once
+/// the caller is authorized, so only unreachable or unauthorized providers are failed over: failing
+/// over a notification that the assigned provider could not send would likely fail in the same way,
+/// or deliver it twice
+fn handled_by_provider(
+    provider: &AgentPubKey,
+    fn_name: &str,
+    response: ExternResult<ZomeCallResponse>,
+) -> bool {
+    match response {
+        Ok(ZomeCallResponse::Ok(_)) => true,
+        Ok(response) => {
+            warn!(
+                "Provider {provider} didn't accept {fn_name}: {response:?}. Failing over to the next provider."
+            );
+            false
+        }
+        Err(err) => {
+            warn!(
+                "Provider {provider} is unreachable for {fn_name}: {err:?}. Failing over to the next provider."
+            );
+            false
+        }
+    }
+}
+
+/// Fails unless the caller of the current zome call is this agent or one of the
+/// push notifications providers
+pub fn check_caller_is_provider() -> ExternResult<()> {
+    let caller = call_info()?.provenance;
+    if caller.eq(&agent_info()?.agent_initial_pubkey) {
+        return Ok(());
+    }
+    if !get_push_notifications_providers()?.contains(&caller) {
+        return Err(wasm_error!(WasmErrorInner::Guest(format!(
+            "Only push notifications providers can route notifications: {caller} is not a provider"
+        ))));
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use hdk::prelude::*;
+use push_notifications_service_trait::PUSH_NOTIFICATIONS_SERVICE_HASH;
+use push_notifications_types::{EncryptedNotification, PushNotification, RemotePushNotification};
+
+#[hdk_extern]
+pub fn init(_: ()) -> ExternResult<InitCallbackResult> {
+    let mut fns: BTreeSet<GrantedFunction> = BTreeSet::new();
+    fns.insert((
+        zome_info()?.name,
+        FunctionName::from("receive_remote_push_notification"),
+    ));
+    let functions = GrantedFunctions::Listed(fns);
+    let cap_grant = ZomeCallCapGrant {
+        tag: String::from("receive_remote_push_notification"),
+        access: CapAccess::Unrestricted,
+        functions,
+    };
+    create_cap_grant(cap_grant)?;
+
+    Ok(InitCallbackResult::Pass)
+}
+
+/// Called by the providers to deliver a push notification directly to this agent while they are online,
+/// which is emitted as a signal to their app
+#[hdk_extern]
+pub fn receive_remote_push_notification(notification: RemotePushNotification) -> ExternResult<()> {
+    let caller = call_info()?.provenance;
+    if !get_push_notifications_providers()?.contains(&caller) {
+        return Err(wasm_error!(WasmErrorInner::Guest(format!(
+            "Only push notifications providers can deliver notifications: {caller} is not a provider"
+        ))));
+    }
+    emit_signal(notification)?;
+    Ok(())
+}
+
+/// Decrypts a notification from the inbox of the agent, as returned by `get_notification_history`
+#[hdk_extern]
+pub fn decrypt_notification(
+    encrypted_notification: EncryptedNotification,
+) -> ExternResult<PushNotification> {
+    push_notifications_service_trait::decrypt_notification(&encrypted_notification)
+}
+
+/// Gets all the agents that have announced themselves as providers for the push notifications service
+fn get_push_notifications_providers() -> ExternResult<Vec<AgentPubKey>> {
+    let response = call(
+        CallTargetCell::Local,
+        ZomeName::from("service_providers"),
+        "get_providers_for_service".into(),
+        None,
+        PUSH_NOTIFICATIONS_SERVICE_HASH.to_vec(),
+    )?;
+    let ZomeCallResponse::Ok(result) = response else {
+        return Err(wasm_error!(
+            "Failed to get push notifications providers: {response:?}"
+        ));
+    };
+    let providers: Vec<AgentPubKey> = result.decode().map_err(|err| wasm_error!(err))?;
+    Ok(providers)
+}
@@ -0,0 +1,11 @@
+{ inputs, ... }:
+
+{
+  perSystem = { inputs', system, self', ... }: {
+    packages.push_notifications_receiver =
+      inputs.holochain-utils.outputs.builders.${system}.rustZome {
+        workspacePath = inputs.self.outPath;
+        crateCargoToml = ./Cargo.toml;
+      };
+  };
+}